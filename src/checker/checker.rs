@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use crate::checker::types::Type;
+use crate::interpreter::natives::NATIVES;
+use crate::lexer::token::{LiteralValue, Token, TokenType};
+use crate::parser::expr::Expr;
+use crate::parser::stmt::{Pattern, Stmt};
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning"
+        };
+        write!(f, "[line {}] {}: {}", self.line, severity, self.message)
+    }
+}
+
+pub struct Checker {
+    scopes: Vec<HashMap<String, Type>>,
+    return_types: Vec<Type>,
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        let natives = NATIVES.iter().map(|n| {
+            (n.name.to_string(), Type::Function { params: vec![Type::Any; n.arity], ret: Box::new(Type::Any) })
+        });
+        Checker {
+            scopes: vec![natives.collect()],
+            return_types: Vec::new(),
+            diagnostics: Vec::new()
+        }
+    }
+
+    pub fn check(&mut self, statements: &[Stmt]) {
+        self.hoist(statements);
+        for s in statements {
+            self.check_stmt(s);
+        }
+    }
+
+    pub fn has_errors(&self, strict: bool) -> bool {
+        self.diagnostics.iter().any(|d| strict || d.severity == Severity::Error)
+    }
+
+    fn error(&mut self, line: usize, message: String) {
+        self.report(Diagnostic { line, severity: Severity::Error, message });
+    }
+
+    fn warning(&mut self, line: usize, message: String) {
+        self.report(Diagnostic { line, severity: Severity::Warning, message });
+    }
+
+    // Signatures are looked at both when hoisting and at the declaration
+    // itself, so the same problem can be found more than once.
+    fn report(&mut self, diagnostic: Diagnostic) {
+        let duplicate = self.diagnostics.iter().any(|d| d.line == diagnostic.line && d.message == diagnostic.message);
+        if !duplicate {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn define(&mut self, name: &str, t: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), t);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(t) = scope.get(name) {
+                return Some(t.clone());
+            }
+        }
+        None
+    }
+
+    // Functions, classes and enums may be used before their declaration is
+    // reached (e.g. from the body of an earlier function), so declare them up
+    // front for every block. Type names are declared first so that signatures
+    // can refer to classes and enums declared further down.
+    fn hoist(&mut self, statements: &[Stmt]) {
+        for s in statements {
+            match s {
+                Stmt::Class { name, .. } => self.define(&name.lexeme, Type::Class { name: name.lexeme.to_string(), init: Vec::new() }),
+                Stmt::Enum { name, .. } => {
+                    let t = self.declaration_type(s);
+                    self.define(&name.lexeme, t);
+                },
+                _ => ()
+            }
+        }
+        for s in statements {
+            if let Stmt::Function { name, .. } | Stmt::Class { name, .. } = s {
+                let t = self.declaration_type(s);
+                self.define(&name.lexeme, t);
+            }
+        }
+    }
+
+    fn declaration_type(&mut self, stmt: &Stmt) -> Type {
+        match stmt {
+            Stmt::Function { param_types, return_type, .. } => {
+                let params = param_types.iter().map(|p| self.annotation_type(p)).collect();
+                let ret = Box::new(self.annotation_type(return_type));
+                Type::Function { params, ret }
+            },
+            Stmt::Class { name, methods } => {
+                let mut init = Vec::new();
+                for m in methods {
+                    if let Stmt::Function { name, param_types, .. } = m {
+                        if name.lexeme == "init" {
+                            init = param_types.iter().map(|p| self.annotation_type(p)).collect();
+                        }
+                    }
+                }
+                Type::Class { name: name.lexeme.to_string(), init }
+            },
+            Stmt::Enum { name, variants } => {
+                let variants = variants.iter().map(|(v, fields)| (v.lexeme.to_string(), fields.len())).collect();
+                Type::Enum { name: name.lexeme.to_string(), variants }
+            },
+            _ => Type::Any
+        }
+    }
+
+    fn annotation_type(&mut self, annotation: &Option<Token>) -> Type {
+        let token = match annotation {
+            Some(t) => t,
+            None => return Type::Any
+        };
+        if token.kind == TokenType::NIL {
+            return Type::Nil;
+        }
+        match token.lexeme.as_str() {
+            "any" => Type::Any,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            "function" => Type::Function { params: Vec::new(), ret: Box::new(Type::Any) },
+            name => match self.lookup(name) {
+                Some(Type::Class { name, .. }) => Type::Instance(name),
+                Some(Type::Enum { name, .. }) => Type::Variant(name),
+                _ => {
+                    self.error(token.line, format!("Unknown type '{}'.", name));
+                    Type::Any
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, params: &[Token], param_types: &[Option<Token>], return_type: &Option<Token>, body: &[Stmt], this: Option<Type>) {
+        let ret = self.annotation_type(return_type);
+        let mut scope = HashMap::new();
+        if let Some(this) = this {
+            scope.insert("this".to_string(), this);
+        }
+        for (param, annotation) in params.iter().zip(param_types) {
+            let t = self.annotation_type(annotation);
+            scope.insert(param.lexeme.to_string(), t);
+        }
+        self.scopes.push(scope);
+        self.return_types.push(ret);
+        self.hoist(body);
+        for s in body {
+            self.check_stmt(s);
+        }
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    // A variable declared without an initializer has not been assigned yet,
+    // so only its annotation is known.
+    fn check_declaration(&mut self, name: &Token, type_annotation: &Option<Token>, initializer: Option<&Expr>, infer: bool) {
+        let declared = self.annotation_type(type_annotation);
+        let value = match initializer {
+            Some(initializer) => self.check_expr(initializer),
+            None => {
+                self.define(&name.lexeme, declared);
+                return;
+            }
+        };
+        if !value.is_assignable_to(&declared) {
+            self.error(name.line, format!("Cannot initialize '{}' of type {} with a value of type {}.", name.lexeme, declared, value));
+        }
+        // Unannotated variables are `any` because they may be reassigned to
+        // anything; constants keep the type of their initializer.
+        let t = if type_annotation.is_none() && infer { value } else { declared };
+        self.define(&name.lexeme, t);
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.scopes.push(HashMap::new());
+                self.hoist(statements);
+                for s in statements {
+                    self.check_stmt(s);
+                }
+                self.scopes.pop();
+            },
+            Stmt::Class { name, methods } => {
+                let t = self.declaration_type(stmt);
+                self.define(&name.lexeme, t);
+                for m in methods {
+                    if let Stmt::Function { params, param_types, return_type, body, .. } = m {
+                        self.check_function(params, param_types, return_type, body, Some(Type::Instance(name.lexeme.to_string())));
+                    }
+                }
+            },
+            Stmt::Const { name, type_annotation, initializer } => self.check_declaration(name, type_annotation, Some(initializer), true),
+            Stmt::Enum { name, .. } => {
+                let t = self.declaration_type(stmt);
+                self.define(&name.lexeme, t);
+            },
+            Stmt::Expression { expression } => {
+                self.check_expr(expression);
+            },
+            Stmt::Function { name, params, param_types, return_type, body } => {
+                let t = self.declaration_type(stmt);
+                self.define(&name.lexeme, t);
+                self.check_function(params, param_types, return_type, body, None);
+            },
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.check_expr(condition);
+                self.check_stmt(then_branch);
+                if let Some(b) = else_branch {
+                    self.check_stmt(b);
+                }
+            },
+            Stmt::Match { keyword, subject, arms } => {
+                let subject_type = self.check_expr(subject);
+                for (pattern, body) in arms {
+                    let mut scope = HashMap::new();
+                    if let Pattern::Variant { enum_name, name, bindings } = pattern {
+                        if let Some(enum_name) = enum_name {
+                            match self.lookup(&enum_name.lexeme) {
+                                Some(Type::Enum { name: enum_type, variants }) => {
+                                    if !Type::Variant(enum_type.clone()).is_assignable_to(&subject_type) {
+                                        self.error(keyword.line, format!("Pattern of enum {} can never match a value of type {}.", enum_type, subject_type));
+                                    }
+                                    match variants.get(name.lexeme.as_str()) {
+                                        Some(arity) if *arity != bindings.len() => {
+                                            self.error(name.line, format!("Pattern '{}' expects {} field(s) but variant has {}.", name.lexeme, bindings.len(), arity));
+                                        },
+                                        Some(_) => (),
+                                        None => self.error(name.line, format!("Enum {} has no variant '{}'.", enum_type, name.lexeme))
+                                    }
+                                },
+                                _ => self.error(enum_name.line, format!("'{}' is not an enum.", enum_name.lexeme))
+                            }
+                        }
+                        for b in bindings {
+                            scope.insert(b.lexeme.to_string(), Type::Any);
+                        }
+                    }
+                    self.scopes.push(scope);
+                    self.check_stmt(body);
+                    self.scopes.pop();
+                }
+            },
+            Stmt::Print { expression, .. } => {
+                self.check_expr(expression);
+            },
+            Stmt::Return { keyword, value } => {
+                let t = match value {
+                    Some(v) => self.check_expr(v),
+                    None => Type::Nil
+                };
+                match self.return_types.last().cloned() {
+                    Some(expected) => {
+                        if !t.is_assignable_to(&expected) {
+                            self.error(keyword.line, format!("Cannot return a value of type {} from a function returning {}.", t, expected));
+                        }
+                    },
+                    None => self.warning(keyword.line, "Return outside of a function.".to_string())
+                }
+            },
+            Stmt::While { condition, body, .. } => {
+                self.check_expr(condition);
+                self.check_stmt(body);
+            },
+            Stmt::Var { name, type_annotation, initializer } => self.check_declaration(name, type_annotation, initializer.as_ref(), false)
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                let t = self.check_expr(value);
+                match self.lookup(&name.lexeme) {
+                    Some(declared) => {
+                        if !t.is_assignable_to(&declared) {
+                            self.error(name.line, format!("Cannot assign a value of type {} to '{}' of type {}.", t, name.lexeme, declared));
+                        }
+                    },
+                    None => self.warning(name.line, format!("Undefined variable '{}'.", name.lexeme))
+                }
+                t
+            },
+            Expr::Binary { left, operator, right } => {
+                let l = self.check_expr(left);
+                let r = self.check_expr(right);
+                self.check_binary(operator, l, r)
+            },
+            Expr::Call { callee, paren, arguments } => {
+                let callee_type = self.check_expr(callee);
+                let args: Vec<Type> = arguments.iter().map(|a| self.check_expr(a)).collect();
+                if !callee_type.is_callable() {
+                    self.error(paren.line, format!("Can only call functions and classes, found {}.", callee_type));
+                    return Type::Any;
+                }
+                let (params, ret) = match callee_type {
+                    Type::Function { params, ret } => (params, *ret),
+                    Type::Class { name, init } => (init, Type::Instance(name)),
+                    _ => return Type::Any
+                };
+                if params.len() != args.len() {
+                    self.error(paren.line, format!("Expected {} arguments but got {}.", params.len(), args.len()));
+                }
+                for (i, (arg, param)) in args.iter().zip(params.iter()).enumerate() {
+                    if !arg.is_assignable_to(param) {
+                        self.error(paren.line, format!("Argument {} has type {} but {} was expected.", i + 1, arg, param));
+                    }
+                }
+                ret
+            },
+            Expr::Get { object, name } => {
+                match self.check_expr(object) {
+                    Type::Any | Type::Instance(_) => Type::Any,
+                    Type::Enum { name: enum_name, variants } => {
+                        match variants.get(name.lexeme.as_str()) {
+                            Some(0) => Type::Variant(enum_name),
+                            Some(arity) => Type::Function { params: vec![Type::Any; *arity], ret: Box::new(Type::Variant(enum_name)) },
+                            None => {
+                                self.error(name.line, format!("Enum {} has no variant '{}'.", enum_name, name.lexeme));
+                                Type::Any
+                            }
+                        }
+                    },
+                    t => {
+                        self.error(name.line, format!("Only instances have properties, found {}.", t));
+                        Type::Any
+                    }
+                }
+            },
+            Expr::Set { object, name, value } => {
+                let t = self.check_expr(value);
+                match self.check_expr(object) {
+                    Type::Any | Type::Instance(_) => (),
+                    o => self.error(name.line, format!("Only instances have fields, found {}.", o))
+                }
+                t
+            },
+            Expr::Grouping { expression } => self.check_expr(expression),
+            Expr::Literal { value } => {
+                match value {
+                    LiteralValue::FloatVal(_) | LiteralValue::NumberVal(_) => Type::Number,
+                    LiteralValue::StringVal(_) => Type::String,
+                    LiteralValue::BooleanVal(_) => Type::Bool,
+                    LiteralValue::NullVal => Type::Nil,
+                    _ => Type::Any
+                }
+            },
+            Expr::Logical { left, right, .. } => {
+                let l = self.check_expr(left);
+                let r = self.check_expr(right);
+                if l == r { l } else { Type::Any }
+            },
+            Expr::This { .. } => self.lookup("this").unwrap_or(Type::Any),
+            Expr::Unary { operator, right } => {
+                let t = self.check_expr(right);
+                match operator.kind {
+                    TokenType::MINUS => {
+                        if !t.is_assignable_to(&Type::Number) {
+                            self.error(operator.line, format!("Operand of '-' must be a number, found {}.", t));
+                        }
+                        Type::Number
+                    },
+                    _ => Type::Bool
+                }
+            },
+            Expr::Variable { name, .. } => {
+                match self.lookup(&name.lexeme) {
+                    Some(t) => t,
+                    None => {
+                        self.warning(name.line, format!("Undefined variable '{}'.", name.lexeme));
+                        Type::Any
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_binary(&mut self, operator: &Token, l: Type, r: Type) -> Type {
+        match operator.kind {
+            TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Type::Bool,
+            TokenType::PLUS => {
+                match (&l, &r) {
+                    (Type::Number, Type::Number) => Type::Number,
+                    (Type::String, Type::String) => Type::String,
+                    (Type::Any, Type::Number) | (Type::Number, Type::Any) => Type::Number,
+                    (Type::Any, Type::String) | (Type::String, Type::Any) => Type::String,
+                    (Type::Any, Type::Any) => Type::Any,
+                    _ => {
+                        self.error(operator.line, format!("Operands of '+' must be two numbers or two strings, found {} and {}.", l, r));
+                        Type::Any
+                    }
+                }
+            },
+            _ => {
+                if !l.is_assignable_to(&Type::Number) || !r.is_assignable_to(&Type::Number) {
+                    self.error(operator.line, format!("Operands of '{}' must be numbers, found {} and {}.", operator.lexeme, l, r));
+                }
+                match operator.kind {
+                    TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => Type::Bool,
+                    _ => Type::Number
+                }
+            }
+        }
+    }
+}
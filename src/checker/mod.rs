@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod checker;
+pub mod types;
\ No newline at end of file
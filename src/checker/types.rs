@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Type {
+    Any,
+    Number,
+    String,
+    Bool,
+    Nil,
+    Function {
+        params: Vec<Type>,
+        ret: Box<Type>
+    },
+    Class {
+        name: String,
+        init: Vec<Type>
+    },
+    Instance(String),
+    Enum {
+        name: String,
+        variants: HashMap<String, usize>
+    },
+    Variant(String)
+}
+
+impl Type {
+    // Gradual typing: `any` is compatible with everything in both directions.
+    pub fn is_assignable_to(&self, target: &Type) -> bool {
+        match (self, target) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function { .. }, Type::Function { .. }) => true,
+            _ => self == target
+        }
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(self, Type::Any | Type::Function { .. } | Type::Class { .. })
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Function { .. } => write!(f, "function"),
+            Type::Class { name, .. } => write!(f, "class {}", name),
+            Type::Instance(name) => write!(f, "{}", name),
+            Type::Enum { name, .. } => write!(f, "enum {}", name),
+            Type::Variant(name) => write!(f, "{}", name)
+        }
+    }
+}
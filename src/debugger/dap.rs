@@ -0,0 +1,373 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::rc::Rc;
+use crate::debugger::debugger::{evaluate, scopes, Step, Stepper};
+use crate::interpreter::environment::Environment;
+use crate::interpreter::interpreter::{ExecutionHook, Interpreter};
+use crate::lexer::dialect::Dialect;
+use crate::optimizer::optimizer::OptLevel;
+use crate::parser::frontend::parse_program;
+use crate::parser::stmt::Stmt;
+use crate::util::json::Json;
+use crate::util::rpc::{read_message, write_message};
+
+// There is only ever one thread, so the adapter always reports thread 1.
+const THREAD_ID: usize = 1;
+
+// Frame `f`'s locals and globals are variable references `f * 2 + 1` and
+// `f * 2 + 2`, leaving 0 for values with nothing to expand.
+fn locals_reference(frame: usize) -> usize {
+    frame * 2 + 1
+}
+
+fn globals_reference(frame: usize) -> usize {
+    frame * 2 + 2
+}
+
+// Sends numbered DAP messages on stdout.
+struct Connection {
+    seq: Cell<usize>
+}
+
+impl Connection {
+    fn send(&self, kind: &str, mut fields: Vec<(&str, Json)>) {
+        self.seq.set(self.seq.get() + 1);
+        fields.insert(0, ("seq", self.seq.get().into()));
+        fields.insert(1, ("type", kind.into()));
+        // A client that has gone away also closes stdin, which ends the
+        // session.
+        let _ = write_message(&mut io::stdout().lock(), &Json::object(fields));
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send("event", vec![("event", event.into()), ("body", body)]);
+    }
+
+    fn respond(&self, request: &Json, result: Result<Json, String>) {
+        let command = request.get("command").cloned().unwrap_or(Json::Null);
+        let request_seq = request.get("seq").cloned().unwrap_or(Json::Null);
+        match result {
+            Ok(body) => self.send("response", vec![
+                ("request_seq", request_seq), ("success", true.into()), ("command", command), ("body", body)
+            ]),
+            Err(message) => self.send("response", vec![
+                ("request_seq", request_seq), ("success", false.into()), ("command", command),
+                ("message", message.into())
+            ])
+        }
+    }
+}
+
+// Turns what the program prints into `output` events, one per line.
+struct OutputEvents {
+    connection: Rc<Connection>,
+    pending: String
+}
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.push_str(&String::from_utf8_lossy(buf));
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            self.connection.event("output", Json::object(vec![("category", "stdout".into()), ("output", line.into())]));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let text = std::mem::take(&mut self.pending);
+            self.connection.event("output", Json::object(vec![("category", "stdout".into()), ("output", text.into())]));
+        }
+        Ok(())
+    }
+}
+
+fn next_message() -> Option<Result<Json, String>> {
+    read_message(&mut io::stdin().lock())
+}
+
+fn frame_id(arguments: &Json) -> Option<usize> {
+    arguments.get("frameId").and_then(|f| f.as_f64()).map(|f| f as usize)
+}
+
+enum Control {
+    Wait,
+    Resume(Step),
+    Run
+}
+
+// A Debug Adapter Protocol server over stdin/stdout for one program.
+pub struct DapServer {
+    connection: Rc<Connection>,
+    stepper: Stepper,
+    dialect: Dialect,
+    path: String,
+    program: Vec<Stmt>,
+    stop_on_entry: bool,
+    started: bool,
+    // The scope each caller was in when it made the call on top of it, by
+    // frame id. The innermost frame is in the interpreter's current scope.
+    callers: Vec<Rc<RefCell<Environment>>>
+}
+
+impl DapServer {
+    pub fn new(dialect: Dialect) -> DapServer {
+        DapServer {
+            connection: Rc::new(Connection { seq: Cell::new(0) }),
+            stepper: Stepper::new(),
+            dialect,
+            path: String::new(),
+            program: Vec::new(),
+            stop_on_entry: false,
+            started: false,
+            callers: Vec::new()
+        }
+    }
+
+    // Serves until the client disconnects or closes stdin.
+    pub fn run(self) -> i32 {
+        let connection = self.connection.clone();
+        let server = Rc::new(RefCell::new(self));
+        // The stdin lock must not be held while the program runs, as the hook
+        // reads from stdin whenever it pauses.
+        while let Some(message) = next_message() {
+            let message = match message {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Ignoring malformed message: {}", e);
+                    continue;
+                }
+            };
+            let control = server.borrow_mut().handle(&message, None);
+            if let Control::Run = control {
+                let program = std::mem::take(&mut server.borrow_mut().program);
+                let mut interpreter = Interpreter::new();
+                interpreter.allow_host_access();
+                interpreter.hook = Some(server.clone());
+                interpreter.output = Rc::new(RefCell::new(OutputEvents { connection: connection.clone(), pending: String::new() }));
+                let result = interpreter.interpret_stmt(&program);
+                interpreter.output.borrow_mut().flush().unwrap();
+                let exit_code: usize = match result {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        connection.event("output", Json::object(vec![
+                            ("category", "stderr".into()), ("output", format!("{}\n", e).into())
+                        ]));
+                        70
+                    }
+                };
+                connection.event("exited", Json::object(vec![("exitCode", exit_code.into())]));
+                connection.event("terminated", Json::object(vec![]));
+            }
+        }
+        0
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments.get("program").and_then(|p| p.as_str())
+            .ok_or("Missing 'program' in launch arguments.")?;
+        let source = fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path, e))?;
+        let statements = parse_program(source, &self.dialect, OptLevel::None)?;
+        self.path = path.to_string();
+        self.program = statements;
+        self.stop_on_entry = arguments.get("stopOnEntry") == Some(&Json::Bool(true));
+        Ok(Json::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let lines: Vec<usize> = arguments.get("breakpoints").and_then(|b| b.as_array())
+            .map(|b| b.iter().filter_map(|b| b.get("line").and_then(|l| l.as_f64())).map(|l| l as usize).collect())
+            .unwrap_or_default();
+        self.stepper.breakpoints = lines.iter().copied().collect();
+        let breakpoints: Vec<Json> = lines.into_iter()
+            .map(|l| Json::object(vec![("verified", true.into()), ("line", l.into())]))
+            .collect();
+        Json::object(vec![("breakpoints", breakpoints.into())])
+    }
+
+    fn source(&self) -> Json {
+        let name = self.path.rsplit(['/', '\\']).next().unwrap_or("");
+        Json::object(vec![("name", name.into()), ("path", self.path.as_str().into())])
+    }
+
+    fn stack_trace(&self, interpreter: &Interpreter) -> Json {
+        let frames: Vec<Json> = interpreter.call_stack.iter().enumerate().rev().map(|(id, frame)| Json::object(vec![
+            ("id", id.into()), ("name", frame.function.as_str().into()), ("source", self.source()),
+            ("line", frame.line.into()), ("column", 1usize.into())
+        ])).collect();
+        let total = frames.len();
+        Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())])
+    }
+
+    // The innermost scope of frame `id`, where 0 is the script.
+    fn frame_environment(&self, interpreter: &Interpreter, id: usize) -> Result<Rc<RefCell<Environment>>, String> {
+        let top = interpreter.call_stack.len() - 1;
+        if id == top {
+            Ok(interpreter.environment.clone())
+        } else {
+            self.callers.get(id).filter(|_| id < top).cloned().ok_or(format!("There is no frame {}.", id))
+        }
+    }
+
+    fn scopes(&self, interpreter: &Interpreter, frame: usize) -> Result<Json, String> {
+        self.frame_environment(interpreter, frame)?;
+        Ok(Json::object(vec![("scopes", vec![
+            Json::object(vec![("name", "Locals".into()), ("variablesReference", locals_reference(frame).into()), ("expensive", false.into())]),
+            Json::object(vec![("name", "Globals".into()), ("variablesReference", globals_reference(frame).into()), ("expensive", false.into())])
+        ].into())]))
+    }
+
+    fn variables(&self, interpreter: &Interpreter, reference: usize) -> Result<Json, String> {
+        if reference == 0 {
+            return Err("Variable reference 0 has no variables.".to_string());
+        }
+        let frame = (reference - 1) / 2;
+        let mut scopes = scopes(&self.frame_environment(interpreter, frame)?);
+        let globals = scopes.pop().unwrap_or_default();
+        let names: Vec<(String, String)> = if reference == globals_reference(frame) {
+            globals
+        } else {
+            // Inner scopes shadow outer ones.
+            let mut locals: Vec<(String, String)> = Vec::new();
+            for (name, value) in scopes.into_iter().flatten() {
+                if !locals.iter().any(|(n, _)| *n == name) {
+                    locals.push((name, value));
+                }
+            }
+            locals.sort();
+            locals
+        };
+        let variables: Vec<Json> = names.into_iter().map(|(name, value)| Json::object(vec![
+            ("name", name.into()), ("value", value.into()), ("variablesReference", 0usize.into())
+        ])).collect();
+        Ok(Json::object(vec![("variables", variables.into())]))
+    }
+
+    // Evaluates in the scope of frame `frame`, or the innermost one if the
+    // client did not pick a frame.
+    fn evaluate(&self, interpreter: &mut Interpreter, expression: &str, frame: Option<usize>) -> Result<String, String> {
+        let environment = match frame {
+            Some(frame) => self.frame_environment(interpreter, frame)?,
+            None => interpreter.environment.clone()
+        };
+        let current = std::mem::replace(&mut interpreter.environment, environment);
+        let result = evaluate(interpreter, expression, &self.dialect);
+        interpreter.environment = current;
+        result
+    }
+
+    // Answers one request. `interpreter` is set while the program is paused.
+    fn handle(&mut self, request: &Json, interpreter: Option<&mut Interpreter>) -> Control {
+        let command = request.get("command").and_then(|c| c.as_str()).unwrap_or("");
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        let mut control = Control::Wait;
+        let result = match (command, interpreter) {
+            ("initialize", _) => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsEvaluateForHovers", true.into())
+            ])),
+            ("launch", _) => self.launch(&arguments),
+            ("setBreakpoints", _) => Ok(self.set_breakpoints(&arguments)),
+            ("configurationDone", _) => {
+                // Nothing runs until a launch has succeeded.
+                if !self.started && !self.path.is_empty() {
+                    self.started = true;
+                    let step = if self.stop_on_entry { Step::Into } else { Step::Continue };
+                    self.stepper.resume(step, 0);
+                    control = Control::Run;
+                }
+                Ok(Json::Null)
+            },
+            ("threads", _) => Ok(Json::object(vec![("threads", vec![
+                Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())])
+            ].into())])),
+            ("disconnect", _) => {
+                self.connection.respond(request, Ok(Json::Null));
+                process::exit(0);
+            },
+            ("stackTrace", Some(interpreter)) => Ok(self.stack_trace(interpreter)),
+            ("scopes", Some(interpreter)) => {
+                let frame = frame_id(&arguments).unwrap_or(interpreter.call_stack.len() - 1);
+                self.scopes(interpreter, frame)
+            },
+            ("variables", Some(interpreter)) => {
+                let reference = arguments.get("variablesReference").and_then(|r| r.as_f64()).unwrap_or(0.0) as usize;
+                self.variables(interpreter, reference)
+            },
+            ("evaluate", Some(interpreter)) => {
+                let expression = arguments.get("expression").and_then(|e| e.as_str()).unwrap_or("");
+                self.evaluate(interpreter, expression, frame_id(&arguments))
+                    .map(|v| Json::object(vec![("result", v.into()), ("variablesReference", 0usize.into())]))
+            },
+            ("continue", Some(_)) => {
+                control = Control::Resume(Step::Continue);
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            },
+            ("next", Some(_)) => {
+                control = Control::Resume(Step::Over);
+                Ok(Json::Null)
+            },
+            ("stepIn", Some(_)) => {
+                control = Control::Resume(Step::Into);
+                Ok(Json::Null)
+            },
+            ("stepOut", Some(_)) => {
+                control = Control::Resume(Step::Out);
+                Ok(Json::Null)
+            },
+            ("stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn" | "stepOut", None) => {
+                Err("The program is not paused.".to_string())
+            },
+            _ => Err(format!("Request '{}' is not supported.", command))
+        };
+        self.connection.respond(request, result);
+        if command == "initialize" {
+            self.connection.event("initialized", Json::Null);
+        }
+        control
+    }
+}
+
+impl ExecutionHook for DapServer {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, line: usize) -> Result<(), String> {
+        let depth = interpreter.call_stack.len();
+        if !self.stepper.should_pause(stmt, line, depth) {
+            return Ok(());
+        }
+        interpreter.output.borrow_mut().flush().map_err(|e| e.to_string())?;
+        let reason = if self.stepper.breakpoints.contains(&line) {
+            "breakpoint"
+        } else if self.stop_on_entry {
+            "entry"
+        } else {
+            "step"
+        };
+        self.stop_on_entry = false;
+        self.connection.event("stopped", Json::object(vec![
+            ("reason", reason.into()), ("threadId", THREAD_ID.into()), ("allThreadsStopped", true.into())
+        ]));
+        while let Some(message) = next_message() {
+            let message = match message {
+                Ok(m) => m,
+                Err(_) => continue
+            };
+            if let Control::Resume(step) = self.handle(&message, Some(interpreter)) {
+                self.stepper.resume(step, depth);
+                return Ok(());
+            }
+        }
+        process::exit(0);
+    }
+
+    fn function_entered(&mut self, interpreter: &Interpreter) {
+        // The callee has not entered its own scope yet, so this is still the
+        // caller's. Anything above the caller has returned.
+        let caller = interpreter.call_stack.len() - 2;
+        self.callers.truncate(caller);
+        self.callers.push(interpreter.environment.clone());
+    }
+}
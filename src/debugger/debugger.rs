@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::io::Write;
+use std::process;
+use std::rc::Rc;
+use crate::interpreter::environment::Environment;
+use crate::interpreter::interpreter::{ExecutionHook, Interpreter, LineSteps};
+use crate::lexer::dialect::Dialect;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::token::LiteralValue;
+use crate::parser::parser::Parser;
+use crate::parser::stmt::Stmt;
+
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Step {
+    Continue,
+    Into,
+    Over,
+    Out
+}
+
+// Decides where a running program pauses. Depth is the length of the
+// interpreter's call stack.
+pub struct Stepper {
+    pub breakpoints: HashSet<usize>,
+    step: Step,
+    depth: usize,
+    steps: LineSteps
+}
+
+impl Stepper {
+    // Starts out stepping, so the first statement pauses.
+    pub fn new() -> Stepper {
+        Stepper {
+            breakpoints: HashSet::new(),
+            step: Step::Into,
+            depth: 0,
+            steps: LineSteps::default()
+        }
+    }
+
+    pub fn resume(&mut self, step: Step, depth: usize) {
+        self.step = step;
+        self.depth = depth;
+    }
+
+    pub fn should_pause(&mut self, stmt: &Stmt, line: usize, depth: usize) -> bool {
+        if !self.steps.starts_step(stmt, line, depth) {
+            return false;
+        }
+        if self.breakpoints.contains(&line) {
+            return true;
+        }
+        match self.step {
+            Step::Continue => false,
+            Step::Into => true,
+            Step::Over => depth <= self.depth,
+            Step::Out => depth < self.depth
+        }
+    }
+}
+
+// Runs `source` (statements, or a single expression whose value is returned)
+// in the paused interpreter's current environment. Unless paused at the top
+// level, declarations go into a scope of their own that is dropped afterwards,
+// so they cannot shift the slots of the paused scope's variables.
+pub fn evaluate(interpreter: &mut Interpreter, source: &str, dialect: &Dialect) -> Result<String, String> {
+    let mut source = source.trim().to_string();
+    if !source.ends_with(';') && !source.ends_with('}') {
+        source.push(';');
+    }
+    let mut lexer = Lexer::new_with_dialect(source, dialect);
+    lexer.collect_errors();
+    lexer.scan_tokens();
+    if let Some(e) = lexer.errors.first() {
+        return Err(e.message.clone());
+    }
+    let mut parser = Parser::new(lexer.tokens);
+    parser.collect_errors();
+    let statements = parser.parse();
+    if let Some(e) = parser.parse_errors.first() {
+        return Err(e.describe());
+    }
+    let depth = interpreter.call_stack.len();
+    let line = interpreter.line();
+    let environment = interpreter.environment.clone();
+    if environment.borrow().enclosing.is_some() {
+        interpreter.environment = Rc::new(RefCell::new(Environment::new_with_enclosing(environment.clone())));
+    }
+    let mut value = LiteralValue::NullVal;
+    let mut result = Ok(());
+    for s in &statements {
+        let outcome = match s {
+            Stmt::Expression { expression } => interpreter.evaluate_expr(expression),
+            s => interpreter.execute(s).map(|_| LiteralValue::NullVal)
+        };
+        match outcome {
+            Ok(v) => value = v,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    interpreter.environment = environment;
+    interpreter.call_stack.truncate(depth);
+    if let Some(frame) = interpreter.call_stack.last_mut() {
+        frame.line = line;
+    }
+    result.map(|_| value.to_string())
+}
+
+// Every scope from `environment` outwards, as (name, value) pairs.
+pub fn scopes(environment: &Rc<RefCell<Environment>>) -> Vec<Vec<(String, String)>> {
+    let mut scopes = Vec::new();
+    let mut environment = Some(environment.clone());
+    while let Some(e) = environment {
+        let e = e.borrow();
+        let mut names: BTreeMap<String, String> = e.values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        // A redeclared name shows its latest value.
+        for (name, value) in e.names.iter().zip(&e.slots) {
+            names.insert(name.to_string(), value.to_string());
+        }
+        scopes.push(names.into_iter().collect());
+        environment = e.enclosing.clone();
+    }
+    scopes
+}
+
+const HELP: &str = "\
+Commands:
+  break N, b N     pause whenever line N is reached (no N lists breakpoints)
+  delete N, d N    remove the breakpoint on line N
+  continue, c      run to the next breakpoint
+  step, s          run to the next line, entering calls
+  next, n          run to the next line in this function
+  out, o           run until this function returns
+  print EXPR, p    evaluate an expression in the current scope
+  env, e           show every scope from the innermost outwards
+  backtrace, bt    show the call stack
+  list, l          show the source around the current line
+  quit, q          stop the program";
+
+// The command-line front end used by `pepega debug`.
+pub struct Debugger {
+    pub stepper: Stepper,
+    source: Vec<String>,
+    dialect: Dialect
+}
+
+impl Debugger {
+    pub fn new(source: &str, dialect: Dialect) -> Debugger {
+        Debugger {
+            stepper: Stepper::new(),
+            source: source.lines().map(|l| l.to_string()).collect(),
+            dialect
+        }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source.get(line.wrapping_sub(1)).map(|l| l.as_str()).unwrap_or("")
+    }
+
+    fn list(&self, line: usize) {
+        let first = line.saturating_sub(3).max(1);
+        for l in first..=(line + 3).min(self.source.len()) {
+            let marker = if l == line { "->" } else if self.stepper.breakpoints.contains(&l) { " *" } else { "  " };
+            println!("{} {:>4} {}", marker, l, self.source_line(l));
+        }
+    }
+
+    fn backtrace(&self, interpreter: &Interpreter) {
+        for (i, frame) in interpreter.call_stack.iter().rev().enumerate() {
+            println!("#{} {} at line {}", i, frame.function, frame.line);
+        }
+    }
+
+    fn env(&self, interpreter: &Interpreter) {
+        let scopes = scopes(&interpreter.environment);
+        let count = scopes.len();
+        for (i, names) in scopes.into_iter().enumerate() {
+            let label = if i + 1 == count { "global".to_string() } else { format!("scope {}", i) };
+            let names: Vec<String> = names.into_iter().map(|(k, v)| format!("{} = {}", k, v)).collect();
+            println!("{}: {}", label, names.join(", "));
+        }
+    }
+
+    fn breakpoint_line(argument: &str) -> Option<usize> {
+        match argument.parse::<usize>() {
+            Ok(line) if line > 0 => Some(line),
+            _ => {
+                println!("Expected a line number, got '{}'.", argument);
+                None
+            }
+        }
+    }
+}
+
+impl ExecutionHook for Debugger {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, line: usize) -> Result<(), String> {
+        let depth = interpreter.call_stack.len();
+        if !self.stepper.should_pause(stmt, line, depth) {
+            return Ok(());
+        }
+        println!("[line {}] {}", line, self.source_line(line).trim());
+        loop {
+            print!("(pepega) ");
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                process::exit(0);
+            }
+            let input = input.trim();
+            let (command, argument) = match input.split_once(' ') {
+                Some((c, a)) => (c, a.trim()),
+                None => (input, "")
+            };
+            match command {
+                "break" | "b" if argument.is_empty() => {
+                    let mut lines: Vec<&usize> = self.stepper.breakpoints.iter().collect();
+                    lines.sort();
+                    for l in lines {
+                        println!("line {}: {}", l, self.source_line(*l).trim());
+                    }
+                },
+                "break" | "b" => {
+                    if let Some(l) = Debugger::breakpoint_line(argument) {
+                        self.stepper.breakpoints.insert(l);
+                    }
+                },
+                "delete" | "d" => {
+                    if let Some(l) = Debugger::breakpoint_line(argument) {
+                        self.stepper.breakpoints.remove(&l);
+                    }
+                },
+                "continue" | "c" => {
+                    self.stepper.resume(Step::Continue, depth);
+                    return Ok(());
+                },
+                "step" | "s" => {
+                    self.stepper.resume(Step::Into, depth);
+                    return Ok(());
+                },
+                "next" | "n" => {
+                    self.stepper.resume(Step::Over, depth);
+                    return Ok(());
+                },
+                "out" | "o" | "finish" => {
+                    self.stepper.resume(Step::Out, depth);
+                    return Ok(());
+                },
+                "print" | "p" => {
+                    match evaluate(interpreter, argument, &self.dialect) {
+                        Ok(v) => println!("{}", v),
+                        Err(e) => println!("{}", e)
+                    }
+                },
+                "env" | "e" => self.env(interpreter),
+                "backtrace" | "bt" => self.backtrace(interpreter),
+                "list" | "l" => self.list(line),
+                "quit" | "q" => process::exit(0),
+                "" => (),
+                "help" | "h" => println!("{}", HELP),
+                _ => println!("Unknown command '{}'. Type 'help' for a list.", command)
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+// Every budget error starts with this.
+pub const BUDGET_EXCEEDED: &str = "Budget exceeded";
+
+// How often the clock is read, in steps.
+const CLOCK_EVERY: u64 = 1024;
+
+// Limits for running scripts nobody has vetted, like ones sent in chat. A
+// limit left at `None` is not enforced. The counters say how much has been
+// used so far.
+//
+// Going over a limit fails with an error starting with `BUDGET_EXCEEDED`.
+// Every check after that fails the same way, so a script cannot catch the
+// error and carry on.
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Budget {
+    // Statements executed plus expressions evaluated.
+    pub max_steps: Option<u64>,
+    // Wall-clock time from the first step.
+    pub max_duration: Option<Duration>,
+    // Functions, classes, instances, enums and variants created, plus strings
+    // built while running.
+    pub max_values: Option<u64>,
+    // Bytes in those strings.
+    pub max_string_bytes: Option<u64>,
+    // Bytes written by `chatting`, newlines included.
+    pub max_output_bytes: Option<u64>,
+    pub steps: u64,
+    pub started: Option<Instant>,
+    pub values: u64,
+    pub string_bytes: u64,
+    pub output_bytes: u64,
+    // The error the first limit gone over raised.
+    pub exceeded: Option<String>
+}
+
+impl Budget {
+    pub fn step(&mut self) -> Result<(), String> {
+        self.check()?;
+        self.steps += 1;
+        if self.max_steps.is_some_and(|max| self.steps > max) {
+            return self.exceed(format!("more than {} steps", self.steps - 1));
+        }
+        if let Some(max) = self.max_duration {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if self.steps.is_multiple_of(CLOCK_EVERY) && started.elapsed() > max {
+                return self.exceed(format!("ran longer than {} ms", max.as_millis()));
+            }
+        }
+        Ok(())
+    }
+
+    // Counts a new value, and `string_bytes` more bytes of string if it is one.
+    pub fn allocate(&mut self, string_bytes: usize) -> Result<(), String> {
+        self.check()?;
+        self.values += 1;
+        self.string_bytes += string_bytes as u64;
+        if self.max_values.is_some_and(|max| self.values > max) {
+            return self.exceed(format!("more than {} values created", self.values - 1));
+        }
+        if let Some(max) = self.max_string_bytes.filter(|max| self.string_bytes > *max) {
+            return self.exceed(format!("more than {} bytes of strings created", max));
+        }
+        Ok(())
+    }
+
+    pub fn output(&mut self, bytes: usize) -> Result<(), String> {
+        self.check()?;
+        self.output_bytes += bytes as u64;
+        if let Some(max) = self.max_output_bytes.filter(|max| self.output_bytes > *max) {
+            return self.exceed(format!("more than {} bytes of output", max));
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), String> {
+        match &self.exceeded {
+            Some(e) => Err(e.clone()),
+            None => Ok(())
+        }
+    }
+
+    fn exceed(&mut self, what: String) -> Result<(), String> {
+        let error = format!("{}: {}.", BUDGET_EXCEEDED, what);
+        self.exceeded = Some(error.clone());
+        Err(error)
+    }
+}
@@ -1,34 +1,95 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use crate::lexer::symbol::Symbol;
 use crate::lexer::token::{LiteralValue, Token};
 
-#[derive(Clone)]
+// One scope's variables. Frames are shared: a block, a call and every
+// function declared in the scope hold the same `Rc`, so entering a scope is
+// O(1) and assignments are seen by everyone who can reach the variable.
+//
+// The global scope keeps its variables by name. Every other scope keeps them
+// in declaration order, so resolved code reads them by slot.
 pub struct Environment {
-    pub enclosing: Option<Box<Environment>>,
-    pub values: HashMap<String, LiteralValue>
+    pub enclosing: Option<Rc<RefCell<Environment>>>,
+    pub values: HashMap<Symbol, LiteralValue>,
+    pub slots: Vec<LiteralValue>,
+    // The name declared in each slot, for lookups by name and the debugger.
+    pub names: Vec<Symbol>,
+    pub constants: HashSet<Symbol>,
+    // Whether the heap watches this scope for cycles.
+    pub tracked: bool
+}
+
+// Functions hold the frame they were declared in, which usually holds them
+// back, so the chain is not printed.
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Environment")
+    }
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
             enclosing: None,
-            values: HashMap::new()
+            values: HashMap::new(),
+            slots: Vec::new(),
+            names: Vec::new(),
+            constants: HashSet::new(),
+            tracked: false
+        }
+    }
+
+    fn insert(&mut self, name: Symbol, value: LiteralValue) {
+        if self.enclosing.is_none() {
+            self.values.insert(name, value);
+        } else {
+            self.slots.push(value);
+            self.names.push(name);
         }
     }
 
-    pub fn define(&mut self, name: String, value: LiteralValue) -> Result<LiteralValue, String> {
-        self.values.insert(name, value);
+    // A constant stays constant: redeclaring its name in the same scope, as a
+    // variable or otherwise, is an error.
+    pub fn define(&mut self, name: Symbol, value: LiteralValue) -> Result<LiteralValue, String> {
+        if self.constants.contains(&name) {
+            return Err(format!("Cannot redeclare constant '{}'.", name));
+        }
+        self.insert(name, value);
+        Ok(LiteralValue::NullVal)
+    }
+
+    pub fn define_constant(&mut self, name: Symbol, value: LiteralValue) -> Result<LiteralValue, String> {
+        if !self.constants.insert(name.clone()) {
+            return Err(format!("Cannot redeclare constant '{}'.", name));
+        }
+        self.insert(name, value);
         Ok(LiteralValue::NullVal)
     }
 
+    // The latest declaration of `name` in this scope alone.
+    fn lookup(&mut self, name: &Symbol) -> Option<&mut LiteralValue> {
+        match self.names.iter().rposition(|n| n == name) {
+            Some(i) => Some(&mut self.slots[i]),
+            None => self.values.get_mut(name)
+        }
+    }
+
     pub fn assign(&mut self, name: &Token, value: LiteralValue) -> Result<(), String> {
-        match self.values.get_mut(&name.lexeme) {
+        let symbol = name.name();
+        if self.constants.contains(&symbol) {
+            return Err(format!("Cannot assign to constant '{}'.", name.lexeme));
+        }
+        match self.lookup(&symbol) {
             Some(v) => {
                 *v = value;
                 Ok(())
             },
             None => {
                 match self.enclosing {
-                    Some(ref mut e) => e.assign(name, value),
+                    Some(ref e) => e.borrow_mut().assign(name, value),
                     None => Err(format!("Undefined variable '{}'.", name.lexeme))
                 }
             }
@@ -36,21 +97,47 @@ impl Environment {
     }
 
     pub fn get(&self, name: &Token) -> Result<LiteralValue, String> {
-        match self.values.get(&name.lexeme) {
+        let symbol = name.name();
+        let found = match self.names.iter().rposition(|n| *n == symbol) {
+            Some(i) => Some(&self.slots[i]),
+            None => self.values.get(&symbol)
+        };
+        match found {
             Some(v) => Ok(v.clone()),
             None => {
                 match self.enclosing {
-                    Some(ref e) => e.get(name),
+                    Some(ref e) => e.borrow().get(name),
                     None => Err(format!("Undefined variable '{}'.", name.lexeme))
                 }
             }
         }
     }
 
-    pub fn new_with_enclosing(enclosing: Environment) -> Environment {
+    // Reads a resolved local `depth` scopes out from this one.
+    pub fn get_at(&self, depth: usize, slot: usize) -> LiteralValue {
+        if depth == 0 {
+            return self.slots[slot].clone();
+        }
+        self.enclosing.as_ref().expect("Resolved scope is missing.").borrow().get_at(depth - 1, slot)
+    }
+
+    // The resolver has already rejected assignments to constants.
+    pub fn assign_at(&mut self, depth: usize, slot: usize, value: LiteralValue) {
+        if depth == 0 {
+            self.slots[slot] = value;
+            return;
+        }
+        self.enclosing.as_ref().expect("Resolved scope is missing.").borrow_mut().assign_at(depth - 1, slot, value);
+    }
+
+    pub fn new_with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
         Environment {
-            enclosing: Some(Box::new(enclosing)),
-            values: HashMap::new()
+            enclosing: Some(enclosing),
+            values: HashMap::new(),
+            slots: Vec::new(),
+            names: Vec::new(),
+            constants: HashSet::new(),
+            tracked: false
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use crate::interpreter::environment::Environment;
+use crate::lexer::token::LiteralValue;
+
+// Scopes tracked before the first collection, and at least this many between
+// collections after that.
+const FIRST_COLLECTION: usize = 10_000;
+
+type Scope = Rc<RefCell<Environment>>;
+
+#[derive(Clone)]
+#[derive(Default)]
+pub struct GcStats {
+    pub collections: usize,
+    // Scopes functions have captured since the program started.
+    pub scopes_tracked: usize,
+    // Scopes that were only kept alive by a cycle.
+    pub scopes_freed: usize
+}
+
+// Frees scopes that only cycles keep alive. A function declared in a scope
+// holds that scope, which holds the function back, so neither is ever dropped
+// once the scope is left.
+//
+// Only a function can make a scope part of a cycle, so the heap keeps a weak
+// reference to every scope a function has been declared in, and the scopes
+// around those. Collecting counts the references each scope gets from other tracked scopes
+// and the values in them. A scope with more references than that is held from
+// outside: by the interpreter's current scope, by a call in progress, or by a
+// value the host is holding. Those scopes and everything they reach are live.
+// The rest are emptied, which breaks their cycles and frees them.
+//
+// References that cannot be seen, like those inside fields shared by several
+// variants, only make scopes look live, never dead.
+//
+// Only the tree-walker has a heap. The VM relies on reference counting alone,
+// and its `gc()` is an error.
+#[derive(Clone)]
+pub struct Heap {
+    scopes: Vec<Weak<RefCell<Environment>>>,
+    next_collection: usize,
+    pub stats: GcStats
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            scopes: Vec::new(),
+            next_collection: FIRST_COLLECTION,
+            stats: GcStats::default()
+        }
+    }
+
+    // Tracks `scope` and the scopes around it that are not tracked yet.
+    pub fn track(&mut self, scope: &Scope) {
+        let mut next = Some(scope.clone());
+        while let Some(scope) = next {
+            let mut environment = scope.borrow_mut();
+            if environment.tracked {
+                break;
+            }
+            environment.tracked = true;
+            next = environment.enclosing.clone();
+            drop(environment);
+            self.scopes.push(Rc::downgrade(&scope));
+            self.stats.scopes_tracked += 1;
+        }
+    }
+
+    // Whether enough scopes have been tracked since the last collection.
+    pub fn should_collect(&self) -> bool {
+        self.scopes.len() >= self.next_collection
+    }
+
+    // Frees scopes kept alive only by cycles and returns how many there were.
+    pub fn collect(&mut self) -> usize {
+        let scopes: Vec<Scope> = self.scopes.iter().filter_map(|s| s.upgrade()).collect();
+        // A scope being changed right now cannot be looked into, so its
+        // references are unknown.
+        if scopes.iter().any(|s| s.try_borrow().is_err()) {
+            return 0;
+        }
+        let index: HashMap<*const RefCell<Environment>, usize> = scopes.iter().enumerate().map(|(i, s)| (Rc::as_ptr(s), i)).collect();
+
+        let mut internal = vec![0; scopes.len()];
+        for scope in &scopes {
+            for_each_reference(scope, |s| {
+                if let Some(&i) = index.get(&Rc::as_ptr(s)) {
+                    internal[i] += 1;
+                }
+            });
+        }
+
+        // `scopes` itself holds one reference to each.
+        let mut live: Vec<bool> = scopes.iter().zip(&internal).map(|(s, n)| Rc::strong_count(s) - 1 > *n).collect();
+        let mut pending: Vec<usize> = (0..scopes.len()).filter(|i| live[*i]).collect();
+        while let Some(i) = pending.pop() {
+            for_each_reference(&scopes[i], |s| {
+                if let Some(&j) = index.get(&Rc::as_ptr(s)) {
+                    if !live[j] {
+                        live[j] = true;
+                        pending.push(j);
+                    }
+                }
+            });
+        }
+
+        // Every garbage scope is emptied before anything in them is dropped,
+        // since dropping a value can drop what another scope still refers to.
+        let mut contents = Vec::new();
+        for (scope, _) in scopes.iter().zip(&live).filter(|(_, live)| !**live) {
+            let mut environment = scope.borrow_mut();
+            let enclosing = environment.enclosing.take();
+            environment.names.clear();
+            contents.push((enclosing, std::mem::take(&mut environment.slots), std::mem::take(&mut environment.values)));
+        }
+        let freed = contents.len();
+        drop(contents);
+        drop(scopes);
+
+        self.scopes.retain(|s| s.strong_count() > 0);
+        self.next_collection = (self.scopes.len() * 2).max(FIRST_COLLECTION);
+        self.stats.collections += 1;
+        self.stats.scopes_freed += freed;
+        freed
+    }
+}
+
+// Calls `found` with every scope `scope` references directly: the scope it is
+// nested in and the closures of functions stored in it.
+fn for_each_reference(scope: &Scope, mut found: impl FnMut(&Scope)) {
+    let environment = scope.borrow();
+    if let Some(enclosing) = &environment.enclosing {
+        found(enclosing);
+    }
+    let mut pending: Vec<&LiteralValue> = environment.slots.iter().chain(environment.values.values()).collect();
+    while let Some(value) = pending.pop() {
+        match value {
+            LiteralValue::FunctionVal(_, closure) => found(closure),
+            LiteralValue::ClassVal(_, values) | LiteralValue::InstanceVal(_, values) => pending.extend(values.values()),
+            LiteralValue::VariantVal(_, _, fields) if !fields.is_shared() => pending.extend(fields.iter()),
+            _ => ()
+        }
+    }
+}
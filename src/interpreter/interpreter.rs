@@ -1,125 +1,399 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+use crate::interpreter::budget::Budget;
 use crate::interpreter::callable::{Callable};
 use crate::interpreter::environment::Environment;
-use crate::lexer::token::{call_function_val, LiteralValue};
-use crate::parser::expr::Expr;
-use crate::parser::stmt::Stmt;
+use crate::interpreter::gc::Heap;
+use crate::interpreter::natives::NATIVES;
+use crate::lexer::symbol::Symbol;
+use crate::lexer::token::{call_function_val, LiteralValue, NativeFn, Token, TokenType};
+use crate::parser::expr::{Expr, Resolution};
+use crate::parser::stmt::{Pattern, Stmt};
+
+// Lets tools such as the debugger watch a running program. Called before
+// every statement that has a line; an error stops the program.
+pub trait ExecutionHook {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, line: usize) -> Result<(), String>;
+
+    // Called once the callee's frame is on the call stack.
+    fn function_entered(&mut self, _interpreter: &Interpreter) {}
+
+    // Called while the callee's frame is still on the call stack, whether or
+    // not the call failed.
+    fn function_exited(&mut self, _interpreter: &Interpreter) {}
+
+    // Called when a `clueless` or an `and`/`or` picks a side. `token` is the
+    // keyword or operator. `branch` is 0 for the then-branch or the right
+    // operand being evaluated, 1 for the else side or a short circuit.
+    fn branch_taken(&mut self, _token: &Token, _branch: usize) {}
+}
+
+// Tells hooks which statements start a new step on a line. A body on the same
+// line as its `clueless`/`residentsleeper`/`modcheck` is part of that step, so
+// tools pause on or count the line once rather than once per statement.
+#[derive(Default)]
+pub struct LineSteps {
+    // Line and call depth of the last such header.
+    header: Option<(usize, usize)>
+}
+
+impl LineSteps {
+    pub fn starts_step(&mut self, stmt: &Stmt, line: usize, depth: usize) -> bool {
+        let nested = self.header == Some((line, depth));
+        self.header = match stmt {
+            Stmt::If { .. } | Stmt::While { .. } | Stmt::Match { .. } => Some((line, depth)),
+            _ => None
+        };
+        !nested
+    }
+}
+
+#[derive(Clone)]
+pub struct Frame {
+    pub function: String,
+    // The line currently executing in this frame.
+    pub line: usize
+}
+
+// How many calls may nest, unless `max_call_depth` says otherwise. Calls in
+// tail position replace their caller and do not count.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+// The error for a call nested deeper than `max_depth`, followed by the calls
+// that led to it, innermost first. Runs of frames at the same place, as in
+// plain recursion, are shown once. Shared with the VM.
+pub fn stack_overflow(max_depth: usize, call_stack: &[Frame]) -> String {
+    let mut message = format!("Stack overflow: more than {} nested calls.", max_depth);
+    let mut frames = call_stack.iter().rev().peekable();
+    while let Some(frame) = frames.next() {
+        let mut count = 1;
+        while frames.next_if(|f| f.function == frame.function && f.line == frame.line).is_some() {
+            count += 1;
+        }
+        message.push_str(&format!("\n  in {} at line {}", frame.function, frame.line));
+        if count > 1 {
+            message.push_str(&format!(" ({} times)", count));
+        }
+    }
+    message
+}
+
+// How an `xdd` left its function, waiting on the interpreter until the call
+// it returns from picks it up.
+#[derive(Clone)]
+pub enum Completion {
+    Return(LiteralValue),
+    // `xdd f(...)` where `f` is a `pog`. The caller runs `f` in place of the
+    // function that returned, so tail calls do not nest on the host stack.
+    TailCall(Rc<Stmt>, Rc<RefCell<Environment>>, Vec<LiteralValue>)
+}
 
 #[derive(Clone)]
 pub struct Interpreter {
-    pub global: Environment,
-    pub environment: Environment
+    // The outermost scope, where top-level declarations and natives live.
+    pub global: Rc<RefCell<Environment>>,
+    // The innermost scope of the code being run.
+    pub environment: Rc<RefCell<Environment>>,
+    // Innermost call last. The first frame is the script itself. Frames of a
+    // call that failed stay on the stack so the error can be traced.
+    pub call_stack: Vec<Frame>,
+    pub hook: Option<Rc<RefCell<dyn ExecutionHook>>>,
+    // Set by `xdd`. Blocks and loops stop while it is set.
+    pub returning: Option<Completion>,
+    // Calling a function this many calls deep fails with a stack overflow
+    // instead of exhausting the host stack.
+    pub max_call_depth: usize,
+    // Where `chatting` writes to; stdout unless a tool redirects it.
+    pub output: Rc<RefCell<dyn Write>>,
+    // Limits on how much the program may do. Nothing is counted without one.
+    pub budget: Option<Budget>,
+    // Scopes functions have captured, for freeing those left in cycles.
+    pub heap: Heap
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        let new_interpreter = Interpreter {
-            global: Environment::new(),
-            environment: Environment::new()
+        let global = Rc::new(RefCell::new(Environment::new()));
+        let mut new_interpreter = Interpreter {
+            global: global.clone(),
+            environment: global,
+            call_stack: vec![Frame { function: "script".to_string(), line: 0 }],
+            hook: None,
+            returning: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            output: Rc::new(RefCell::new(io::stdout())),
+            budget: None,
+            heap: Heap::new()
         };
+        new_interpreter.define_natives(false);
         new_interpreter
     }
 
-    pub fn interpret_stmt(&mut self, stmt: Vec<Stmt>) {
+    pub fn line(&self) -> usize {
+        self.call_stack.last().map(|f| f.line).unwrap_or(0)
+    }
+
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        self.environment.borrow_mut().define(Symbol::intern(name), LiteralValue::NativeVal(name.to_string(), arity, function)).unwrap();
+    }
+
+    // Also defines the natives that touch files, processes or the network.
+    // Untrusted scripts should never be given these.
+    pub fn allow_host_access(&mut self) {
+        self.define_natives(true);
+    }
+
+    fn define_natives(&mut self, host: bool) {
+        for native in NATIVES.iter().filter(|n| n.host == host) {
+            let value = LiteralValue::NativeVal(native.name.to_string(), native.arity, native.function);
+            self.global.borrow_mut().define(Symbol::intern(native.name), value).unwrap();
+        }
+    }
+
+    // Counts a value the program created against the budget, if there is one.
+    pub fn allocate(&mut self, string_bytes: usize) -> Result<(), String> {
+        match &mut self.budget {
+            Some(budget) => budget.allocate(string_bytes),
+            None => Ok(())
+        }
+    }
+
+    pub fn enter_function(&mut self, frame: Frame) {
+        self.call_stack.push(frame);
+        if let Some(hook) = self.hook.clone() {
+            if let Ok(mut hook) = hook.try_borrow_mut() {
+                hook.function_entered(self);
+            }
+        }
+    }
+
+    pub fn exit_function(&mut self, succeeded: bool) {
+        if let Some(hook) = self.hook.clone() {
+            if let Ok(mut hook) = hook.try_borrow_mut() {
+                hook.function_exited(self);
+            }
+        }
+        if succeeded {
+            self.call_stack.pop();
+        }
+    }
+
+    fn branch_taken(&mut self, token: &Token, branch: usize) {
+        if let Some(hook) = self.hook.clone() {
+            if let Ok(mut hook) = hook.try_borrow_mut() {
+                hook.branch_taken(token, branch);
+            }
+        }
+    }
+
+    pub fn interpret_stmt(&mut self, stmt: &[Stmt]) -> Result<(), String> {
         for s in stmt {
-            self.execute(s).expect("Error executing statement.");
+            let result = self.execute(s).and_then(|_| match self.returning.take() {
+                // `xdd` at the top level ends the script, once any call it
+                // makes has run.
+                Some(Completion::Return(_)) => Ok(true),
+                Some(Completion::TailCall(stmt, closure, arguments)) => {
+                    call_function_val(self, &stmt, &closure, arguments, LiteralValue::NullVal).map(|_| true)
+                },
+                None => Ok(false)
+            });
+            match result {
+                Ok(true) => break,
+                Ok(false) => (),
+                Err(e) => {
+                    let error = format!("{}\n[line {}]", e, self.line());
+                    self.call_stack.truncate(1);
+                    self.returning = None;
+                    return Err(error);
+                }
+            }
         }
+        Ok(())
     }
 
-    pub fn execute_block (&mut self, statements: Vec<Stmt>) -> Result<LiteralValue, String> {
-        self.execute(Stmt::Block { statements })
+    // A function about to be declared will hold the current scope, so the
+    // heap watches it from now on. Collects first if it is due.
+    fn capture_scope(&mut self) {
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        self.heap.track(&self.environment);
+    }
+
+    // Runs a block's statements in `environment`, then returns to the
+    // current scope whether or not they succeeded. Stops early at an `xdd`.
+    pub fn execute_block(&mut self, statements: &[Stmt], environment: Environment) -> Result<LiteralValue, String> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let mut result = Ok(LiteralValue::NullVal);
+        for s in statements {
+            result = self.execute(s);
+            if result.is_err() || self.returning.is_some() {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    pub fn execute(&mut self, stmt: &Stmt) -> Result<LiteralValue, String> {
+        if let Some(budget) = &mut self.budget {
+            budget.step()?;
+        }
+        if let Some(line) = stmt.line() {
+            if let Some(frame) = self.call_stack.last_mut() {
+                frame.line = line;
+            }
+            if let Some(hook) = self.hook.clone() {
+                // Code the hook runs itself, like an expression evaluated in
+                // the debugger, is not reported to it again.
+                if let Ok(mut hook) = hook.try_borrow_mut() {
+                    hook.before_statement(self, stmt, line)?;
+                }
+            }
+        }
+        self.execute_stmt(stmt)
     }
 
-    pub fn execute(&mut self, stmt: Stmt) -> Result<LiteralValue, String>{
+    fn execute_stmt(&mut self, stmt: &Stmt) -> Result<LiteralValue, String> {
         match stmt {
             Stmt::Block { statements } => {
-                let previous = self.environment.clone();
-                self.environment = Environment::new_with_enclosing(previous.clone());
-                for s in statements {
-                    match s {
-                        Stmt::Return { keyword: _, value: _ } => {
-                            self.environment = *(self.environment.enclosing.clone().unwrap());
-                            return Ok(self.execute(s).expect("Error executing return statement."));
-                        },
-                        _ => { self.execute(s).expect("Error executing statement."); }
-                    }
-                }
-                self.environment = *(self.environment.enclosing.clone().unwrap());
-                Ok(LiteralValue::NullVal)
+                let environment = Environment::new_with_enclosing(self.environment.clone());
+                self.execute_block(statements, environment)
             },
             Stmt::Class { name, methods } => {
+                self.allocate(0)?;
+                self.capture_scope();
                 let mut methods_map = std::collections::HashMap::new();
                 for m in methods {
                     match m {
-                        Stmt::Function { name, params, body } => {
-                            methods_map.insert(name.lexeme.clone(), LiteralValue::FunctionVal(Box::new(Stmt::Function { name, params, body })));
+                        Stmt::Function { name, .. } => {
+                            methods_map.insert(name.lexeme.symbol(), LiteralValue::FunctionVal(Rc::new(m.clone()), self.environment.clone()));
                         },
                         _ => panic!("")
                     }
                 }
-                self.environment.define(name.lexeme.clone(), LiteralValue::ClassVal(Box::new(name), methods_map))
+                self.environment.borrow_mut().define(name.lexeme.symbol(), LiteralValue::ClassVal(Box::new(name.clone()), methods_map))
+            },
+            Stmt::Enum { name, variants } => {
+                self.allocate(0)?;
+                let mut variants_map = std::collections::HashMap::new();
+                for (variant, fields) in variants {
+                    variants_map.insert(variant.lexeme.symbol(), fields.len());
+                }
+                self.environment.borrow_mut().define(name.lexeme.symbol(), LiteralValue::EnumVal(Box::new(name.clone()), variants_map))
+            },
+            Stmt::Match { keyword: _, subject, arms } => {
+                let value = self.evaluate_expr(subject)?;
+                for (pattern, body) in arms {
+                    if let Some(bindings) = self.match_pattern(pattern, &value)? {
+                        let mut environment = Environment::new_with_enclosing(self.environment.clone());
+                        for (name, field) in bindings {
+                            environment.define(name, field)?;
+                        }
+                        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+                        let result = self.execute(body);
+                        self.environment = previous;
+                        return result;
+                    }
+                }
+                Err(format!("No pattern matched value '{}'.", value.to_string()))
             },
             Stmt::Return { keyword: _, value } => {
-                match value {
-                    Some(v) => {
-                        match self.evaluate_expr(v) {
-                            Ok(v) => Ok(v),
-                            Err(e) => panic!("{}", e)
+                let completion = match value {
+                    Some(Expr::Call { callee, arguments, .. }) => {
+                        let callee = self.evaluate_expr(callee)?;
+                        let arguments = self.evaluate_arguments(arguments)?;
+                        // With the wrong number of arguments the call fails
+                        // here, while this function's frame can report it.
+                        match callee {
+                            LiteralValue::FunctionVal(stmt, closure) if callee.arity() == arguments.len() => {
+                                Completion::TailCall(stmt, closure, arguments)
+                            },
+                            callee => Completion::Return(self.call_value(callee, arguments)?)
                         }
                     },
-                    None => panic!("")
-                }
+                    Some(v) => Completion::Return(self.evaluate_expr(v)?),
+                    None => Completion::Return(LiteralValue::NullVal)
+                };
+                self.returning = Some(completion);
+                Ok(LiteralValue::NullVal)
             },
             Stmt::Expression { expression } => {
-                match self.evaluate_expr(expression.clone()) {
-                    Ok(_) => Ok(LiteralValue::NullVal),
-                    Err(e) => panic!("{}", e)
-                }
+                self.evaluate_expr(expression)?;
+                Ok(LiteralValue::NullVal)
             },
-            Stmt::While { condition, body } => {
-                loop {
-                    match self.evaluate_expr(condition.clone()) {
-                        Ok(v) => {
-                            if v.is_truthy() {
-                                self.execute(*(body.clone())).expect("Error executing body.");
-                            } else {
-                                break;
-                            }
-                        },
-                        Err(e) => Err(e).expect("Error evaluating condition.")
+            Stmt::While { condition, body, .. } => {
+                while self.evaluate_expr(condition)?.is_truthy() {
+                    self.execute(body)?;
+                    if self.returning.is_some() {
+                        break;
                     }
                 }
                 Ok(LiteralValue::NullVal)
             },
-            Stmt::If { condition, then_branch, else_branch } => {
-                match self.evaluate_expr(condition) {
-                    Ok(v) => {
-                        if v.is_truthy() {
-                            self.execute(*then_branch)
-                        } else {
-                            match else_branch {
-                                Some(b) => self.execute(*b),
-                                None => Ok(LiteralValue::NullVal)
-                            }
-                        }
-                    },
-                    Err(e) => panic!("{}", e)
+            Stmt::If { keyword, condition, then_branch, else_branch } => {
+                if self.evaluate_expr(condition)?.is_truthy() {
+                    self.branch_taken(keyword, 0);
+                    self.execute(then_branch)
+                } else {
+                    self.branch_taken(keyword, 1);
+                    match else_branch {
+                        Some(b) => self.execute(b),
+                        None => Ok(LiteralValue::NullVal)
+                    }
                 }
             },
-            Stmt::Function { name, params, body } => {
-                self.environment.define(name.lexeme.clone(), LiteralValue::FunctionVal(Box::new(Stmt::Function { name, params, body })))
+            Stmt::Function { name, .. } => {
+                self.allocate(0)?;
+                self.capture_scope();
+                let function = LiteralValue::FunctionVal(Rc::new(stmt.clone()), self.environment.clone());
+                self.environment.borrow_mut().define(name.lexeme.symbol(), function)
             }
-            Stmt::Print { expression } => {
-                match self.evaluate_expr(expression) {
-                    Ok(v) => {
-                        println!("{}", v.to_string());
-                        Ok(LiteralValue::NullVal)
-                    },
-                    Err(e) => Err(e)
+            Stmt::Print { expression, .. } => {
+                let text = self.evaluate_expr(expression)?.to_string();
+                if let Some(budget) = &mut self.budget {
+                    budget.output(text.len() + 1)?;
                 }
+                writeln!(self.output.borrow_mut(), "{}", text).map_err(|e| e.to_string())?;
+                Ok(LiteralValue::NullVal)
             },
-            Stmt::Var { name, initializer } => {
-                let value = self.evaluate_expr(initializer).unwrap();
-                self.environment.define(name.lexeme, value)
+            Stmt::Var { name, initializer, .. } => {
+                let value = match initializer {
+                    Some(i) => self.evaluate_expr(i)?,
+                    None => LiteralValue::NullVal
+                };
+                self.environment.borrow_mut().define(name.lexeme.symbol(), value)
+            },
+            Stmt::Const { name, initializer, .. } => {
+                let value = self.evaluate_expr(initializer)?;
+                self.environment.borrow_mut().define_constant(name.lexeme.symbol(), value)
+            }
+        }
+    }
+
+    fn match_pattern(&mut self, pattern: &Pattern, value: &LiteralValue) -> Result<Option<Vec<(Symbol, LiteralValue)>>, String> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(Some(Vec::new())),
+            Pattern::Variant { enum_name, name, bindings } => {
+                match value {
+                    LiteralValue::VariantVal(value_enum, variant, fields) => {
+                        if *variant != name.lexeme.symbol() {
+                            return Ok(None);
+                        }
+                        if let Some(enum_name) = enum_name {
+                            if enum_name.lexeme != value_enum.lexeme {
+                                return Ok(None);
+                            }
+                        }
+                        if bindings.len() != fields.len() {
+                            return Err(format!("Pattern '{}' expects {} field(s) but variant has {}.", name.lexeme, bindings.len(), fields.len()));
+                        }
+                        Ok(Some(bindings.iter().map(|b| b.lexeme.symbol()).zip(fields.iter().cloned()).collect()))
+                    },
+                    _ => Ok(None)
+                }
             }
         }
     }
@@ -133,23 +407,26 @@ impl Interpreter {
         }
     }
 
-    pub fn evaluate_expr(&mut self, expr: Expr) -> Result<LiteralValue, String> {
+    pub fn evaluate_expr(&mut self, expr: &Expr) -> Result<LiteralValue, String> {
+        if let Some(budget) = &mut self.budget {
+            budget.step()?;
+        }
         match expr {
-            Expr::Assign { name, value } => {
-                let value = self.evaluate_expr(*value)?;
-                self.environment.assign(&name, value.clone())?;
+            Expr::Assign { name, value, resolution } => {
+                let value = self.evaluate_expr(value)?;
+                match resolution.get() {
+                    Resolution::Local { depth, slot } => self.environment.borrow_mut().assign_at(depth, slot, value.clone()),
+                    Resolution::Global => self.global.borrow_mut().assign(name, value.clone())?,
+                    Resolution::Unresolved => self.environment.borrow_mut().assign(name, value.clone())?
+                }
                 Ok(value)
             },
             Expr::Binary { left, operator, right } => {
-                let left = self.evaluate_expr(*left)?;
-                let right = self.evaluate_expr(*right)?;
+                let left = self.evaluate_expr(left)?;
+                let right = self.evaluate_expr(right)?;
                 match operator.lexeme.as_str() {
                     ">" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::BooleanVal(l > r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::BooleanVal(l > r)),
@@ -157,11 +434,7 @@ impl Interpreter {
                         }
                     },
                     "<" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::BooleanVal(l < r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::BooleanVal(l < r)),
@@ -169,11 +442,7 @@ impl Interpreter {
                         }
                     },
                     ">=" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::BooleanVal(l >= r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::BooleanVal(l >= r)),
@@ -181,11 +450,7 @@ impl Interpreter {
                         }
                     },
                     "<=" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::BooleanVal(l <= r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::BooleanVal(l <= r)),
@@ -195,24 +460,19 @@ impl Interpreter {
                     "==" => Ok(LiteralValue::BooleanVal(LiteralValue::is_equal(left.clone(), right.clone()))),
                     "!=" => Ok(LiteralValue::BooleanVal(!LiteralValue::is_equal(left.clone(), right.clone()))),
                     "+" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers or two strings.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::NumberVal(l + r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::FloatVal(l + r)),
-                            (LiteralValue::StringVal(l), LiteralValue::StringVal(r)) => Ok(LiteralValue::StringVal(format!("{}{}", l, r))),
+                            (LiteralValue::StringVal(l), LiteralValue::StringVal(r)) => {
+                                self.allocate(l.len() + r.len())?;
+                                Ok(LiteralValue::StringVal(format!("{}{}", l, r).into()))
+                            },
                             _ => Err("Operands must be two numbers or two strings.".to_string())
                         }
                     },
                     "-" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::NumberVal(l - r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::FloatVal(l - r)),
@@ -220,11 +480,7 @@ impl Interpreter {
                         }
                     },
                     "*" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::NumberVal(l * r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::FloatVal(l * r)),
@@ -232,11 +488,7 @@ impl Interpreter {
                         }
                     },
                     "/" => {
-                        let check_op = self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.");
-                        match check_op {
-                            Ok(_) => (),
-                            Err(e) => panic!("{}", e)
-                        }
+                        self.check_operands(left.clone(), right.clone(), "Operands must be two numbers.")?;
                         match (left, right) {
                             (LiteralValue::NumberVal(l), LiteralValue::NumberVal(r)) => Ok(LiteralValue::NumberVal(l / r)),
                             (LiteralValue::FloatVal(l), LiteralValue::FloatVal(r)) => Ok(LiteralValue::FloatVal(l / r)),
@@ -247,40 +499,20 @@ impl Interpreter {
                 }
             },
             Expr::Call { callee, paren: _, arguments } => {
-                let callee = self.evaluate_expr(*callee)?;
-                let mut args = Vec::new();
-                for a in arguments {
-                    args.push(self.evaluate_expr(a)?);
-                }
-                // println!("CALLEE: {:?}", callee);
-                match callee {
-                    LiteralValue::FunctionVal(stmt) => {
-                        match *stmt {
-                            Stmt::Function { name, params: _, body: _} => {
-                                LiteralValue::CallableVal(Box::new(name)).call(self, args)
-                            },
-                            _ => Err("Cannot call non-function.".to_string())
-                        }
-                    },
-                    LiteralValue::ClassVal(name, values) => {
-                        Ok(LiteralValue::InstanceVal(name, values).call(self, args)?)
-                    }
-                    _ => {
-                        // println!("CALLEE ERR: {:?}", callee);
-                        Err("Can only call functions and classes.".to_string())
-                    }
-                }
+                let callee = self.evaluate_expr(callee)?;
+                let args = self.evaluate_arguments(arguments)?;
+                self.call_value(callee, args)
             },
             Expr::Get { object, name } => {
-                let object_val = self.evaluate_expr(*object)?;
+                let object_val = self.evaluate_expr(object)?;
                 let object_val_cpy = object_val.clone();
                 // println!("OBJECT: {:?} NAME: {:?}", object, name);
                 match object_val {
                     LiteralValue::InstanceVal(_klass, values) => {
-                        match values.get(&name.lexeme) {
+                        match values.get(&name.lexeme.symbol()) {
                             Some(v) => {
                                 match v {
-                                    LiteralValue::FunctionVal(stmt) => call_function_val(self, stmt, vec![], object_val_cpy.clone()),
+                                    LiteralValue::FunctionVal(stmt, closure) => call_function_val(self, stmt, closure, vec![], object_val_cpy.clone()),
                                     _ => Ok(v.clone())
                                 }
                             },
@@ -289,15 +521,25 @@ impl Interpreter {
                             }
                         }
                     },
+                    LiteralValue::EnumVal(enum_name, variants) => {
+                        match variants.get(&name.lexeme.symbol()) {
+                            Some(0) => {
+                                self.allocate(0)?;
+                                Ok(LiteralValue::VariantVal(enum_name, name.lexeme.symbol(), Vec::new().into()))
+                            },
+                            Some(arity) => Ok(LiteralValue::VariantCtorVal(enum_name, name.lexeme.symbol(), *arity)),
+                            None => Err(format!("Undefined variant '{}'.", name.lexeme))
+                        }
+                    },
                     _ => Err("Only instances have properties.".to_string())
                 }
             },
             Expr::Set { object, name, value } => {
-                let object = self.evaluate_expr(*object)?;
-                let value = self.evaluate_expr(*value)?;
+                let object = self.evaluate_expr(object)?;
+                let value = self.evaluate_expr(value)?;
                 match object {
                     LiteralValue::InstanceVal(_name_instance, mut values) => {
-                        match values.get_mut(&name.lexeme) {
+                        match values.get_mut(&name.lexeme.symbol()) {
                             Some(v) => {
                                 *v = value.clone();
                                 Ok(value)
@@ -308,37 +550,40 @@ impl Interpreter {
                     _ => Err("Only instances have fields.".to_string())
                 }
             },
-            Expr::Grouping { expression } => self.evaluate_expr(*expression),
+            Expr::Grouping { expression } => self.evaluate_expr(expression),
             Expr::Logical { left, operator, right } => {
-                let left = self.evaluate_expr(*left)?;
-                match operator.lexeme.as_str() {
-                    "or" => {
+                let left = self.evaluate_expr(left)?;
+                match operator.kind {
+                    TokenType::OR => {
                         if left.is_truthy() {
+                            self.branch_taken(operator, 1);
                             Ok(left)
                         } else {
-                            self.evaluate_expr(*right)
+                            self.branch_taken(operator, 0);
+                            self.evaluate_expr(right)
                         }
                     },
-                    "and" => {
+                    TokenType::AND => {
                         if !left.is_truthy() {
+                            self.branch_taken(operator, 1);
                             Ok(left)
                         } else {
-                            self.evaluate_expr(*right)
+                            self.branch_taken(operator, 0);
+                            self.evaluate_expr(right)
                         }
                     },
                     _ => Err("Invalid operator.".to_string())
                 }
             },
             Expr::Literal { value } => Ok(value.clone()),
-            Expr::This { keyword } => {
-                // println!("THIS: {:?}", self.environment.get(&keyword));
-                match self.environment.get(&keyword) {
-                    Ok(v) => Ok(v),
-                    Err(e) => Err(e)
+            Expr::This { keyword, resolution } => {
+                if let Resolution::Local { depth, slot } = resolution.get() {
+                    return Ok(self.environment.borrow().get_at(depth, slot));
                 }
+                self.look_up(keyword, resolution.get())
             },
             Expr::Unary { operator, right } => {
-                let right = self.evaluate_expr(*right)?;
+                let right = self.evaluate_expr(right)?;
                 match (right, operator.lexeme.as_str()) {
                     (LiteralValue::NumberVal(r), "-") => Ok(LiteralValue::NumberVal(-r)),
                     (LiteralValue::FloatVal(r), "-") => Ok(LiteralValue::FloatVal(-r)),
@@ -346,13 +591,38 @@ impl Interpreter {
                     _ => Err("Invalid operand.".to_string())
                 }
             },
-            Expr::Variable { name } => {
-                match self.environment.get(&name) {
-                    Ok(v) => Ok(v),
-                    Err(e) => Err(e)
-                }
+            Expr::Variable { name, resolution } => self.look_up(name, resolution.get())
+        }
+    }
+
+    fn evaluate_arguments(&mut self, arguments: &[Expr]) -> Result<Vec<LiteralValue>, String> {
+        let mut args = Vec::new();
+        for a in arguments {
+            args.push(self.evaluate_expr(a)?);
+        }
+        Ok(args)
+    }
+
+    fn call_value(&mut self, callee: LiteralValue, args: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+        // println!("CALLEE: {:?}", callee);
+        match callee {
+            LiteralValue::FunctionVal(stmt, closure) => call_function_val(self, &stmt, &closure, args, LiteralValue::NullVal),
+            LiteralValue::ClassVal(name, values) => {
+                Ok(LiteralValue::InstanceVal(name, values).call(self, args)?)
             },
+            LiteralValue::VariantCtorVal(..) | LiteralValue::NativeVal(..) => callee.call(self, args),
+            _ => {
+                // println!("CALLEE ERR: {:?}", callee);
+                Err("Can only call functions and classes.".to_string())
+            }
+        }
+    }
 
+    fn look_up(&self, name: &Token, resolution: Resolution) -> Result<LiteralValue, String> {
+        match resolution {
+            Resolution::Local { depth, slot } => Ok(self.environment.borrow().get_at(depth, slot)),
+            Resolution::Global => self.global.borrow().get(name),
+            Resolution::Unresolved => self.environment.borrow().get(name)
         }
     }
 }
\ No newline at end of file
@@ -1,3 +1,8 @@
 pub mod interpreter;
 pub mod environment;
-pub mod callable;
\ No newline at end of file
+pub mod callable;
+pub mod resolver;
+pub mod budget;
+pub mod natives;
+pub mod gc;
+pub mod options;
\ No newline at end of file
@@ -0,0 +1,66 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::interpreter::interpreter::Interpreter;
+use crate::lexer::token::{LiteralValue, NativeFn};
+
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: NativeFn,
+    // Reads or changes something outside the interpreter, so it is only
+    // defined once the embedder allows host access.
+    pub host: bool
+}
+
+// Defined in every interpreter's global scope, apart from the `host` ones.
+// There are no process or network natives yet; any added must be marked
+// `host`. The VM has its own table with the same names.
+pub const NATIVES: &[Native] = &[
+    Native { name: "clock", arity: 0, function: clock, host: false },
+    Native { name: "gc", arity: 0, function: gc, host: false },
+    Native { name: "read_file", arity: 1, function: read_file, host: true },
+    Native { name: "env", arity: 1, function: env, host: true }
+];
+
+// Seconds since the Unix epoch.
+pub fn seconds_since_epoch() -> Result<f64, String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+    Ok(now.as_secs_f64())
+}
+
+// The parts of `read_file` and `env` that do not depend on the backend's
+// values. `None` is an argument that is not a string.
+pub fn file_contents(path: Option<&str>) -> Result<String, String> {
+    let path = path.ok_or("read_file expects a path.")?;
+    fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path, e))
+}
+
+pub fn environment_variable(name: Option<&str>) -> Result<Option<String>, String> {
+    let name = name.ok_or("env expects a variable name.")?;
+    Ok(std::env::var(name).ok())
+}
+
+fn string_argument(value: &LiteralValue) -> Option<&str> {
+    match value {
+        LiteralValue::StringVal(s) => Some(s),
+        _ => None
+    }
+}
+
+fn clock(_interpreter: &mut Interpreter, _arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    Ok(LiteralValue::FloatVal(seconds_since_epoch()?))
+}
+
+// Collects garbage now and gives the number of scopes freed.
+fn gc(interpreter: &mut Interpreter, _arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    Ok(LiteralValue::FloatVal(interpreter.heap.collect() as f64))
+}
+
+fn read_file(_interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    Ok(LiteralValue::StringVal(file_contents(string_argument(&arguments[0]))?.into()))
+}
+
+// The environment variable's value, or nil when it is not set.
+fn env(_interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    Ok(environment_variable(string_argument(&arguments[0]))?.map_or(LiteralValue::NullVal, |v| LiteralValue::StringVal(v.into())))
+}
@@ -0,0 +1,23 @@
+use crate::interpreter::budget::Budget;
+use crate::interpreter::interpreter::Interpreter;
+
+// How scripts run, from the command line. Every command that runs a script
+// applies the same options.
+pub struct RunOptions {
+    pub max_depth: usize,
+    pub budget: Option<Budget>,
+    // Leaves out the natives that touch the host.
+    pub sandbox: bool,
+    // Reports what the garbage collector did once the script ends.
+    pub gc_stats: bool
+}
+
+impl RunOptions {
+    pub fn apply(&self, interpreter: &mut Interpreter) {
+        interpreter.max_call_depth = self.max_depth;
+        interpreter.budget = self.budget.clone();
+        if !self.sandbox {
+            interpreter.allow_host_access();
+        }
+    }
+}
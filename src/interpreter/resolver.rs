@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use crate::error_token;
+use crate::lexer::token::Token;
+use crate::parser::expr::{Expr, Resolution};
+use crate::parser::parser::ParseError;
+use crate::parser::stmt::{Pattern, Stmt};
+
+// The names declared in one scope. Each maps to whether it was declared with
+// `kappa123` and the slot the interpreter keeps it in. Slots are handed out
+// in declaration order; redeclaring a name gives it a new one.
+#[derive(Default)]
+struct Scope {
+    bindings: HashMap<String, (bool, usize)>,
+    slots: usize
+}
+
+// Checks declarations and works out which scope every variable use refers
+// to. Uses are annotated with a `Resolution` the interpreter reads.
+pub struct Resolver {
+    // Innermost scope is last; the first scope holds top-level declarations,
+    // which stay globals looked up by name.
+    scopes: Vec<Scope>,
+    report_errors: bool,
+    pub errors: Vec<ParseError>
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: vec![Scope::default()],
+            report_errors: true,
+            errors: Vec::new()
+        }
+    }
+
+    // Collects errors in `errors` instead of reporting the first one and
+    // aborting.
+    pub fn collect_errors(&mut self) {
+        self.report_errors = false;
+    }
+
+    fn error(&mut self, token: &Token, message: String) {
+        if self.report_errors {
+            error_token(token.clone(), &message);
+        }
+        self.errors.push(ParseError::new(message, token.clone()));
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) {
+        for s in statements {
+            self.resolve_stmt(s);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, constant: bool) {
+        let redeclared = self.scopes.last()
+            .and_then(|scope| scope.bindings.get(name.lexeme.as_str()))
+            .is_some_and(|(constant, _)| *constant);
+        if redeclared {
+            self.error(name, format!("Cannot redeclare constant '{}'.", name.lexeme));
+        }
+        self.declare_name(&name.lexeme, constant);
+    }
+
+    fn declare_name(&mut self, name: &str, constant: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.bindings.insert(name.to_string(), (constant, scope.slots));
+            scope.slots += 1;
+        }
+    }
+
+    fn is_constant(&self, name: &Token) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if let Some((constant, _)) = scope.bindings.get(name.lexeme.as_str()) {
+                return *constant;
+            }
+        }
+        false
+    }
+
+    fn resolve_local(&self, name: &str) -> Resolution {
+        for (i, scope) in self.scopes.iter().enumerate().skip(1).rev() {
+            if let Some((_, slot)) = scope.bindings.get(name) {
+                return Resolution::Local { depth: self.scopes.len() - 1 - i, slot: *slot };
+            }
+        }
+        Resolution::Global
+    }
+
+    // Methods get `this` in the first slot, ahead of their parameters, the
+    // way the interpreter binds them.
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], method: bool) {
+        self.begin_scope();
+        if method {
+            self.declare_name("this", false);
+        }
+        for param in params {
+            self.declare(param, false);
+        }
+        self.resolve(body);
+        self.end_scope();
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.resolve(statements);
+                self.end_scope();
+            },
+            Stmt::Class { name, methods } => {
+                self.declare(name, false);
+                for m in methods {
+                    if let Stmt::Function { params, body, .. } = m {
+                        self.resolve_function(params, body, true);
+                    }
+                }
+            },
+            Stmt::Const { name, initializer, .. } => {
+                self.resolve_expr(initializer);
+                self.declare(name, true);
+            },
+            Stmt::Enum { name, .. } => self.declare(name, false),
+            Stmt::Expression { expression } => self.resolve_expr(expression),
+            Stmt::Function { name, params, body, .. } => {
+                self.declare(name, false);
+                self.resolve_function(params, body, false);
+            },
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(b) = else_branch {
+                    self.resolve_stmt(b);
+                }
+            },
+            Stmt::Match { subject, arms, .. } => {
+                self.resolve_expr(subject);
+                for (pattern, body) in arms {
+                    self.begin_scope();
+                    if let Pattern::Variant { bindings, .. } = pattern {
+                        for b in bindings {
+                            self.declare(b, false);
+                        }
+                    }
+                    self.resolve_stmt(body);
+                    self.end_scope();
+                }
+            },
+            Stmt::Print { expression, .. } => self.resolve_expr(expression),
+            Stmt::Return { value, .. } => {
+                if let Some(v) = value {
+                    self.resolve_expr(v);
+                }
+            },
+            Stmt::While { condition, body, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            },
+            Stmt::Var { name, initializer, .. } => {
+                if let Some(i) = initializer {
+                    self.resolve_expr(i);
+                }
+                self.declare(name, false);
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, resolution } => {
+                self.resolve_expr(value);
+                if self.is_constant(name) {
+                    self.error(name, format!("Cannot assign to constant '{}'.", name.lexeme));
+                }
+                resolution.set(self.resolve_local(&name.lexeme));
+            },
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            },
+            Expr::Call { callee, arguments, .. } => {
+                self.resolve_expr(callee);
+                for a in arguments {
+                    self.resolve_expr(a);
+                }
+            },
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            },
+            Expr::Grouping { expression } => self.resolve_expr(expression),
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::This { resolution, .. } => resolution.set(self.resolve_local("this")),
+            Expr::Variable { name, resolution } => resolution.set(self.resolve_local(&name.lexeme)),
+            Expr::Literal { .. } => ()
+        }
+    }
+}
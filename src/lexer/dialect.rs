@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use crate::lexer::lexer::{is_identifier_char, is_identifier_start};
+use crate::lexer::token::TokenType;
+use crate::util::json::Json;
+
+// Every keyword a dialect has to spell, keyed by its plain Lox name. Dialect
+// files use these names as keys.
+const KEYWORDS: [(&str, TokenType); 19] = [
+    ("and", TokenType::AND),
+    ("class", TokenType::CLASS),
+    ("const", TokenType::CONST),
+    ("else", TokenType::ELSE),
+    ("enum", TokenType::ENUM),
+    ("false", TokenType::FALSE),
+    ("for", TokenType::FOR),
+    ("fun", TokenType::FUN),
+    ("if", TokenType::IF),
+    ("match", TokenType::MATCH),
+    ("nil", TokenType::NIL),
+    ("or", TokenType::OR),
+    ("print", TokenType::PRINT),
+    ("return", TokenType::RETURN),
+    ("super", TokenType::SUPER),
+    ("this", TokenType::THIS),
+    ("true", TokenType::TRUE),
+    ("var", TokenType::VAR),
+    ("while", TokenType::WHILE)
+];
+
+// The default spellings. The file doubles as the template for new dialects.
+const PEPEGA: &str = include_str!("../../Dialects/pepega.json");
+
+// The plain Lox name of a keyword, e.g. "if" for `clueless`.
+pub fn keyword_name(kind: &TokenType) -> Option<&'static str> {
+    KEYWORDS.iter().find(|(_, k)| k == kind).map(|(name, _)| *name)
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Dialect {
+    pub name: String,
+    keywords: HashMap<String, TokenType>
+}
+
+impl Dialect {
+    pub fn pepega() -> Dialect {
+        Dialect::from_json(PEPEGA).expect("Built-in pepega dialect is invalid.")
+    }
+
+    pub fn lox() -> Dialect {
+        let spellings: Vec<(&str, &str)> = KEYWORDS.iter().map(|(name, _)| (*name, *name)).collect();
+        Dialect::from_spellings("lox", &spellings).expect("Built-in lox dialect is invalid.")
+    }
+
+    pub fn builtin(name: &str) -> Option<Dialect> {
+        match name {
+            "pepega" => Some(Dialect::pepega()),
+            "lox" => Some(Dialect::lox()),
+            _ => None
+        }
+    }
+
+    // Accepts either the name of a built-in dialect or a path to a dialect file.
+    pub fn load(name_or_path: &str) -> Result<Dialect, String> {
+        if let Some(dialect) = Dialect::builtin(name_or_path) {
+            return Ok(dialect);
+        }
+        let source = fs::read_to_string(name_or_path)
+            .map_err(|e| format!("Cannot read dialect file '{}' (built-in dialects are 'pepega' and 'lox'): {}", name_or_path, e))?;
+        Dialect::from_json(&source)
+    }
+
+    // A dialect file looks like
+    //   { "name": "bttv", "keywords": { "if": "clueless", "else": "aware", ... } }
+    // and has to spell every keyword exactly once.
+    pub fn from_json(source: &str) -> Result<Dialect, String> {
+        let json = Json::parse(source).map_err(|e| format!("Invalid dialect file: {}", e))?;
+        let name = json.get("name").and_then(|n| n.as_str()).unwrap_or("custom");
+        let keywords = json.get("keywords").and_then(|k| k.as_object())
+            .ok_or_else(|| format!("Dialect '{}' has no \"keywords\" object.", name))?;
+        let mut spellings: Vec<(&str, &str)> = Vec::new();
+        for (keyword, spelling) in keywords {
+            let spelling = spelling.as_str()
+                .ok_or_else(|| format!("Dialect '{}' spells '{}' with a non-string value.", name, keyword))?;
+            spellings.push((keyword, spelling));
+        }
+        Dialect::from_spellings(name, &spellings)
+    }
+
+    fn from_spellings(name: &str, spellings: &[(&str, &str)]) -> Result<Dialect, String> {
+        let mut keywords: HashMap<String, TokenType> = HashMap::new();
+        for (keyword, spelling) in spellings {
+            let kind = match KEYWORDS.iter().find(|(k, _)| k == keyword) {
+                Some((_, kind)) => kind.clone(),
+                None => return Err(format!("Dialect '{}' spells unknown keyword '{}'.", name, keyword))
+            };
+            if keywords.values().any(|k| *k == kind) {
+                return Err(format!("Dialect '{}' spells '{}' more than once.", name, keyword));
+            }
+            let mut chars = spelling.chars();
+            let valid = chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_char);
+            if !valid {
+                return Err(format!("Dialect '{}' spells '{}' as '{}', which is not a valid identifier.", name, keyword, spelling));
+            }
+            if let Some(other) = keywords.get(*spelling) {
+                let other = KEYWORDS.iter().find(|(_, k)| k == other).map(|(k, _)| *k).unwrap_or("?");
+                return Err(format!("Dialect '{}' uses '{}' for both '{}' and '{}'.", name, spelling, other, keyword));
+            }
+            keywords.insert(spelling.to_string(), kind);
+        }
+        for (keyword, kind) in KEYWORDS.iter() {
+            if !keywords.values().any(|k| k == kind) {
+                return Err(format!("Dialect '{}' is missing a spelling for '{}'.", name, keyword));
+            }
+        }
+        Ok(Dialect { name: name.to_string(), keywords })
+    }
+
+    pub fn keywords(&self) -> &HashMap<String, TokenType> {
+        &self.keywords
+    }
+
+    pub fn spelling(&self, kind: &TokenType) -> Option<&str> {
+        self.keywords.iter().find(|(_, k)| *k == kind).map(|(spelling, _)| spelling.as_str())
+    }
+}
@@ -1,27 +1,22 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 use crate::error;
+use crate::lexer::dialect::Dialect;
 use crate::lexer::token::{LiteralValue, Token};
 use crate::lexer::token::TokenType;
 
-pub fn get_keywords() -> HashMap<String, TokenType> {
-    [
-        ("and".to_string(), TokenType::AND),
-        ("gigachad".to_string(), TokenType::CLASS),
-        ("aware".to_string(), TokenType::ELSE),
-        ("kappa".to_string(), TokenType::FALSE),
-        ("forsen".to_string(), TokenType::FOR),
-        ("pog".to_string(), TokenType::FUN),
-        ("clueless".to_string(), TokenType::IF),
-        ("nil".to_string(), TokenType::NIL),
-        ("or".to_string(), TokenType::OR),
-        ("chatting".to_string(), TokenType::PRINT),
-        ("xdd".to_string(), TokenType::RETURN),
-        ("super".to_string(), TokenType::SUPER),
-        ("this".to_string(), TokenType::THIS),
-        ("yep".to_string(), TokenType::TRUE),
-        ("kekw".to_string(), TokenType::VAR),
-        ("residentsleeper".to_string(), TokenType::WHILE)
-    ].iter().cloned().collect()
+pub struct LexError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+pub fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+pub fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
 pub struct Lexer {
@@ -30,22 +25,45 @@ pub struct Lexer {
     start: usize,
     current: usize,
     line: usize,
-    keywords: HashMap<String, TokenType>
+    // Columns of `current` and `start`, in characters.
+    column: usize,
+    start_column: usize,
+    keywords: HashMap<String, TokenType>,
+    keep_trivia: bool,
+    report_errors: bool,
+    pub errors: Vec<LexError>
 }
 
 impl Lexer {
-    pub fn new(source: String) -> Lexer {
-
+    pub fn new_with_dialect(source: String, dialect: &Dialect) -> Lexer {
         Lexer {
             source,
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
-            keywords: get_keywords()
+            column: 0,
+            start_column: 0,
+            keywords: dialect.keywords().clone(),
+            keep_trivia: false,
+            report_errors: true,
+            errors: Vec::new()
         }
     }
 
+    // Collects errors in `errors` and keeps scanning instead of reporting the
+    // first one and aborting.
+    pub fn collect_errors(&mut self) {
+        self.report_errors = false;
+    }
+
+    fn error(&mut self, message: &str) {
+        if self.report_errors {
+            error(self.line as i32, message.to_string());
+        }
+        self.errors.push(LexError { line: self.line, column: self.start_column, message: message.to_string() });
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -53,14 +71,37 @@ impl Lexer {
     pub fn scan_tokens(&mut self) {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_column = self.column;
             self.scan_token();
         }
-        self.tokens.push(Token::new(TokenType::EOF, "".to_string(), self.line, None))
+        self.tokens.push(Token::new(TokenType::EOF, "", self.line, self.column, None))
+    }
+
+    // Also emits WHITESPACE, NEWLINE and COMMENT tokens, so that concatenating
+    // every lexeme gives back the source exactly. Only for tooling; the parser
+    // does not expect these.
+    pub fn scan_tokens_with_trivia(&mut self) {
+        self.keep_trivia = true;
+        self.scan_tokens();
+    }
+
+    fn add_trivia(&mut self, kind: TokenType) {
+        if self.keep_trivia {
+            self.add_token(kind);
+        }
     }
 
+    // `start` and `current` are byte offsets into `source`, always on a char
+    // boundary, so the lexeme slices below are valid for any UTF-8 input.
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source.chars().nth(self.current - 1).unwrap()
+        let c = self.peek();
+        self.current += c.len_utf8();
+        if c == '\n' {
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+        c
     }
 
     fn add_token(&mut self, kind: TokenType) {
@@ -68,18 +109,16 @@ impl Lexer {
     }
 
     fn add_token_literal(&mut self, kind: TokenType, literal: Option<LiteralValue>) {
-        let text = self.source[self.start..self.current].to_string();
-        self.tokens.push(Token::new(kind, text, self.line, literal));
+        let text = &self.source[self.start..self.current];
+        self.tokens.push(Token::new(kind, text, self.line, self.start_column, literal));
     }
 
     fn match_advance(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
-            return false;
-        }
-        self.current += 1;
+        self.current += expected.len_utf8();
+        self.column += 1;
         true
     }
 
@@ -91,28 +130,25 @@ impl Lexer {
             self.advance();
         }
         if self.is_at_end() {
-            error(self.line as i32, "Unterminated string.".to_string());
+            self.error("Unterminated string.");
             return;
         }
         self.advance();
-        let value = self.source[self.start + 1..self.current - 1].to_string();
+        let value = Rc::from(&self.source[self.start + 1..self.current - 1]);
         self.add_token_literal(TokenType::STRING, Some(LiteralValue::StringVal(value)));
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -121,38 +157,18 @@ impl Lexer {
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current + 1).unwrap()
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() {
+        while is_identifier_char(self.peek()) {
             self.advance();
         }
-        let text = self.source[self.start..self.current].to_string();
-        let kind = self.keywords.get(&text).unwrap_or(&TokenType::IDENTIFIER).clone();
-        let kind_val: TokenType = match kind {
-            TokenType::AND => TokenType::AND,
-            TokenType::CLASS => TokenType::CLASS,
-            TokenType::ELSE => TokenType::ELSE,
-            TokenType::FALSE => TokenType::FALSE,
-            TokenType::FOR => TokenType::FOR,
-            TokenType::FUN => TokenType::FUN,
-            TokenType::IF => TokenType::IF,
-            TokenType::NIL => TokenType::NIL,
-            TokenType::OR => TokenType::OR,
-            TokenType::PRINT => TokenType::PRINT,
-            TokenType::RETURN => TokenType::RETURN,
-            TokenType::SUPER => TokenType::SUPER,
-            TokenType::THIS => TokenType::THIS,
-            TokenType::TRUE => TokenType::TRUE,
-            TokenType::VAR => TokenType::VAR,
-            TokenType::WHILE => TokenType::WHILE,
-            _ => TokenType::IDENTIFIER
-        };
-        self.add_token(kind_val);
+        let text = &self.source[self.start..self.current];
+        let kind = self.keywords.get(text).unwrap_or(&TokenType::IDENTIFIER).clone();
+        self.add_token(kind);
     }
 
     fn scan_token(&mut self) {
@@ -164,9 +180,16 @@ impl Lexer {
             '}' => self.add_token(TokenType::RIGHT_BRACE),
             ',' => self.add_token(TokenType::COMMA),
             '.' => self.add_token(TokenType::DOT),
-            '-' => self.add_token(TokenType::MINUS),
+            '-' => {
+                if self.match_advance('>') {
+                    self.add_token(TokenType::ARROW);
+                } else {
+                    self.add_token(TokenType::MINUS);
+                }
+            },
             '+' => self.add_token(TokenType::PLUS),
             ';' => self.add_token(TokenType::SEMICOLON),
+            ':' => self.add_token(TokenType::COLON),
             '*' => self.add_token(TokenType::STAR),
             '!' => {
                 if self.match_advance('=') {
@@ -178,6 +201,8 @@ impl Lexer {
             '=' => {
                 if self.match_advance('=') {
                     self.add_token(TokenType::EQUAL_EQUAL);
+                } else if self.match_advance('>') {
+                    self.add_token(TokenType::FAT_ARROW);
                 } else {
                     self.add_token(TokenType::EQUAL);
                 }
@@ -201,24 +226,96 @@ impl Lexer {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_trivia(TokenType::COMMENT);
                 } else {
                     self.add_token(TokenType::SLASH);
                 }
             },
-            ' ' => (),
-            '\r' => (),
-            '\t' => (),
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+                self.add_trivia(TokenType::WHITESPACE);
+            },
+            '\n' => {
+                self.add_trivia(TokenType::NEWLINE);
+                self.line += 1;
+            },
             '"' => self.string(),
             _ => {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     self.number();
-                } else if c.is_alphabetic() {
+                } else if is_identifier_start(c) {
                     self.identifier();
                 } else {
-                    error(self.line as i32, "Unexpected character.".to_string());
+                    self.error("Unexpected character.");
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Lexer;
+    use crate::lexer::dialect::Dialect;
+    use crate::lexer::token::{LiteralValue, Token, TokenType};
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new_with_dialect(source.to_string(), &Dialect::pepega());
+        lexer.collect_errors();
+        lexer.scan_tokens();
+        assert!(lexer.errors.is_empty());
+        lexer.tokens
+    }
+
+    #[test]
+    fn non_ascii_identifiers() {
+        let tokens = tokens("kekw café = 1; kekw 変数 = café; kekw Ωmega_2 = 変数;");
+        let identifiers: Vec<&str> = tokens.iter().filter(|t| t.kind == TokenType::IDENTIFIER).map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(identifiers, ["café", "変数", "café", "Ωmega_2", "変数"]);
+    }
+
+    #[test]
+    fn non_ascii_strings() {
+        let tokens = tokens("chatting \"naïve 🐸 日本\";");
+        assert_eq!(tokens[1].kind, TokenType::STRING);
+        assert_eq!(tokens[1].lexeme.as_str(), "\"naïve 🐸 日本\"");
+        match &tokens[1].literal {
+            Some(LiteralValue::StringVal(s)) => assert_eq!(&**s, "naïve 🐸 日本"),
+            other => panic!("expected a string literal, got {:?}", other)
+        }
+        assert_eq!(tokens[2].kind, TokenType::SEMICOLON);
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let tokens = tokens("kekw é = \"🐸🐸\";\n  日本 = é;");
+        let positions: Vec<(&str, usize, usize)> = tokens.iter().map(|t| (t.lexeme.as_str(), t.line, t.column)).collect();
+        assert_eq!(positions, [
+            ("kekw", 1, 0), ("é", 1, 5), ("=", 1, 7), ("\"🐸🐸\"", 1, 9), (";", 1, 13),
+            ("日本", 2, 2), ("=", 2, 5), ("é", 2, 7), (";", 2, 8), ("", 2, 9)
+        ]);
+    }
+
+    // Scanning by byte offset keeps a long line of multi-byte text linear;
+    // indexing by character would take minutes here.
+    #[test]
+    fn long_non_ascii_line() {
+        let text = "日本🐸é".repeat(50_000);
+        let start = std::time::Instant::now();
+        let tokens = tokens(&format!("chatting \"{}\"; kekw ü = 1;", text));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(tokens.len(), 9);
+        assert_eq!((tokens[4].lexeme.as_str(), tokens[4].column), ("ü", 200_018));
+    }
+
+    #[test]
+    fn error_column_after_multi_byte_characters() {
+        let mut lexer = Lexer::new_with_dialect("kekw ñ = 1 € 2;".to_string(), &Dialect::pepega());
+        lexer.collect_errors();
+        lexer.scan_tokens();
+        let errors: Vec<(usize, usize, &str)> = lexer.errors.iter().map(|e| (e.line, e.column, e.message.as_str())).collect();
+        assert_eq!(errors, [(1, 11, "Unexpected character.")]);
+    }
+}
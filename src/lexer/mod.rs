@@ -1,2 +1,4 @@
 pub mod lexer;
 pub mod token;
+pub mod dialect;
+pub mod symbol;
\ No newline at end of file
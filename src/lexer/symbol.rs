@@ -0,0 +1,142 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+// An interned string, used for identifiers, keywords and every name the
+// interpreter keys a map with. Equal text on one thread always shares one
+// copy, so comparing or hashing a `Symbol` only looks at its address.
+// Dereferences to the text.
+//
+// A symbol is a reference to its text rather than an index into the table:
+// it is the size of an `Rc<str>`, and cloning one bumps a count. In exchange
+// the text never needs the table to be read, and the table can drop text no
+// symbol refers to any more. Symbols hold an `Rc`, so they stay on the thread
+// that interned them.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8 as usize).hash(state);
+    }
+}
+
+// The table holds one reference to each symbol's text. Text no symbol uses
+// any more is swept out once the table has doubled since the last sweep, so
+// a long-running process like the language server only keeps the names its
+// current documents use.
+struct Interner {
+    symbols: HashSet<Rc<str>>,
+    sweep_at: usize
+}
+
+const MIN_SWEEP: usize = 1024;
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner { symbols: HashSet::new(), sweep_at: MIN_SWEEP });
+}
+
+impl Interner {
+    fn sweep(&mut self) {
+        self.symbols.retain(|text| Rc::strong_count(text) > 1);
+        self.sweep_at = (self.symbols.len() * 2).max(MIN_SWEEP);
+    }
+}
+
+impl Symbol {
+    pub fn intern(text: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(text) = interner.symbols.get(text) {
+                return Symbol(text.clone());
+            }
+            if interner.symbols.len() >= interner.sweep_at {
+                interner.sweep();
+            }
+            let text: Rc<str> = Rc::from(text);
+            interner.symbols.insert(text.clone());
+            Symbol(text)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbol;
+
+    #[test]
+    fn same_name_same_symbol() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern(&String::from("counter"));
+        assert_eq!(a, b);
+        assert_eq!(a.as_str(), "counter");
+    }
+
+    #[test]
+    fn different_names_different_symbols() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern("Counter");
+        assert_ne!(a, b);
+        assert_ne!(Symbol::intern("count"), a);
+        assert_eq!(b, "Counter");
+    }
+
+    // Text swept from the table while unused gets a new copy when it is
+    // interned again, which every symbol still alive then shares.
+    #[test]
+    fn equal_after_sweeping() {
+        let kept = Symbol::intern("kept");
+        for i in 0..4096 {
+            Symbol::intern(&format!("name{}", i));
+        }
+        assert_eq!(Symbol::intern("kept"), kept);
+        assert_eq!(Symbol::intern("name0"), Symbol::intern("name0"));
+    }
+}
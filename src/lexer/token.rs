@@ -1,22 +1,81 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use crate::interpreter::callable::{Callable};
+use crate::interpreter::environment::Environment;
+use crate::interpreter::interpreter::{stack_overflow, Completion, Frame};
+use crate::lexer::dialect::keyword_name;
+use crate::lexer::symbol::Symbol;
 use crate::parser::stmt::Stmt;
 
 #[derive(Debug)]
 #[derive(Clone)]
 pub enum LiteralValue {
-    StringVal(String),
+    // Shared, so copying a string value never copies its text.
+    StringVal(Rc<str>),
     NumberVal(i64),
     FloatVal(f64),
     NullVal,
     BooleanVal(bool),
     IdentifierVal(String),
-    FunctionVal(Box<Stmt>),
-    CallableVal(Box<Token>),
-    ClassVal(Box<Token>, HashMap<String, LiteralValue>),
-    InstanceVal(Box<Token>, HashMap<String, LiteralValue>)
+    // A `pog` declaration and the scope it was declared in.
+    FunctionVal(Rc<Stmt>, Rc<RefCell<Environment>>),
+    ClassVal(Box<Token>, HashMap<Symbol, LiteralValue>),
+    InstanceVal(Box<Token>, HashMap<Symbol, LiteralValue>),
+    EnumVal(Box<Token>, HashMap<Symbol, usize>),
+    VariantCtorVal(Box<Token>, Symbol, usize),
+    VariantVal(Box<Token>, Symbol, Fields),
+    // A function written in Rust: its name, arity and implementation.
+    NativeVal(String, usize, NativeFn)
 }
 
+// A variant's fields. Variants never change, so copies share them.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Fields(Rc<Vec<LiteralValue>>);
+
+impl From<Vec<LiteralValue>> for Fields {
+    fn from(fields: Vec<LiteralValue>) -> Fields {
+        Fields(Rc::new(fields))
+    }
+}
+
+impl Fields {
+    // Whether another variant holds these fields too.
+    pub fn is_shared(&self) -> bool {
+        Rc::strong_count(&self.0) > 1
+    }
+}
+
+impl std::ops::Deref for Fields {
+    type Target = [LiteralValue];
+
+    fn deref(&self) -> &[LiteralValue] {
+        &self.0
+    }
+}
+
+// Variants nest, as in a cons list, and dropping them one inside the other
+// would take a host stack frame per level. Fields nobody else shares are
+// unpacked onto a list and dropped one at a time instead.
+impl Drop for Fields {
+    fn drop(&mut self) {
+        let mut pending = match Rc::get_mut(&mut self.0) {
+            Some(fields) => std::mem::take(fields),
+            None => return
+        };
+        while let Some(value) = pending.pop() {
+            if let LiteralValue::VariantVal(_, _, mut fields) = value {
+                if let Some(fields) = Rc::get_mut(&mut fields.0) {
+                    pending.append(fields);
+                }
+            }
+        }
+    }
+}
+
+pub type NativeFn = fn(&mut crate::interpreter::interpreter::Interpreter, Vec<LiteralValue>) -> Result<LiteralValue, String>;
+
 impl LiteralValue {
     pub fn to_string(&self) -> String {
         match self {
@@ -26,10 +85,20 @@ impl LiteralValue {
             LiteralValue::NullVal => "nil".to_string(),
             LiteralValue::IdentifierVal(i) => i.to_string(),
             LiteralValue::BooleanVal(b) => b.to_string(),
-            LiteralValue::CallableVal(_) => "callable".to_string(),
-            LiteralValue::FunctionVal(_) => "function".to_string(),
+            LiteralValue::FunctionVal(_, _) => "function".to_string(),
             LiteralValue::ClassVal(_, _) => "class".to_string(),
-            LiteralValue::InstanceVal(token, _values) => (*(token.lexeme.clone())).to_string()
+            LiteralValue::InstanceVal(token, _values) => token.lexeme.to_string(),
+            LiteralValue::EnumVal(_, _) => "enum".to_string(),
+            LiteralValue::VariantCtorVal(_, _, _) => "variant".to_string(),
+            LiteralValue::NativeVal(..) => "native function".to_string(),
+            LiteralValue::VariantVal(_, variant, fields) => {
+                if fields.is_empty() {
+                    variant.to_string()
+                } else {
+                    let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+                    format!("{}({})", variant, fields.join(", "))
+                }
+            }
         }
     }
 
@@ -41,10 +110,13 @@ impl LiteralValue {
             LiteralValue::NumberVal(n) => *n != 0,
             LiteralValue::StringVal(s) => s.len() > 0,
             LiteralValue::IdentifierVal(_) => true,
-            LiteralValue::CallableVal(_) => true,
-            LiteralValue::FunctionVal(_) => true,
+            LiteralValue::FunctionVal(_, _) => true,
             LiteralValue::ClassVal(_, _) => true,
-            LiteralValue::InstanceVal(_, _) => true
+            LiteralValue::InstanceVal(_, _) => true,
+            LiteralValue::EnumVal(_, _) => true,
+            LiteralValue::VariantCtorVal(_, _, _) => true,
+            LiteralValue::VariantVal(_, _, _) => true,
+            LiteralValue::NativeVal(..) => true
         }
     }
 
@@ -86,15 +158,9 @@ impl LiteralValue {
                     _ => false
                 }
             },
-            LiteralValue::CallableVal(_) => {
-                match other_val {
-                    LiteralValue::CallableVal(_) => false,
-                    _ => false
-                }
-            },
-            LiteralValue::FunctionVal(_) => {
+            LiteralValue::FunctionVal(_, _) => {
                 match other_val {
-                    LiteralValue::FunctionVal(_) => false,
+                    LiteralValue::FunctionVal(_, _) => false,
                     _ => false
                 }
             },
@@ -109,6 +175,25 @@ impl LiteralValue {
                     LiteralValue::InstanceVal(_, _) => false,
                     _ => false
                 }
+            },
+            LiteralValue::EnumVal(_, _) => false,
+            LiteralValue::VariantCtorVal(_, _, _) => false,
+            LiteralValue::NativeVal(name, ..) => {
+                match other_val {
+                    LiteralValue::NativeVal(o, ..) => name == o,
+                    _ => false
+                }
+            },
+            LiteralValue::VariantVal(enum_name, variant, fields) => {
+                match other_val {
+                    LiteralValue::VariantVal(o_enum_name, o_variant, o_fields) => {
+                        enum_name.lexeme == o_enum_name.lexeme
+                            && variant == o_variant
+                            && fields.len() == o_fields.len()
+                            && fields.iter().zip(o_fields.iter()).all(|(f, o)| LiteralValue::is_equal(f.clone(), o.clone()))
+                    },
+                    _ => false
+                }
             }
         }
     }
@@ -117,19 +202,17 @@ impl LiteralValue {
 impl Callable for LiteralValue {
     fn arity(&self) -> usize {
         match self {
-            LiteralValue::FunctionVal(stmt) => {
-                let stmt_non_box = *(stmt.clone());
-                match stmt_non_box {
+            LiteralValue::FunctionVal(stmt, _) => {
+                match &**stmt {
                     Stmt::Function { params, .. } => params.len(),
                     _ => 0
                 }
             },
             LiteralValue::ClassVal(_token, values) => {
-                match values.get("init") {
+                match values.get(&Symbol::intern("init")) {
                     Some(v) => match v {
-                        LiteralValue::FunctionVal(stmt) => {
-                            let stmt_non_box = *(stmt.clone());
-                            match stmt_non_box {
+                        LiteralValue::FunctionVal(stmt, _) => {
+                            match &**stmt {
                                 Stmt::Function { params, .. } => params.len(),
                                 _ => 0
                             }
@@ -139,82 +222,195 @@ impl Callable for LiteralValue {
                     None => 0
                 }
             },
+            LiteralValue::VariantCtorVal(_, _, arity) => *arity,
+            LiteralValue::NativeVal(_, arity, ..) => *arity,
             _ => 0
         }
     }
 
     fn call(&self, interpreter: &mut crate::interpreter::interpreter::Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
         match self {
-            LiteralValue::CallableVal(name, ..) => {
-                match interpreter.environment.get(name) {
-                    Ok(v) => {
-                        match v {
-                            LiteralValue::FunctionVal(stmt) => call_function_val(interpreter, &stmt, arguments, LiteralValue::NullVal),
-                            _ => Err("Cannot call non-function.".to_string())
-                        }
-                    },
-                    Err(e) => Err(e)
-                }
-            },
             LiteralValue::InstanceVal(name, values) => {
                 // println!("InstanceVal Call: {:?}", name);
-                match values.get("init") {
+                match values.get(&Symbol::intern("init")) {
                     Some(v) => match v {
-                        LiteralValue::FunctionVal(stmt) => call_function_val(interpreter, &stmt, arguments, self.clone()),
+                        LiteralValue::FunctionVal(stmt, closure) => call_function_val(interpreter, stmt, closure, arguments, self.clone()),
                         _ => Err("Cannot call non-init function.".to_string())
                     }
-                    None => Err("Cannot call non-init function.".to_string())
-                }.expect("Error calling init function.");
+                    None => Ok(LiteralValue::NullVal)
+                }?;
+                interpreter.allocate(0)?;
                 Ok(LiteralValue::InstanceVal(name.clone(), values.clone()))
+            },
+            LiteralValue::VariantCtorVal(enum_name, variant, _) => {
+                if arguments.len() != self.arity() {
+                    return Err(format!("Variant '{}' expects {} field(s) but got {}.", variant, self.arity(), arguments.len()));
+                }
+                interpreter.allocate(0)?;
+                Ok(LiteralValue::VariantVal(enum_name.clone(), variant.clone(), arguments.into()))
+            },
+            LiteralValue::NativeVal(name, arity, function) => {
+                if arguments.len() != *arity {
+                    return Err(format!("Expected {} arguments but got {}.", arity, arguments.len()));
+                }
+                interpreter.enter_function(Frame { function: name.clone(), line: interpreter.line() });
+                let result = function(interpreter, arguments);
+                interpreter.exit_function(result.is_ok());
+                if let Ok(LiteralValue::StringVal(s)) = &result {
+                    interpreter.allocate(s.len())?;
+                }
+                result
             }
             _ => Err("Cannot call non-function.".to_string())
         }
     }
 }
 
-pub fn call_function_val (interpreter: &mut crate::interpreter::interpreter::Interpreter, stmt: &Stmt, arguments: Vec<LiteralValue>, instance_value: LiteralValue) -> Result<LiteralValue, String> {
+// Calls a function in a new scope inside `closure`, the scope it was declared in.
+// Functions it tail calls run here one after another rather than inside it.
+pub fn call_function_val (interpreter: &mut crate::interpreter::interpreter::Interpreter, stmt: &Stmt, closure: &Rc<RefCell<Environment>>, arguments: Vec<LiteralValue>, instance_value: LiteralValue) -> Result<LiteralValue, String> {
+    let mut completion = run_function(interpreter, stmt, closure, arguments, instance_value)?;
+    loop {
+        match completion {
+            Completion::Return(value) => return Ok(value),
+            Completion::TailCall(stmt, closure, arguments) => {
+                completion = run_function(interpreter, &stmt, &closure, arguments, LiteralValue::NullVal)?;
+            }
+        }
+    }
+}
+
+// Runs one function's body and returns how it finished.
+fn run_function(interpreter: &mut crate::interpreter::interpreter::Interpreter, stmt: &Stmt, closure: &Rc<RefCell<Environment>>, arguments: Vec<LiteralValue>, instance_value: LiteralValue) -> Result<Completion, String> {
     match stmt {
-        Stmt::Function { params, body, .. } => {
-            let environment_copy = interpreter.environment.clone();
-            let mut environment = interpreter.environment.clone();
+        Stmt::Function { name, params, body, .. } => {
+            if arguments.len() != params.len() {
+                return Err(format!("Expected {} arguments but got {}.", params.len(), arguments.len()));
+            }
+            let mut environment = Environment::new_with_enclosing(closure.clone());
             match instance_value {
-                LiteralValue::InstanceVal(_, _) => { environment.define("this".to_string(), instance_value.clone()).expect("Error defining 'this' variable."); },
+                LiteralValue::InstanceVal(_, _) => { environment.define(Symbol::intern("this"), instance_value.clone()).expect("Error defining 'this' variable."); },
                 _ => ()
             }
 
             for (i, param) in params.iter().enumerate() {
-                environment.define(param.lexeme.clone(), arguments[i].clone()).expect("Error defining function parameter.");
+                environment.define(param.lexeme.symbol(), arguments[i].clone()).expect("Error defining function parameter.");
+            }
+            // The script's own frame is not a call.
+            if interpreter.call_stack.len() > interpreter.max_call_depth {
+                return Err(stack_overflow(interpreter.max_call_depth, &interpreter.call_stack));
             }
-            interpreter.environment = environment;
-            let result = interpreter.execute_block(body.clone());
-            interpreter.environment = environment_copy;
-            result
+            interpreter.enter_function(Frame { function: name.lexeme.to_string(), line: name.line });
+            let result = interpreter.execute_block(body, environment);
+            interpreter.exit_function(result.is_ok());
+            result?;
+            Ok(interpreter.returning.take().unwrap_or(Completion::Return(LiteralValue::NullVal)))
         },
         _ => Err("Cannot call non-function.".to_string())
     }
 }
 
+// A token's text. Identifiers and keywords are interned so they can name
+// things; the text of every other token, like comments and literals, is kept
+// as it is so that the symbol table only grows with the names in use.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Lexeme {
+    Name(Symbol),
+    Text(Rc<str>)
+}
+
+impl Lexeme {
+    // The text as a symbol, for keying a map with a name.
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            Lexeme::Name(symbol) => symbol.clone(),
+            Lexeme::Text(text) => Symbol::intern(text)
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Lexeme::Name(symbol) => symbol.as_str(),
+            Lexeme::Text(text) => text
+        }
+    }
+}
+
+impl std::ops::Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for Lexeme {
+    fn eq(&self, other: &Lexeme) -> bool {
+        match (self, other) {
+            (Lexeme::Name(a), Lexeme::Name(b)) => a == b,
+            _ => self.as_str() == other.as_str()
+        }
+    }
+}
+
+impl PartialEq<str> for Lexeme {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Lexeme {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl std::fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct Token {
     pub kind: TokenType,
-    pub lexeme: String,
+    pub lexeme: Lexeme,
     pub line: usize,
+    // Characters between the start of the line and the token, counting from 0.
+    pub column: usize,
     pub literal: Option<LiteralValue>
 }
 
 impl Token {
-    pub fn new(kind: TokenType, lexeme: String, line: usize, literal: Option<LiteralValue>) -> Token {
+    pub fn new(kind: TokenType, lexeme: &str, line: usize, column: usize, literal: Option<LiteralValue>) -> Token {
+        let lexeme = if kind == TokenType::IDENTIFIER || keyword_name(&kind).is_some() {
+            Lexeme::Name(Symbol::intern(lexeme))
+        } else {
+            Lexeme::Text(Rc::from(lexeme))
+        };
         Token {
             kind,
             lexeme,
             line,
+            column,
             literal
         }
     }
 
+    // The name the token declares or refers to. Dialects may spell `this`
+    // differently, but it is always bound as "this".
+    pub fn name(&self) -> Symbol {
+        match self.kind {
+            TokenType::THIS => Symbol::intern("this"),
+            _ => self.lexeme.symbol()
+        }
+    }
+
     pub fn to_string(&self) -> String {
-        format!("{:?} {} {}", self.kind, self.lexeme, self.literal.as_ref().unwrap().to_string())
+        let literal = self.literal.as_ref().map(|l| l.to_string()).unwrap_or("nil".to_string());
+        format!("{:?} {} {}", self.kind, self.lexeme, literal)
     }
 
     pub fn print(&self) {
@@ -230,22 +426,24 @@ impl Token {
             kind: self.kind.clone(),
             lexeme: self.lexeme.clone(),
             line: self.line,
+            column: self.column,
             literal: self.literal.clone()
         }
     }
 }
 
 #[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq)]
 pub enum TokenType {
     LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE,
-    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR,
+    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR, COLON,
 
     // One or two character tokens.
-    BANG, BANG_EQUAL,
-    EQUAL, EQUAL_EQUAL,
+    BANG, BANG_EQUAL, ARROW,
+    EQUAL, EQUAL_EQUAL, FAT_ARROW,
     GREATER, GREATER_EQUAL,
     LESS, LESS_EQUAL,
 
@@ -253,8 +451,11 @@ pub enum TokenType {
     IDENTIFIER, STRING, NUMBER,
 
     // Keywords.
-    AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
+    AND, CLASS, CONST, ELSE, ENUM, FALSE, FUN, FOR, IF, MATCH, NIL, OR,
     PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE,
 
+    // Trivia, only produced by `Lexer::scan_tokens_with_trivia`.
+    WHITESPACE, NEWLINE, COMMENT,
+
     EOF
 }
\ No newline at end of file
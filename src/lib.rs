@@ -0,0 +1,4 @@
+// What the `pepega` binary shares with other programs, like its integration
+// tests: the JSON reader and writer and the message framing the language and
+// debug servers speak.
+pub mod util;
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use crate::interpreter::resolver::Resolver;
+use crate::lexer::dialect::Dialect;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::token::{Token, TokenType};
+use crate::parser::expr::Expr;
+use crate::parser::parser::Parser;
+use crate::parser::stmt::{Pattern, Stmt};
+
+// Everything the language server knows about one open document. Positions are
+// 1-based lines and 0-based character columns, like `Token`.
+
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub message: String
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Constant,
+    Parameter,
+    Function,
+    Class,
+    Method,
+    Enum,
+    Variant
+}
+
+pub struct Declaration {
+    pub name: Token,
+    pub kind: SymbolKind,
+    // How the declaration reads in source, e.g. `pog add(a, b)`.
+    pub detail: String,
+    // The class or enum a method or variant belongs to.
+    pub parent: Option<usize>,
+    pub top_level: bool
+}
+
+pub struct Analysis {
+    pub source: String,
+    // Every token including comments, in source order.
+    pub tokens: Vec<Token>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub declarations: Vec<Declaration>,
+    // (line, column) of an identifier mapped to the declaration it names.
+    pub references: HashMap<(usize, usize), usize>
+}
+
+impl Analysis {
+    pub fn new(source: String, dialect: &Dialect) -> Analysis {
+        let mut lexer = Lexer::new_with_dialect(source.clone(), dialect);
+        lexer.collect_errors();
+        lexer.scan_tokens_with_trivia();
+        let mut diagnostics: Vec<Diagnostic> = lexer.errors.iter()
+            .map(|e| Diagnostic { line: e.line, column: e.column, length: 1, message: e.message.clone() })
+            .collect();
+        let significant: Vec<Token> = lexer.tokens.iter()
+            .filter(|t| !matches!(t.kind, TokenType::WHITESPACE | TokenType::NEWLINE | TokenType::COMMENT))
+            .cloned()
+            .collect();
+        let mut parser = Parser::new(significant);
+        parser.collect_errors();
+        let statements = parser.parse();
+        let mut errors = parser.parse_errors;
+        if errors.is_empty() {
+            let mut resolver = Resolver::new();
+            resolver.collect_errors();
+            resolver.resolve(&statements);
+            errors = resolver.errors;
+        }
+        for e in errors {
+            let length = e.token.lexeme.chars().count().max(1);
+            diagnostics.push(Diagnostic { line: e.token.line, column: e.token.column, length, message: e.describe() });
+        }
+        let mut walker = Walker {
+            declarations: Vec::new(),
+            references: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            unresolved: Vec::new(),
+            dialect
+        };
+        walker.statements(&statements);
+        walker.resolve_globals();
+        Analysis {
+            source,
+            tokens: lexer.tokens,
+            diagnostics,
+            declarations: walker.declarations,
+            references: walker.references
+        }
+    }
+
+    pub fn token_at(&self, line: usize, column: usize) -> Option<&Token> {
+        self.tokens.iter().find(|t| {
+            t.line == line && t.column <= column && column < t.column + t.lexeme.chars().count().max(1)
+                && !matches!(t.kind, TokenType::WHITESPACE | TokenType::NEWLINE)
+        })
+    }
+
+    // The declaration an identifier token refers to or declares.
+    pub fn declaration_of(&self, token: &Token) -> Option<&Declaration> {
+        self.references.get(&(token.line, token.column)).map(|i| &self.declarations[*i])
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        self.source.split('\n').nth(line.wrapping_sub(1)).unwrap_or("")
+    }
+
+    // LSP counts columns in UTF-16 code units.
+    pub fn utf16_column(&self, line: usize, column: usize) -> usize {
+        self.line_text(line).chars().take(column).map(|c| c.len_utf16()).sum()
+    }
+
+    pub fn char_column(&self, line: usize, utf16_column: usize) -> usize {
+        let mut units = 0;
+        for (i, c) in self.line_text(line).chars().enumerate() {
+            if units >= utf16_column {
+                return i;
+            }
+            units += c.len_utf16();
+        }
+        self.line_text(line).chars().count()
+    }
+}
+
+// Walks the tree with the same scoping rules as the resolver, recording every
+// declaration and which declaration each variable refers to.
+struct Walker<'a> {
+    declarations: Vec<Declaration>,
+    references: HashMap<(usize, usize), usize>,
+    scopes: Vec<HashMap<String, usize>>,
+    // Names not found when they were used; they may be globals declared
+    // further down, such as a function called before its declaration.
+    unresolved: Vec<Token>,
+    dialect: &'a Dialect
+}
+
+impl Walker<'_> {
+    fn keyword(&self, kind: TokenType) -> &str {
+        self.dialect.spelling(&kind).unwrap_or("")
+    }
+
+    fn declare(&mut self, name: &Token, kind: SymbolKind, detail: String, parent: Option<usize>) -> usize {
+        let index = self.declarations.len();
+        let top_level = self.scopes.len() == 1 && parent.is_none();
+        self.declarations.push(Declaration { name: name.clone(), kind, detail, parent, top_level });
+        self.references.insert((name.line, name.column), index);
+        if parent.is_none() {
+            self.scopes.last_mut().unwrap().insert(name.lexeme.to_string(), index);
+        }
+        index
+    }
+
+    fn reference(&mut self, name: &Token) -> Option<usize> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(index) = scope.get(name.lexeme.as_str()) {
+                self.references.insert((name.line, name.column), *index);
+                return Some(*index);
+            }
+        }
+        self.unresolved.push(name.clone());
+        None
+    }
+
+    fn resolve_globals(&mut self) {
+        for name in std::mem::take(&mut self.unresolved) {
+            if let Some(index) = self.scopes[0].get(name.lexeme.as_str()) {
+                self.references.insert((name.line, name.column), *index);
+            }
+        }
+    }
+
+    fn variant(&mut self, enum_index: usize, name: &Token) {
+        let found = self.declarations.iter()
+            .position(|d| d.parent == Some(enum_index) && d.name.lexeme == name.lexeme);
+        if let Some(index) = found {
+            self.references.insert((name.line, name.column), index);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for s in statements {
+            self.stmt(s);
+        }
+    }
+
+    fn function(&mut self, stmt: &Stmt, kind: SymbolKind, parent: Option<usize>) {
+        if let Stmt::Function { name, params, body, .. } = stmt {
+            let names: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+            let detail = match kind {
+                SymbolKind::Method => format!("{}({})", name.lexeme, names.join(", ")),
+                _ => format!("{} {}({})", self.keyword(TokenType::FUN), name.lexeme, names.join(", "))
+            };
+            self.declare(name, kind, detail, parent);
+            self.scopes.push(HashMap::new());
+            for p in params {
+                self.declare(p, SymbolKind::Parameter, p.lexeme.to_string(), None);
+            }
+            self.statements(body);
+            self.scopes.pop();
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.scopes.push(HashMap::new());
+                self.statements(statements);
+                self.scopes.pop();
+            },
+            Stmt::Class { name, methods } => {
+                let detail = format!("{} {}", self.keyword(TokenType::CLASS), name.lexeme);
+                let class = self.declare(name, SymbolKind::Class, detail, None);
+                for m in methods {
+                    self.function(m, SymbolKind::Method, Some(class));
+                }
+            },
+            Stmt::Const { name, initializer, .. } => {
+                self.expr(initializer);
+                let detail = format!("{} {}", self.keyword(TokenType::CONST), name.lexeme);
+                self.declare(name, SymbolKind::Constant, detail, None);
+            },
+            Stmt::Enum { name, variants } => {
+                let detail = format!("{} {}", self.keyword(TokenType::ENUM), name.lexeme);
+                let e = self.declare(name, SymbolKind::Enum, detail, None);
+                for (variant, fields) in variants {
+                    let detail = if fields.is_empty() {
+                        format!("{}.{}", name.lexeme, variant.lexeme)
+                    } else {
+                        let fields: Vec<&str> = fields.iter().map(|f| f.lexeme.as_str()).collect();
+                        format!("{}.{}({})", name.lexeme, variant.lexeme, fields.join(", "))
+                    };
+                    self.declare(variant, SymbolKind::Variant, detail, Some(e));
+                }
+            },
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => self.expr(expression),
+            Stmt::Function { .. } => self.function(stmt, SymbolKind::Function, None),
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            },
+            Stmt::Match { subject, arms, .. } => {
+                self.expr(subject);
+                for (pattern, body) in arms {
+                    self.scopes.push(HashMap::new());
+                    if let Pattern::Variant { enum_name, name, bindings } = pattern {
+                        if let Some(enum_name) = enum_name {
+                            if let Some(e) = self.reference(enum_name) {
+                                self.variant(e, name);
+                            }
+                        }
+                        for b in bindings {
+                            self.declare(b, SymbolKind::Variable, b.lexeme.to_string(), None);
+                        }
+                    }
+                    self.stmt(body);
+                    self.scopes.pop();
+                }
+            },
+            Stmt::Return { value, .. } => {
+                if let Some(v) = value {
+                    self.expr(v);
+                }
+            },
+            Stmt::While { condition, body, .. } => {
+                self.expr(condition);
+                self.stmt(body);
+            },
+            Stmt::Var { name, initializer, .. } => {
+                if let Some(i) = initializer {
+                    self.expr(i);
+                }
+                let detail = format!("{} {}", self.keyword(TokenType::VAR), name.lexeme);
+                self.declare(name, SymbolKind::Variable, detail, None);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.expr(value);
+                self.reference(name);
+            },
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Call { callee, arguments, .. } => {
+                self.expr(callee);
+                for a in arguments {
+                    self.expr(a);
+                }
+            },
+            Expr::Get { object, name } => {
+                self.expr(object);
+                if let Expr::Variable { name: object, .. } = &**object {
+                    let e = self.references.get(&(object.line, object.column)).copied();
+                    if let Some(e) = e.filter(|e| self.declarations[*e].kind == SymbolKind::Enum) {
+                        self.variant(e, name);
+                    }
+                }
+            },
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Set { object, value, .. } => {
+                self.expr(object);
+                self.expr(value);
+            },
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name, .. } => {
+                self.reference(name);
+            },
+            Expr::Literal { .. } | Expr::This { .. } => ()
+        }
+    }
+}
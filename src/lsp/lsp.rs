@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use crate::lexer::dialect::{keyword_name, Dialect};
+use crate::lexer::token::{Token, TokenType};
+use crate::lsp::analysis::{Analysis, SymbolKind};
+use crate::util::json::Json;
+use crate::util::rpc::{read_message, write_message};
+
+const TOKEN_TYPES: [&str; 12] = [
+    "keyword", "variable", "parameter", "function", "method", "class",
+    "enum", "enumMember", "string", "number", "comment", "operator"
+];
+
+// A language server speaking LSP over stdin/stdout. Documents are synced in
+// full and re-analysed on every change.
+pub struct LanguageServer {
+    dialect: Dialect,
+    documents: HashMap<String, Analysis>,
+    shutting_down: bool
+}
+
+impl LanguageServer {
+    pub fn new(dialect: Dialect) -> LanguageServer {
+        LanguageServer {
+            dialect,
+            documents: HashMap::new(),
+            shutting_down: false
+        }
+    }
+
+    // Serves until the client sends `exit`, closes stdin or stops reading,
+    // returning the process exit code.
+    pub fn run(&mut self) -> i32 {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        let stdout = io::stdout();
+        let mut output = stdout.lock();
+        while let Some(message) = read_message(&mut input) {
+            let message = match message {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Ignoring malformed message: {}", e);
+                    continue;
+                }
+            };
+            let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+            if method == "exit" {
+                return if self.shutting_down { 0 } else { 1 };
+            }
+            let params = message.get("params").cloned().unwrap_or(Json::Null);
+            let written = match message.get("id") {
+                Some(id) => {
+                    let response = match self.request(&method, &params) {
+                        Ok(result) => Json::object(vec![
+                            ("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)
+                        ]),
+                        Err((code, error)) => Json::object(vec![
+                            ("jsonrpc", "2.0".into()), ("id", id.clone()),
+                            ("error", Json::object(vec![("code", Json::Number(code)), ("message", error.into())]))
+                        ])
+                    };
+                    write_message(&mut output, &response)
+                },
+                None => self.notification(&method, &params, &mut output)
+            };
+            if written.is_err() {
+                return 1;
+            }
+        }
+        1
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (f64, String)> {
+        match method {
+            "initialize" => Ok(self.initialize()),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Json::Null)
+            },
+            "textDocument/hover" => Ok(self.at_position(params, hover)),
+            "textDocument/definition" => {
+                let uri = uri(params);
+                Ok(self.at_position(params, |analysis, token| {
+                    let declaration = analysis.declaration_of(token)?;
+                    Some(Json::object(vec![("uri", uri.as_str().into()), ("range", range(analysis, &declaration.name))]))
+                }))
+            },
+            "textDocument/documentSymbol" => Ok(self.document(params).map(document_symbols).unwrap_or(Json::Null)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/semanticTokens/full" => {
+                Ok(self.document(params).map(semantic_tokens).unwrap_or(Json::Null))
+            },
+            _ => Err((-32601.0, format!("Method '{}' is not supported.", method)))
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json, output: &mut impl Write) -> io::Result<()> {
+        let uri = uri(params);
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|d| d.get("text")).and_then(|t| t.as_str());
+                self.update(uri, text.unwrap_or(""), output)
+            },
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(|c| c.as_array());
+                let text = changes.and_then(|c| c.last()).and_then(|c| c.get("text")).and_then(|t| t.as_str());
+                match text {
+                    Some(text) => self.update(uri, text, output),
+                    None => Ok(())
+                }
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                publish_diagnostics(output, &uri, Json::from(Vec::new()))
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn initialize(&self) -> Json {
+        let legend = Json::object(vec![
+            ("tokenTypes", TOKEN_TYPES.iter().map(|t| Json::from(*t)).collect::<Vec<Json>>().into()),
+            ("tokenModifiers", Json::from(Vec::new()))
+        ]);
+        Json::object(vec![
+            ("capabilities", Json::object(vec![
+                ("textDocumentSync", 1usize.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+                ("completionProvider", Json::object(vec![])),
+                ("semanticTokensProvider", Json::object(vec![("legend", legend), ("full", true.into())]))
+            ])),
+            ("serverInfo", Json::object(vec![("name", "pepega".into()), ("version", "0.1.0".into())]))
+        ])
+    }
+
+    fn update(&mut self, uri: String, text: &str, output: &mut impl Write) -> io::Result<()> {
+        let analysis = Analysis::new(text.to_string(), &self.dialect);
+        let diagnostics: Vec<Json> = analysis.diagnostics.iter().map(|d| {
+            let start = analysis.utf16_column(d.line, d.column);
+            let end = analysis.utf16_column(d.line, d.column + d.length);
+            Json::object(vec![
+                ("range", span(d.line, start, end)),
+                ("severity", 1usize.into()),
+                ("source", "pepega".into()),
+                ("message", d.message.clone().into())
+            ])
+        }).collect();
+        self.documents.insert(uri.clone(), analysis);
+        publish_diagnostics(output, &uri, diagnostics.into())
+    }
+
+    fn document(&self, params: &Json) -> Option<&Analysis> {
+        self.documents.get(&uri(params))
+    }
+
+    // Finds the token under `params.position` and hands it to `f`.
+    fn at_position(&self, params: &Json, f: impl Fn(&Analysis, &Token) -> Option<Json>) -> Json {
+        let analysis = match self.document(params) {
+            Some(a) => a,
+            None => return Json::Null
+        };
+        let position = params.get("position");
+        let line = position.and_then(|p| p.get("line")).and_then(|l| l.as_f64()).unwrap_or(0.0) as usize + 1;
+        let character = position.and_then(|p| p.get("character")).and_then(|c| c.as_f64()).unwrap_or(0.0) as usize;
+        let column = analysis.char_column(line, character);
+        analysis.token_at(line, column).and_then(|t| f(analysis, t)).unwrap_or(Json::Null)
+    }
+
+    fn completion(&self, params: &Json) -> Json {
+        let mut items: Vec<Json> = Vec::new();
+        let mut keywords: Vec<(&String, &TokenType)> = self.dialect.keywords().iter().collect();
+        keywords.sort_by(|a, b| a.0.cmp(b.0));
+        for (spelling, kind) in keywords {
+            items.push(Json::object(vec![
+                ("label", spelling.clone().into()),
+                ("kind", 14usize.into()),
+                ("detail", keyword_name(kind).unwrap_or("").into())
+            ]));
+        }
+        if let Some(analysis) = self.document(params) {
+            let mut seen: Vec<&str> = Vec::new();
+            for d in analysis.declarations.iter().filter(|d| d.top_level) {
+                if seen.contains(&d.name.lexeme.as_str()) {
+                    continue;
+                }
+                seen.push(&d.name.lexeme);
+                let kind: usize = match d.kind {
+                    SymbolKind::Function => 3,
+                    SymbolKind::Class => 7,
+                    SymbolKind::Enum => 13,
+                    SymbolKind::Constant => 21,
+                    _ => 6
+                };
+                items.push(Json::object(vec![
+                    ("label", d.name.lexeme.to_string().into()),
+                    ("kind", kind.into()),
+                    ("detail", d.detail.clone().into())
+                ]));
+            }
+        }
+        items.into()
+    }
+}
+
+fn uri(params: &Json) -> String {
+    params.get("textDocument").and_then(|d| d.get("uri")).and_then(|u| u.as_str()).unwrap_or("").to_string()
+}
+
+fn publish_diagnostics(output: &mut impl Write, uri: &str, diagnostics: Json) -> io::Result<()> {
+    write_message(output, &Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        ("params", Json::object(vec![("uri", uri.into()), ("diagnostics", diagnostics)]))
+    ]))
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![("line", (line - 1).into()), ("character", character.into())])
+}
+
+fn span(line: usize, start: usize, end: usize) -> Json {
+    Json::object(vec![("start", position(line, start)), ("end", position(line, end))])
+}
+
+fn range(analysis: &Analysis, token: &Token) -> Json {
+    let start = analysis.utf16_column(token.line, token.column);
+    let end = analysis.utf16_column(token.line, token.column + token.lexeme.chars().count());
+    span(token.line, start, end)
+}
+
+fn hover(analysis: &Analysis, token: &Token) -> Option<Json> {
+    let text = match keyword_name(&token.kind) {
+        Some(name) => format!("`{}` is the `{}` keyword.", token.lexeme, name),
+        None => format!("```pepega\n{}\n```", analysis.declaration_of(token)?.detail)
+    };
+    Some(Json::object(vec![
+        ("contents", Json::object(vec![("kind", "markdown".into()), ("value", text.into())])),
+        ("range", range(analysis, token))
+    ]))
+}
+
+fn document_symbols(analysis: &Analysis) -> Json {
+    let symbol = |index: usize, children: Vec<Json>| {
+        let d = &analysis.declarations[index];
+        let kind: usize = match d.kind {
+            SymbolKind::Class => 5,
+            SymbolKind::Method => 6,
+            SymbolKind::Enum => 10,
+            SymbolKind::Function => 12,
+            SymbolKind::Constant => 14,
+            SymbolKind::Variant => 22,
+            _ => 13
+        };
+        Json::object(vec![
+            ("name", d.name.lexeme.to_string().into()),
+            ("detail", d.detail.clone().into()),
+            ("kind", kind.into()),
+            ("range", range(analysis, &d.name)),
+            ("selectionRange", range(analysis, &d.name)),
+            ("children", children.into())
+        ])
+    };
+    let mut symbols: Vec<Json> = Vec::new();
+    for (i, d) in analysis.declarations.iter().enumerate() {
+        if !d.top_level {
+            continue;
+        }
+        let children: Vec<Json> = analysis.declarations.iter().enumerate()
+            .filter(|(_, c)| c.parent == Some(i))
+            .map(|(j, _)| symbol(j, Vec::new()))
+            .collect();
+        symbols.push(symbol(i, children));
+    }
+    symbols.into()
+}
+
+fn semantic_tokens(analysis: &Analysis) -> Json {
+    let mut data: Vec<Json> = Vec::new();
+    let (mut last_line, mut last_start) = (1, 0);
+    for token in &analysis.tokens {
+        let token_type = match token.kind {
+            _ if keyword_name(&token.kind).is_some() => 0,
+            TokenType::IDENTIFIER => match analysis.declaration_of(token).map(|d| d.kind) {
+                Some(SymbolKind::Parameter) => 2,
+                Some(SymbolKind::Function) => 3,
+                Some(SymbolKind::Method) => 4,
+                Some(SymbolKind::Class) => 5,
+                Some(SymbolKind::Enum) => 6,
+                Some(SymbolKind::Variant) => 7,
+                _ => 1
+            },
+            TokenType::STRING => 8,
+            TokenType::NUMBER => 9,
+            TokenType::COMMENT => 10,
+            TokenType::MINUS | TokenType::PLUS | TokenType::SLASH | TokenType::STAR | TokenType::BANG |
+            TokenType::BANG_EQUAL | TokenType::EQUAL | TokenType::EQUAL_EQUAL | TokenType::GREATER |
+            TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => 11,
+            _ => continue
+        };
+        // Tokens may not span lines, so multi-line strings are left out.
+        if token.lexeme.contains('\n') {
+            continue;
+        }
+        let start = analysis.utf16_column(token.line, token.column);
+        let length: usize = token.lexeme.chars().map(|c| c.len_utf16()).sum();
+        let delta_line = token.line - last_line;
+        let delta_start = if delta_line == 0 { start - last_start } else { start };
+        for value in [delta_line, delta_start, length, token_type, 0] {
+            data.push(value.into());
+        }
+        last_line = token.line;
+        last_start = start;
+    }
+    Json::object(vec![("data", data.into())])
+}
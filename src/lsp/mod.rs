@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod lsp;
+pub mod analysis;
\ No newline at end of file
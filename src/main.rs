@@ -1,19 +1,64 @@
 mod lexer;
 mod parser;
 mod interpreter;
+mod checker;
+mod tools;
+mod lsp;
+mod debugger;
+mod vm;
+mod optimizer;
 
+use std::cell::RefCell;
 use std::env::args;
 use std::fs;
+use std::panic;
 use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use pepega::util;
+use crate::lexer::dialect::Dialect;
 use crate::lexer::token::Token;
 use crate::parser::parser::Parser;
-use crate::interpreter::interpreter::Interpreter;
+use crate::parser::frontend::parse_program;
+use crate::parser::stmt::Stmt;
+use crate::interpreter::budget::Budget;
+use crate::interpreter::interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+use crate::interpreter::options::RunOptions;
+use crate::checker::checker::Checker;
+use crate::tools::translate::translate;
+use crate::tools::formatter::format_source;
+use crate::tools::profiler::Profiler;
+use crate::tools::coverage::Coverage;
+use crate::tools::testing::run_tests;
+use crate::util::json::Json;
+use crate::lsp::lsp::LanguageServer;
+use crate::debugger::debugger::Debugger;
+use crate::debugger::dap::DapServer;
+use crate::vm::compiler::Compiler;
+use crate::vm::vm::Vm;
+use crate::optimizer::optimizer::{OptLevel, Optimizer};
 
-fn run_prompt() {
+// Which engine runs scripts: the tree-walking interpreter or the bytecode VM.
+#[derive(Clone, Copy, PartialEq)]
+enum Backend {
+    Tree,
+    Vm
+}
+
+// Host stack set aside for each nested call the interpreter allows, a few
+// times what a call takes. Calls recurse through the tree-walker's Rust
+// functions, which take far more stack in unoptimized builds.
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) { 128 * 1024 } else { 16 * 1024 };
+
+fn run_prompt(dialect: &Dialect, backend: Backend, opt_level: OptLevel, options: &RunOptions) {
     loop {
         let mut input = String::new();
         std::io::stdin().read_line(&mut input).unwrap();
-        run(input).expect("Cannot run input.");
+        let result = parse_program(input, dialect, opt_level).and_then(|statements| run(statements, backend, options));
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
     }
 }
 
@@ -34,31 +79,406 @@ pub fn report(line: i32, where_error: String, message: String) {
     panic!("Error");
 }
 
-fn run(contents: String) -> Result<(), String>{
-    let mut lexer = lexer::lexer::Lexer::new(contents);
+fn run(statements: Vec<Stmt>, backend: Backend, options: &RunOptions) -> Result<(), String>{
+    if backend == Backend::Vm {
+        let function = Compiler::compile(&statements)?;
+        let mut vm = Vm::new();
+        vm.max_call_depth = options.max_depth;
+        if !options.sandbox {
+            vm.allow_host_access();
+        }
+        return vm.interpret(function);
+    }
+    let mut interpreter = Interpreter::new();
+    options.apply(&mut interpreter);
+    let result = interpreter.interpret_stmt(&statements);
+    if options.gc_stats {
+        let stats = &interpreter.heap.stats;
+        eprintln!("GC: {} collections, {} of {} scopes freed from cycles.", stats.collections, stats.scopes_freed, stats.scopes_tracked);
+    }
+    result
+}
+
+// Reads the script at `path` and readies it to run, returning its source too.
+// A script that does not compile is reported and never started.
+fn compile_file(path: &str, dialect: &Dialect, opt_level: OptLevel) -> (String, Vec<Stmt>) {
+    let content = fs::read_to_string(path).unwrap();
+    match parse_program(content.clone(), dialect, opt_level) {
+        Ok(statements) => (content, statements),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(65);
+        }
+    }
+}
+
+fn run_file(path: String, dialect: &Dialect, backend: Backend, opt_level: OptLevel, options: &RunOptions) -> Result<(),String> {
+    let (_, statements) = compile_file(&path, dialect, opt_level);
+    run(statements, backend, options)
+}
+
+// Runs a script under the command-line debugger. Without breakpoints it
+// pauses before the first statement.
+fn debug_file(path: String, breakpoints: Vec<usize>, dialect: &Dialect, options: &RunOptions) -> Result<(), String> {
+    let (content, statements) = compile_file(&path, dialect, OptLevel::None);
+    let mut debugger = Debugger::new(&content, dialect.clone());
+    if !breakpoints.is_empty() {
+        debugger.stepper.breakpoints.extend(breakpoints);
+        debugger.stepper.resume(debugger::debugger::Step::Continue, 0);
+    }
+    let mut interpreter = Interpreter::new();
+    options.apply(&mut interpreter);
+    interpreter.hook = Some(Rc::new(RefCell::new(debugger)));
+    let result = interpreter.interpret_stmt(&statements);
+    println!("Program finished.");
+    result
+}
+
+// Runs a script with the profiler attached, prints its report to stderr and
+// writes the folded stacks to `folded_path`.
+fn profile_file(path: String, folded_path: &str, dialect: &Dialect, options: &RunOptions) -> Result<(), String> {
+    let (content, statements) = compile_file(&path, dialect, OptLevel::None);
+    let profiler = Rc::new(RefCell::new(Profiler::new(&content)));
+    let mut interpreter = Interpreter::new();
+    options.apply(&mut interpreter);
+    interpreter.hook = Some(profiler.clone());
+    let result = interpreter.interpret_stmt(&statements);
+    let mut profiler = profiler.borrow_mut();
+    profiler.finish();
+    eprint!("{}", profiler.report());
+    match fs::write(folded_path, profiler.folded_stacks()) {
+        Ok(_) => eprintln!("Folded stacks written to {}.", folded_path),
+        Err(e) => eprintln!("Cannot write '{}': {}", folded_path, e)
+    }
+    result
+}
+
+// Runs a script recording coverage, prints a summary to stderr and writes an
+// LCOV tracefile to `lcov_path`.
+fn coverage_file(path: String, lcov_path: &str, dialect: &Dialect, options: &RunOptions) -> Result<(), String> {
+    let (_, statements) = compile_file(&path, dialect, OptLevel::None);
+    let coverage = Rc::new(RefCell::new(Coverage::new(&statements)));
+    let mut interpreter = Interpreter::new();
+    options.apply(&mut interpreter);
+    interpreter.hook = Some(coverage.clone());
+    let result = interpreter.interpret_stmt(&statements);
+    let coverage = coverage.borrow();
+    eprint!("{}", coverage.summary());
+    let source = fs::canonicalize(&path).map(|p| p.display().to_string()).unwrap_or(path);
+    match fs::write(lcov_path, coverage.lcov(&source)) {
+        Ok(_) => eprintln!("Coverage written to {}.", lcov_path),
+        Err(e) => eprintln!("Cannot write '{}': {}", lcov_path, e)
+    }
+    result
+}
+
+fn translate_file(path: String, from: &Dialect, to: &Dialect) -> bool {
+    let content = fs::read_to_string(path).unwrap();
+    match translate(content, from, to) {
+        Ok(output) => {
+            print!("{}", output);
+            true
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            false
+        }
+    }
+}
+
+// Formats `path` in place, or with `check` only reports whether it would
+// change. Returns whether the file was already formatted, or why it could not
+// be formatted.
+fn format_file(path: &str, check: bool, dialect: &Dialect) -> Result<bool, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path, e))?;
+    let formatted = format_source(content.clone(), dialect).map_err(|e| format!("Cannot format '{}':\n{}", path, e))?;
+    if formatted == content {
+        return Ok(true);
+    }
+    if check {
+        println!("Would reformat: {}", path);
+    } else {
+        fs::write(path, formatted).map_err(|e| format!("Cannot write '{}': {}", path, e))?;
+    }
+    Ok(false)
+}
+
+fn load_dialect(name_or_path: &str) -> Dialect {
+    Dialect::load(name_or_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(64);
+    })
+}
+
+// Type checks `path` and prints what it finds. A script that does not parse
+// is reported and not checked.
+fn check_file(path: String, strict: bool, dialect: &Dialect) -> bool {
+    let content = fs::read_to_string(path).unwrap();
+    let mut lexer = lexer::lexer::Lexer::new_with_dialect(content, dialect);
+    lexer.collect_errors();
     lexer.scan_tokens();
+    if !lexer.errors.is_empty() {
+        for e in &lexer.errors {
+            eprintln!("[line {}] Error: {}", e.line, e.message);
+        }
+        return false;
+    }
     let mut parser = Parser::new(lexer.tokens);
-    let expr = parser.parse();
-    let mut interpreter = Interpreter::new();
-    interpreter.interpret_stmt(expr);
-    Ok(())
+    parser.collect_errors();
+    let statements = parser.parse();
+    if !parser.parse_errors.is_empty() {
+        for e in &parser.parse_errors {
+            eprintln!("[line {}] {}", e.token.line, e.describe());
+        }
+        return false;
+    }
+    let mut checker = Checker::new();
+    checker.check(&statements);
+    for d in &checker.diagnostics {
+        eprintln!("{}", d);
+    }
+    !checker.has_errors(strict)
 }
 
-fn run_file(path: String) -> Result<(),String> {
+// Prints the front-end's view of a script instead of running it. The tree is
+// printed after optimizing it at `opt_level`.
+fn dump_file(path: String, tokens: bool, ast: bool, ast_json: bool, dialect: &Dialect, opt_level: OptLevel) {
     let content = fs::read_to_string(path).unwrap();
-    run(content)
+    let mut lexer = lexer::lexer::Lexer::new_with_dialect(content, dialect);
+    lexer.scan_tokens();
+    if tokens {
+        for token in &lexer.tokens {
+            token.print();
+        }
+    }
+    if !ast && !ast_json {
+        return;
+    }
+    let mut parser = Parser::new(lexer.tokens);
+    let statements = Optimizer::new(opt_level).optimize(parser.parse());
+    if ast {
+        for s in &statements {
+            println!("{}", s);
+        }
+    }
+    if ast_json {
+        let statements: Vec<Json> = statements.iter().map(|s| s.to_json()).collect();
+        println!("{}", Json::from(statements));
+    }
 }
 
+// Removes `flag` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        },
+        None => false
+    }
+}
+
+// Removes `option` and the value following it from `args`.
+fn take_option(args: &mut Vec<String>, option: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == option)?;
+    if i + 1 >= args.len() {
+        eprintln!("Missing value for {}.", option);
+        process::exit(64);
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+// Removes `option` and the count following it from `args`.
+fn take_limit(args: &mut Vec<String>, option: &str) -> Option<u64> {
+    let value = take_option(args, option)?;
+    match value.parse::<u64>() {
+        Ok(limit) => Some(limit),
+        Err(_) => {
+            eprintln!("Invalid value '{}' for {}.", value, option);
+            process::exit(64);
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = args().collect();
-    if args.len() > 2 {
-        println!("Usage: pepega [script]");
+    let mut args: Vec<String> = args().skip(1).collect();
+    let max_depth = match take_option(&mut args, "--max-depth") {
+        Some(depth) => match depth.parse::<usize>() {
+            Ok(d) if d > 0 => d,
+            _ => {
+                eprintln!("Invalid call depth '{}'.", depth);
+                process::exit(64);
+            }
+        },
+        None => DEFAULT_MAX_CALL_DEPTH
+    };
+    // Scripts run on a thread with room for `max_depth` nested calls, so
+    // they hit the limit before the host stack runs out.
+    let stack_size = STACK_PER_CALL.saturating_mul(max_depth).saturating_add(8 * 1024 * 1024);
+    let cli = thread::Builder::new().stack_size(stack_size).spawn(move || cli(args, max_depth)).unwrap_or_else(|e| {
+        eprintln!("Cannot allow {} nested calls: {}", max_depth, e);
+        process::exit(64);
+    });
+    if let Err(e) = cli.join() {
+        panic::resume_unwind(e);
+    }
+}
+
+fn cli(mut args: Vec<String>, max_depth: usize) {
+    let dialect = match take_option(&mut args, "--dialect") {
+        Some(d) => load_dialect(&d),
+        None => Dialect::pepega()
+    };
+    let tokens = take_flag(&mut args, "--tokens");
+    let ast = take_flag(&mut args, "--ast");
+    let ast_json = take_flag(&mut args, "--ast-json");
+    let backend = match take_option(&mut args, "--backend").as_deref() {
+        None | Some("tree") => Backend::Tree,
+        Some("vm") => Backend::Vm,
+        Some(other) => {
+            eprintln!("Unknown backend '{}'. Expected 'tree' or 'vm'.", other);
+            process::exit(64);
+        }
+    };
+    let opt_level = match take_option(&mut args, "--opt-level") {
+        Some(level) => OptLevel::parse(&level).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(64);
+        }),
+        None => OptLevel::None
+    };
+    let budget = Budget {
+        max_steps: take_limit(&mut args, "--max-steps"),
+        max_duration: take_limit(&mut args, "--timeout").map(Duration::from_millis),
+        max_values: take_limit(&mut args, "--max-values"),
+        max_string_bytes: take_limit(&mut args, "--max-string-bytes"),
+        max_output_bytes: take_limit(&mut args, "--max-output"),
+        ..Budget::default()
+    };
+    let limited = budget.max_steps.is_some() || budget.max_duration.is_some() || budget.max_values.is_some()
+        || budget.max_string_bytes.is_some() || budget.max_output_bytes.is_some();
+    if limited && backend == Backend::Vm {
+        eprintln!("Budgets are only enforced by the tree backend.");
+        process::exit(64);
+    }
+    let options = RunOptions {
+        max_depth,
+        budget: if limited { Some(budget) } else { None },
+        sandbox: take_flag(&mut args, "--sandbox"),
+        gc_stats: take_flag(&mut args, "--gc-stats")
+    };
+    if options.gc_stats && backend == Backend::Vm {
+        eprintln!("--gc-stats is only supported by the tree backend.");
+        process::exit(64);
+    }
+    let profile = take_flag(&mut args, "--profile");
+    let profile_out = take_option(&mut args, "--profile-out").unwrap_or("profile.folded".to_string());
+    if args.first().map(|a| a.as_str()) == Some("translate") {
+        let from = load_dialect(&take_option(&mut args, "--from").unwrap_or("pepega".to_string()));
+        let to = load_dialect(&take_option(&mut args, "--to").unwrap_or("lox".to_string()));
+        if args.len() != 2 {
+            println!("Usage: pepega translate [--from name|file] [--to name|file] [script]");
+            process::exit(64);
+        }
+        if !translate_file(args[1].clone(), &from, &to) {
+            process::exit(65);
+        }
+    } else if args.first().map(|a| a.as_str()) == Some("fmt") {
+        let check = take_flag(&mut args, "--check");
+        if args.len() < 2 {
+            println!("Usage: pepega fmt [--check] [--dialect name|file] [script...]");
+            process::exit(64);
+        }
+        // A file that cannot be formatted does not stop the others.
+        let mut unformatted = false;
+        let mut failed = false;
+        for path in &args[1..] {
+            match format_file(path, check, &dialect) {
+                Ok(formatted) => unformatted |= !formatted,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            process::exit(65);
+        }
+        if check && unformatted {
+            process::exit(1);
+        }
+    } else if args.first().map(|a| a.as_str()) == Some("lsp") {
+        process::exit(LanguageServer::new(dialect).run());
+    } else if args.first().map(|a| a.as_str()) == Some("dap") {
+        process::exit(DapServer::new(dialect).run());
+    } else if args.first().map(|a| a.as_str()) == Some("debug") {
+        let mut breakpoints = Vec::new();
+        while let Some(line) = take_option(&mut args, "--break") {
+            match line.parse::<usize>() {
+                Ok(l) => breakpoints.push(l),
+                Err(_) => {
+                    eprintln!("Invalid line number '{}'.", line);
+                    process::exit(64);
+                }
+            }
+        }
+        if args.len() != 2 {
+            println!("Usage: pepega debug [--break line]... [--dialect name|file] [script]");
+            process::exit(64);
+        }
+        if let Err(e) = debug_file(args[1].clone(), breakpoints, &dialect, &options) {
+            eprintln!("{}", e);
+            process::exit(70);
+        }
+    } else if args.first().map(|a| a.as_str()) == Some("run") {
+        let coverage = take_flag(&mut args, "--coverage");
+        let coverage_out = take_option(&mut args, "--coverage-out").unwrap_or("lcov.info".to_string());
+        if args.len() != 2 {
+            println!("Usage: pepega run [--coverage [--coverage-out file]] [--backend tree|vm] [--opt-level 0|1|2] [--max-depth n] [--max-steps n] [--timeout ms] [--max-values n] [--max-string-bytes n] [--max-output n] [--sandbox] [--gc-stats] [--dialect name|file] [script]");
+            process::exit(64);
+        }
+        let result = if coverage {
+            coverage_file(args[1].clone(), &coverage_out, &dialect, &options)
+        } else {
+            run_file(args[1].clone(), &dialect, backend, opt_level, &options)
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(70);
+        }
+    } else if args.first().map(|a| a.as_str()) == Some("test") {
+        let paths = if args.len() > 1 { args[1..].to_vec() } else { vec![".".to_string()] };
+        if !run_tests(&paths, &dialect, opt_level, &options) {
+            process::exit(1);
+        }
+    } else if args.first().map(|a| a.as_str()) == Some("check") {
+        let strict = take_flag(&mut args, "--strict");
+        if args.len() != 2 {
+            println!("Usage: pepega check [--strict] [--dialect name|file] [script]");
+            process::exit(64);
+        }
+        if !check_file(args[1].clone(), strict, &dialect) {
+            process::exit(65);
+        }
+    } else if args.len() > 1 {
+        println!("Usage: pepega [--dialect name|file] [--backend tree|vm] [--opt-level 0|1|2] [--max-depth n] [--max-steps n] [--timeout ms] [--max-values n] [--max-string-bytes n] [--max-output n] [--sandbox] [--gc-stats] [--tokens] [--ast] [--ast-json] [--profile [--profile-out file]] [script]");
         process::exit(64);
-    } else if args.len() == 2 {
-        run_file(args[1].clone()).expect("Cannot run file.");
+    } else if args.len() == 1 && (tokens || ast || ast_json) {
+        dump_file(args[0].clone(), tokens, ast, ast_json, &dialect, opt_level);
+    } else if args.len() == 1 && profile {
+        if let Err(e) = profile_file(args[0].clone(), &profile_out, &dialect, &options) {
+            eprintln!("{}", e);
+            process::exit(70);
+        }
+    } else if args.len() == 1 {
+        if let Err(e) = run_file(args[0].clone(), &dialect, backend, opt_level, &options) {
+            eprintln!("{}", e);
+            process::exit(70);
+        }
     } else {
-        println!("Pepega 0.1.0: >>>> Interactive Mode <<<<");
-        run_prompt();
+        println!("Pepega 0.1.0: >>>> Interactive Mode <<<< ({} dialect)", dialect.name);
+        run_prompt(&dialect, backend, opt_level, &options);
     }
 }
@@ -0,0 +1,169 @@
+use crate::interpreter::interpreter::Interpreter;
+use crate::lexer::token::TokenType;
+use crate::parser::expr::Expr;
+use crate::parser::stmt::Stmt;
+
+// How much `optimize` rewrites, from `--opt-level`.
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum OptLevel {
+    // Runs the tree as parsed.
+    None,
+    // Folds operators over literals and drops groupings.
+    Fold,
+    // Also removes branches and loops whose condition is a constant.
+    DeadCode
+}
+
+impl OptLevel {
+    pub fn parse(level: &str) -> Result<OptLevel, String> {
+        match level {
+            "0" => Ok(OptLevel::None),
+            "1" => Ok(OptLevel::Fold),
+            "2" => Ok(OptLevel::DeadCode),
+            _ => Err(format!("Unknown optimization level '{}'. Expected 0, 1 or 2.", level))
+        }
+    }
+}
+
+// Rewrites a parsed program before it is resolved and run. Constants are
+// folded by the interpreter itself, so a folded result is exactly what running
+// the expression would give. An operation that would fail is left alone, so
+// its error is still reported at run time on its own line.
+pub struct Optimizer {
+    level: OptLevel,
+    interpreter: Interpreter
+}
+
+impl Optimizer {
+    pub fn new(level: OptLevel) -> Optimizer {
+        Optimizer {
+            level,
+            interpreter: Interpreter::new()
+        }
+    }
+
+    pub fn optimize(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        if self.level == OptLevel::None {
+            return statements;
+        }
+        self.statements(statements)
+    }
+
+    fn statements(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements.into_iter().filter_map(|s| self.stmt(s)).collect()
+    }
+
+    // A statement where the grammar needs one, such as a loop body.
+    fn branch(&mut self, stmt: Stmt) -> Stmt {
+        self.stmt(stmt).unwrap_or(Stmt::Block { statements: Vec::new() })
+    }
+
+    // The optimized statement, or `None` when it can never run.
+    fn stmt(&mut self, stmt: Stmt) -> Option<Stmt> {
+        let stmt = match stmt {
+            Stmt::Block { statements } => Stmt::Block { statements: self.statements(statements) },
+            Stmt::Class { name, methods } => Stmt::Class { name, methods: self.statements(methods) },
+            Stmt::Const { name, type_annotation, initializer } => {
+                Stmt::Const { name, type_annotation, initializer: self.expr(initializer) }
+            },
+            Stmt::Expression { expression } => Stmt::Expression { expression: self.expr(expression) },
+            Stmt::Function { name, params, param_types, return_type, body } => {
+                Stmt::Function { name, params, param_types, return_type, body: self.statements(body) }
+            },
+            Stmt::If { keyword, condition, then_branch, else_branch } => {
+                let condition = self.expr(condition);
+                if let (OptLevel::DeadCode, Expr::Literal { value }) = (self.level, &condition) {
+                    return if value.is_truthy() {
+                        self.stmt(*then_branch)
+                    } else {
+                        else_branch.and_then(|e| self.stmt(*e))
+                    };
+                }
+                Stmt::If {
+                    keyword,
+                    condition,
+                    then_branch: Box::new(self.branch(*then_branch)),
+                    else_branch: else_branch.map(|e| Box::new(self.branch(*e)))
+                }
+            },
+            Stmt::Match { keyword, subject, arms } => Stmt::Match {
+                keyword,
+                subject: self.expr(subject),
+                arms: arms.into_iter().map(|(pattern, body)| (pattern, self.branch(body))).collect()
+            },
+            Stmt::Print { keyword, expression } => Stmt::Print { keyword, expression: self.expr(expression) },
+            Stmt::Return { keyword, value } => Stmt::Return { keyword, value: value.map(|v| self.expr(v)) },
+            Stmt::While { keyword, condition, body } => {
+                let condition = self.expr(condition);
+                if let (OptLevel::DeadCode, Expr::Literal { value }) = (self.level, &condition) {
+                    if !value.is_truthy() {
+                        return None;
+                    }
+                }
+                Stmt::While { keyword, condition, body: Box::new(self.branch(*body)) }
+            },
+            Stmt::Var { name, type_annotation, initializer } => {
+                Stmt::Var { name, type_annotation, initializer: initializer.map(|i| self.expr(i)) }
+            },
+            s @ Stmt::Enum { .. } => s
+        };
+        Some(stmt)
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Assign { name, value, resolution } => Expr::Assign { name, value: Box::new(self.expr(*value)), resolution },
+            Expr::Binary { left, operator, right } => {
+                let binary = Expr::new_binary(self.expr(*left), operator, self.expr(*right));
+                match &binary {
+                    Expr::Binary { left, right, .. } if is_literal(left) && is_literal(right) => self.fold(binary),
+                    _ => binary
+                }
+            },
+            Expr::Call { callee, paren, arguments } => Expr::Call {
+                callee: Box::new(self.expr(*callee)),
+                paren,
+                arguments: arguments.into_iter().map(|a| self.expr(a)).collect()
+            },
+            Expr::Get { object, name } => Expr::new_get(self.expr(*object), name),
+            Expr::Grouping { expression } => self.expr(*expression),
+            Expr::Logical { left, operator, right } => {
+                let left = self.expr(*left);
+                let right = self.expr(*right);
+                // `or` and `and` give back one of their operands, so a constant
+                // left side decides which.
+                if let Expr::Literal { value } = &left {
+                    let short_circuits = match operator.kind {
+                        TokenType::OR => value.is_truthy(),
+                        _ => !value.is_truthy()
+                    };
+                    return if short_circuits { left } else { right };
+                }
+                Expr::new_logical(left, operator, right)
+            },
+            Expr::Set { object, name, value } => Expr::new_set(self.expr(*object), name, self.expr(*value)),
+            Expr::Unary { operator, right } => {
+                let unary = Expr::new_unary(operator, self.expr(*right));
+                match &unary {
+                    Expr::Unary { right, .. } if is_literal(right) => self.fold(unary),
+                    _ => unary
+                }
+            },
+            e @ (Expr::Literal { .. } | Expr::This { .. } | Expr::Variable { .. }) => e
+        }
+    }
+
+    // Evaluates an operator whose operands are all literals.
+    fn fold(&mut self, expr: Expr) -> Expr {
+        match self.interpreter.evaluate_expr(&expr) {
+            Ok(value) => Expr::new_literal(value),
+            Err(_) => expr
+        }
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal { .. })
+}
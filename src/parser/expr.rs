@@ -1,12 +1,29 @@
+use std::cell::Cell;
 use std::fmt::Display;
 use crate::lexer::token::{LiteralValue, Token};
+use crate::util::json::Json;
+
+// Where the resolver found the variable an expression names.
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Resolution {
+    // Not seen by a resolver, like code typed into the debugger. Looked up
+    // by name through every enclosing scope.
+    Unresolved,
+    Global,
+    // In the scope `depth` levels out from where it is used, at index `slot`.
+    Local { depth: usize, slot: usize }
+}
 
 #[derive(Clone)]
 #[derive(Debug)]
 pub enum Expr {
     Assign {
         name: Token,
-        value: Box<Expr>
+        value: Box<Expr>,
+        resolution: Cell<Resolution>
     },
     Binary {
         left: Box<Expr>,
@@ -39,62 +56,142 @@ pub enum Expr {
         value: Box<Expr>
     },
     This {
-        keyword: Token
+        keyword: Token,
+        resolution: Cell<Resolution>
     },
     Unary {
         operator: Token,
         right: Box<Expr>
     },
     Variable {
-        name: Token
+        name: Token,
+        resolution: Cell<Resolution>
     }
 }
 
+// Prints the tree as an S-expression in the style of Crafting Interpreters'
+// AstPrinter, e.g. `(+ 1 (group (* 2 3)))`.
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Expr::Assign { ref name, ref value } => {
-                write!(f, "Assign: {:?}, {:?}", name, value)
+            Expr::Assign { ref name, ref value, .. } => {
+                write!(f, "(= {} {})", name.lexeme, value)
             },
             Expr::Binary { ref left, ref operator, ref right } => {
-                write!(f, "Binary: {:?}, {:?}, {:?}", left, operator, right)
+                write!(f, "({} {} {})", operator.lexeme, left, right)
             },
-            Expr::Call { ref callee, ref paren, ref arguments } => {
-                write!(f, "Call: {:?}, {:?}, {:?}", callee, paren, arguments)
+            Expr::Call { ref callee, ref arguments, .. } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
             },
             Expr::Grouping { ref expression } => {
-                write!(f, "Grouping: {:?}", expression)
+                write!(f, "(group {})", expression)
             },
             Expr::Literal { ref value } => {
-                write!(f, "Literal: {:?}", value)
+                match value {
+                    LiteralValue::StringVal(s) => write!(f, "\"{}\"", s),
+                    v => write!(f, "{}", v.to_string())
+                }
             },
             Expr::Logical { ref left, ref operator, ref right } => {
-                write!(f, "Logical: {:?}, {:?}, {:?}", left, operator, right)
+                write!(f, "({} {} {})", operator.lexeme, left, right)
             },
             Expr::Unary { ref operator, ref right } => {
-                write!(f, "Unary: {:?}, {:?}", operator, right)
+                write!(f, "({} {})", operator.lexeme, right)
             },
-            Expr::Variable { ref name } => {
-                write!(f, "Variable: {:?}", name)
+            Expr::Variable { ref name, .. } => {
+                write!(f, "{}", name.lexeme)
             },
             Expr::Get { ref object, ref name } => {
-                write!(f, "Get: {:?}, {:?}", object, name)
+                write!(f, "(. {} {})", object, name.lexeme)
             },
             Expr::Set { ref object, ref name, ref value } => {
-                write!(f, "Set: {:?}, {:?}, {:?}", object, name, value)
+                write!(f, "(= (. {} {}) {})", object, name.lexeme, value)
             },
-            Expr::This { ref keyword } => {
-                write!(f, "This: {:?}", keyword)
+            Expr::This { ref keyword, .. } => {
+                write!(f, "{}", keyword.lexeme)
             }
         }
     }
 }
 
 impl Expr {
+    // The line of the first token that belongs to the expression itself.
+    // Literals carry no token.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Assign { name, .. } | Expr::Get { name, .. } | Expr::Variable { name, .. } => Some(name.line),
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => left.line().or(Some(operator.line)),
+            Expr::Call { callee, paren, .. } => callee.line().or(Some(paren.line)),
+            Expr::Grouping { expression } => expression.line(),
+            Expr::Literal { .. } => None,
+            Expr::Set { object, name, .. } => object.line().or(Some(name.line)),
+            Expr::This { keyword, .. } => Some(keyword.line),
+            Expr::Unary { operator, .. } => Some(operator.line)
+        }
+    }
+
+    // Used by `--ast-json`; every node is an object with a "type" field.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Expr::Assign { name, value, .. } => Json::object(vec![
+                ("type", "Assign".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("value", value.to_json())
+            ]),
+            Expr::Binary { left, operator, right } => Json::object(vec![
+                ("type", "Binary".into()), ("operator", operator.lexeme.to_string().into()), ("line", operator.line.into()),
+                ("left", left.to_json()), ("right", right.to_json())
+            ]),
+            Expr::Call { callee, paren, arguments } => Json::object(vec![
+                ("type", "Call".into()), ("line", paren.line.into()), ("callee", callee.to_json()),
+                ("arguments", arguments.iter().map(|a| a.to_json()).collect::<Vec<Json>>().into())
+            ]),
+            Expr::Get { object, name } => Json::object(vec![
+                ("type", "Get".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("object", object.to_json())
+            ]),
+            Expr::Grouping { expression } => Json::object(vec![
+                ("type", "Grouping".into()), ("expression", expression.to_json())
+            ]),
+            Expr::Literal { value } => {
+                let value = match value {
+                    LiteralValue::FloatVal(n) => Json::Number(*n),
+                    LiteralValue::StringVal(s) => s.to_string().into(),
+                    LiteralValue::BooleanVal(b) => Json::Bool(*b),
+                    LiteralValue::NullVal => Json::Null,
+                    v => v.to_string().into()
+                };
+                Json::object(vec![("type", "Literal".into()), ("value", value)])
+            },
+            Expr::Logical { left, operator, right } => Json::object(vec![
+                ("type", "Logical".into()), ("operator", operator.lexeme.to_string().into()), ("line", operator.line.into()),
+                ("left", left.to_json()), ("right", right.to_json())
+            ]),
+            Expr::Set { object, name, value } => Json::object(vec![
+                ("type", "Set".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("object", object.to_json()), ("value", value.to_json())
+            ]),
+            Expr::This { keyword, .. } => Json::object(vec![
+                ("type", "This".into()), ("line", keyword.line.into())
+            ]),
+            Expr::Unary { operator, right } => Json::object(vec![
+                ("type", "Unary".into()), ("operator", operator.lexeme.to_string().into()), ("line", operator.line.into()),
+                ("right", right.to_json())
+            ]),
+            Expr::Variable { name, .. } => Json::object(vec![
+                ("type", "Variable".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into())
+            ])
+        }
+    }
+
     pub fn new_assign(name: Token, value: Expr) -> Expr {
         Expr::Assign {
             name,
-            value: Box::new(value)
+            value: Box::new(value),
+            resolution: Cell::new(Resolution::Unresolved)
         }
     }
 
@@ -142,7 +239,8 @@ impl Expr {
 
     pub fn new_this(keyword: Token) -> Expr {
         Expr::This {
-            keyword
+            keyword,
+            resolution: Cell::new(Resolution::Unresolved)
         }
     }
 
@@ -163,7 +261,8 @@ impl Expr {
 
     pub fn new_variable(name: Token) -> Expr {
         Expr::Variable {
-            name
+            name,
+            resolution: Cell::new(Resolution::Unresolved)
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+use crate::interpreter::resolver::Resolver;
+use crate::lexer::dialect::Dialect;
+use crate::lexer::lexer::Lexer;
+use crate::optimizer::optimizer::{OptLevel, Optimizer};
+use crate::parser::parser::{ParseError, Parser};
+use crate::parser::stmt::Stmt;
+
+// Lexes, parses, optimizes at `opt_level` and resolves a script, ready to
+// run. Every error is collected rather than aborting at the first, and they
+// are returned one per line, each starting with `[line N] Error`. Later
+// stages are skipped once one has failed.
+pub fn parse_program(source: String, dialect: &Dialect, opt_level: OptLevel) -> Result<Vec<Stmt>, String> {
+    let mut lexer = Lexer::new_with_dialect(source, dialect);
+    lexer.collect_errors();
+    lexer.scan_tokens();
+    if !lexer.errors.is_empty() {
+        let messages: Vec<String> = lexer.errors.iter().map(|e| format!("[line {}] Error: {}", e.line, e.message)).collect();
+        return Err(messages.join("\n"));
+    }
+    let mut parser = Parser::new(lexer.tokens);
+    parser.collect_errors();
+    let statements = parser.parse();
+    if !parser.parse_errors.is_empty() {
+        return Err(describe(&parser.parse_errors));
+    }
+    let statements = Optimizer::new(opt_level).optimize(statements);
+    let mut resolver = Resolver::new();
+    resolver.collect_errors();
+    resolver.resolve(&statements);
+    if !resolver.errors.is_empty() {
+        return Err(describe(&resolver.errors));
+    }
+    Ok(statements)
+}
+
+fn describe(errors: &[ParseError]) -> String {
+    let messages: Vec<String> = errors.iter().map(|e| format!("[line {}] {}", e.token.line, e.describe())).collect();
+    messages.join("\n")
+}
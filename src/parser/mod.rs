@@ -1,4 +1,6 @@
 pub mod parser;
+pub mod frontend;
 
 pub mod expr;
-pub mod stmt;
\ No newline at end of file
+pub mod stmt;
+pub mod syntax;
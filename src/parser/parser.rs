@@ -2,8 +2,8 @@ use crate::{error_token};
 use crate::lexer::token::{LiteralValue, Token, TokenType};
 use crate::lexer::token::TokenType::IDENTIFIER;
 use crate::parser::expr::Expr;
-use crate::parser::stmt::Stmt;
-use crate::parser::stmt::Stmt::Print;
+use crate::parser::stmt::{Pattern, Stmt};
+use crate::parser::syntax::{Comment, Comments, Node, Syntax};
 
 pub struct ParseError {
     pub message: String,
@@ -17,12 +17,35 @@ impl ParseError {
             token
         }
     }
+
+    pub fn describe(&self) -> String {
+        if self.token.kind == TokenType::EOF {
+            format!("Error at end: {}", self.message)
+        } else {
+            format!("Error at '{}': {}", self.token.lexeme, self.message)
+        }
+    }
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
-    parse_errors: Vec<ParseError>
+    pub parse_errors: Vec<ParseError>,
+    report_errors: bool,
+    // Set after an error until the parser has skipped to the next statement,
+    // so that one mistake is reported once.
+    panic_mode: bool,
+    keep_trivia: bool,
+    // trivia[i] holds the comments and newlines in front of tokens[i];
+    // trivia_pos is how many of those in front of the current token were
+    // already turned into statements.
+    trivia: Vec<Vec<Token>>,
+    trivia_pos: usize,
+    // Comments inside the statements being parsed, innermost last.
+    comments: Vec<Comments>,
+    // Comments between the arguments of the calls being parsed, innermost
+    // last, with one slot per argument as in `Comments::calls`.
+    call_comments: Vec<Vec<Vec<Comment>>>
 }
 
 impl Parser {
@@ -30,35 +53,187 @@ impl Parser {
         Parser {
             tokens,
             current: 0,
-            parse_errors: Vec::new()
+            parse_errors: Vec::new(),
+            report_errors: true,
+            panic_mode: false,
+            keep_trivia: false,
+            trivia: Vec::new(),
+            trivia_pos: 0,
+            comments: Vec::new(),
+            call_comments: Vec::new()
+        }
+    }
+
+    // Takes tokens from `Lexer::scan_tokens_with_trivia` and keeps comments
+    // and blank lines in the tree `parse_syntax` returns, for the formatter.
+    pub fn new_with_trivia(tokens: Vec<Token>) -> Parser {
+        let mut significant: Vec<Token> = Vec::new();
+        let mut trivia: Vec<Vec<Token>> = Vec::new();
+        let mut pending: Vec<Token> = Vec::new();
+        for token in tokens {
+            match token.kind {
+                TokenType::WHITESPACE => (),
+                TokenType::NEWLINE | TokenType::COMMENT => pending.push(token),
+                _ => {
+                    significant.push(token);
+                    trivia.push(std::mem::take(&mut pending));
+                }
+            }
         }
+        let mut parser = Parser::new(significant);
+        parser.keep_trivia = true;
+        parser.trivia = trivia;
+        parser
+    }
+
+    // Collects errors in `parse_errors` and recovers at the next statement
+    // instead of reporting the first one and aborting. The statements parsed
+    // around an error are still returned.
+    pub fn collect_errors(&mut self) {
+        self.report_errors = false;
     }
 
     pub(crate) fn parse(&mut self) -> Vec<Stmt> {
-        let mut statements: Vec<Stmt> = Vec::new();
-        while !self.is_at_end() {
+        self.parse_syntax().into_iter().filter_map(Syntax::lower).collect()
+    }
+
+    // Parses the script into the tree the formatter works on, which still
+    // has `forsen` loops and, with trivia kept, comments and blank lines.
+    pub fn parse_syntax(&mut self) -> Vec<Syntax> {
+        let mut statements: Vec<Syntax> = Vec::new();
+        while !self.is_at_end() || self.has_trivia() {
             statements.push(self.declaration());
         }
-        if self.parse_errors.len() > 0 {
+        if self.report_errors && !self.parse_errors.is_empty() {
             panic!("Cannot parse with errors.");
         }
         statements
     }
 
-    fn declaration(&mut self) -> Stmt {
-        if self.match_check(vec![TokenType::CLASS]) {
-            return self.class_statement();
+    // Skips tokens until something that looks like the start of a statement,
+    // always moving past the token the failed declaration started at.
+    fn synchronize(&mut self, start: usize) {
+        self.panic_mode = false;
+        while !self.is_at_end() {
+            if self.current > start {
+                if self.previous().kind == TokenType::SEMICOLON {
+                    return;
+                }
+                match self.peek().kind {
+                    TokenType::CLASS | TokenType::FUN | TokenType::VAR | TokenType::CONST | TokenType::ENUM |
+                    TokenType::FOR | TokenType::IF | TokenType::WHILE | TokenType::PRINT | TokenType::RETURN |
+                    TokenType::MATCH | TokenType::RIGHT_BRACE => return,
+                    _ => ()
+                }
+            }
+            self.advance();
+        }
+    }
+
+    // Looks at the comments and newlines in front of the current token and
+    // returns the next one worth keeping, together with the trivia position
+    // just after it. Runs of blank lines collapse into one `BlankLine`, and
+    // blank lines at the start or end of a block are dropped.
+    fn peek_trivia(&self) -> Option<(Node, usize)> {
+        if !self.keep_trivia {
+            return None;
+        }
+        let trivia = &self.trivia[self.current];
+        let next = &self.tokens[self.current].kind;
+        let after_open = self.current == 0 || self.tokens[self.current - 1].kind == TokenType::LEFT_BRACE;
+        let before_close = *next == TokenType::RIGHT_BRACE || *next == TokenType::EOF;
+        let mut newlines = 0;
+        let mut i = self.trivia_pos;
+        while i < trivia.len() {
+            if trivia[i].kind == TokenType::COMMENT {
+                return Some((Node::Comment(self.comment(i)), i + 1));
+            }
+            newlines += 1;
+            i += 1;
+            let after_comment = trivia[..i].iter().any(|t| t.kind == TokenType::COMMENT);
+            if newlines >= 2 && (!after_open || after_comment) && !before_close {
+                while i < trivia.len() && trivia[i].kind == TokenType::NEWLINE {
+                    i += 1;
+                }
+                return Some((Node::BlankLine, i));
+            }
         }
-        if self.match_check(vec![TokenType::FUN]) {
-            return self.function("function");
+        None
+    }
+
+    fn has_trivia(&self) -> bool {
+        self.peek_trivia().is_some()
+    }
+
+    // The comment at `trivia[i]` in front of the current token.
+    fn comment(&self, i: usize) -> Comment {
+        let trivia = &self.trivia[self.current];
+        let trailing = self.current > 0 && !trivia[..i].iter().any(|t| t.kind == TokenType::NEWLINE);
+        Comment { token: trivia[i].clone(), trailing }
+    }
+
+    fn trivia_statement(&mut self) -> Syntax {
+        let (node, pos) = self.peek_trivia().expect("No trivia in front of the current token.");
+        self.trivia_pos = pos;
+        Syntax::new(node)
+    }
+
+    // Parses a statement with `parse`, together with the comments inside it.
+    fn with_comments(&mut self, parse: impl FnOnce(&mut Parser) -> Node) -> Syntax {
+        self.comments.push(Comments::default());
+        let node = parse(self);
+        let comments = self.comments.pop().unwrap_or_default();
+        Syntax { node, comments }
+    }
+
+    // Comments skipped over in the middle of a statement belong to the call
+    // whose arguments are being parsed, or else to the statement.
+    fn keep_comments(&mut self, comments: Vec<Comment>) {
+        if let Some(slot) = self.call_comments.last_mut().and_then(|slots| slots.last_mut()) {
+            slot.extend(comments);
+        } else if let Some(statement) = self.comments.last_mut() {
+            statement.inline.extend(comments);
+        }
+    }
+
+    fn declaration(&mut self) -> Syntax {
+        let start = self.current;
+        let syntax = self.declaration_or_error();
+        if self.panic_mode {
+            self.synchronize(start);
         }
-        if self.match_check(vec![TokenType::VAR]) {
-            return self.var_declaration();
+        syntax
+    }
+
+    fn declaration_or_error(&mut self) -> Syntax {
+        if self.has_trivia() {
+            return self.trivia_statement();
         }
-        self.statement()
+        self.with_comments(|parser| {
+            if parser.match_check(vec![TokenType::CLASS]) {
+                return parser.class_statement();
+            }
+            if parser.match_check(vec![TokenType::FUN]) {
+                return parser.function("function");
+            }
+            if parser.match_check(vec![TokenType::VAR]) {
+                return parser.var_declaration();
+            }
+            if parser.match_check(vec![TokenType::CONST]) {
+                return parser.const_declaration();
+            }
+            if parser.match_check(vec![TokenType::ENUM]) {
+                return parser.enum_declaration();
+            }
+            parser.statement_node()
+        })
+    }
+
+    fn statement(&mut self) -> Syntax {
+        self.with_comments(Parser::statement_node)
     }
 
-    fn statement(&mut self) -> Stmt {
+    fn statement_node(&mut self) -> Node {
         if self.match_check(vec![TokenType::IF]) {
             return self.if_statement();
         }
@@ -74,33 +249,122 @@ impl Parser {
         if self.match_check(vec![TokenType::FOR]) {
             return self.for_statement();
         }
+        if self.match_check(vec![TokenType::MATCH]) {
+            return self.match_statement();
+        }
         if self.match_check(vec![TokenType::LEFT_BRACE]) {
-            return Stmt::Block { statements: self.block() };
+            return Node::Block { statements: self.block() };
         }
         self.expression_statement()
     }
 
-    fn block(&mut self) -> Vec<Stmt> {
-        let mut statements: Vec<Stmt> = Vec::new();
-        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+    fn block(&mut self) -> Vec<Syntax> {
+        let mut statements: Vec<Syntax> = Vec::new();
+        while (!self.check(TokenType::RIGHT_BRACE) && !self.is_at_end()) || self.has_trivia() {
             statements.push(self.declaration());
         }
         self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.");
         statements
     }
 
-    fn class_statement(&mut self) -> Stmt {
+    fn class_statement(&mut self) -> Node {
         let name = self.consume(TokenType::IDENTIFIER, "Expect class name.");
         self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.");
-        let mut methods: Vec<Stmt> = Vec::new();
-        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            methods.push(self.function("method"));
+        let mut methods: Vec<Syntax> = Vec::new();
+        while (!self.check(TokenType::RIGHT_BRACE) && !self.is_at_end()) || self.has_trivia() {
+            let start = self.current;
+            if self.has_trivia() {
+                methods.push(self.trivia_statement());
+            } else {
+                methods.push(self.with_comments(|parser| parser.function("method")));
+            }
+            if self.panic_mode {
+                self.synchronize(start);
+            }
         }
         self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.");
-        Stmt::Class { name, methods }
+        Node::Class { name, methods }
+    }
+
+    fn enum_declaration(&mut self) -> Node {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect enum name.");
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before enum body.");
+        let mut variants: Vec<(Token, Vec<Token>)> = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let variant = self.consume(TokenType::IDENTIFIER, "Expect variant name.");
+            let mut fields: Vec<Token> = Vec::new();
+            if self.match_check(vec![TokenType::LEFT_PAREN]) {
+                if !self.check(TokenType::RIGHT_PAREN) {
+                    loop {
+                        fields.push(self.consume(TokenType::IDENTIFIER, "Expect field name."));
+                        if !self.match_check(vec![TokenType::COMMA]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RIGHT_PAREN, "Expect ')' after variant fields.");
+            }
+            variants.push((variant, fields));
+            if !self.match_check(vec![TokenType::COMMA]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after enum body.");
+        Node::Simple(Stmt::Enum { name, variants })
     }
 
-    fn return_statement(&mut self) -> Stmt {
+    fn match_statement(&mut self) -> Node {
+        let keyword = self.previous();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'match'.");
+        let subject = self.expression();
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after match value.");
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before match arms.");
+        let mut arms: Vec<Syntax> = Vec::new();
+        while (!self.check(TokenType::RIGHT_BRACE) && !self.is_at_end()) || self.has_trivia() {
+            let start = self.current;
+            if self.has_trivia() {
+                arms.push(self.trivia_statement());
+            } else {
+                arms.push(self.with_comments(|parser| {
+                    let pattern = parser.pattern();
+                    parser.consume(TokenType::FAT_ARROW, "Expect '=>' after pattern.");
+                    Node::Arm { pattern, body: Box::new(parser.statement()) }
+                }));
+            }
+            if self.panic_mode {
+                self.synchronize(start);
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after match arms.");
+        Node::Match { keyword, subject, arms }
+    }
+
+    fn pattern(&mut self) -> Pattern {
+        if self.match_check(vec![TokenType::ELSE]) {
+            return Pattern::Wildcard { keyword: self.previous() };
+        }
+        let mut enum_name = None;
+        let mut name = self.consume(TokenType::IDENTIFIER, "Expect variant name in pattern.");
+        if self.match_check(vec![TokenType::DOT]) {
+            enum_name = Some(name);
+            name = self.consume(TokenType::IDENTIFIER, "Expect variant name after '.'.");
+        }
+        let mut bindings: Vec<Token> = Vec::new();
+        if self.match_check(vec![TokenType::LEFT_PAREN]) {
+            if !self.check(TokenType::RIGHT_PAREN) {
+                loop {
+                    bindings.push(self.consume(TokenType::IDENTIFIER, "Expect binding name in pattern."));
+                    if !self.match_check(vec![TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after pattern bindings.");
+        }
+        Pattern::Variant { enum_name, name, bindings }
+    }
+
+    fn return_statement(&mut self) -> Node {
         let keyword = self.previous();
         let value = if !self.check(TokenType::SEMICOLON) {
             Some(self.expression())
@@ -108,22 +372,27 @@ impl Parser {
             None
         };
         self.consume(TokenType::SEMICOLON, "Expect ';' after return value.");
-        Stmt::Return { keyword, value }
+        Node::Simple(Stmt::Return { keyword, value })
     }
 
-    fn for_statement(&mut self) -> Stmt {
+    fn for_statement(&mut self) -> Node {
+        let keyword = self.previous();
         self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.");
         let initializer = if self.match_check(vec![TokenType::SEMICOLON]) {
             None
-        } else if self.match_check(vec![TokenType::VAR]) {
-            Some(self.var_declaration())
         } else {
-            Some(self.expression_statement())
+            Some(Box::new(self.with_comments(|parser| {
+                if parser.match_check(vec![TokenType::VAR]) {
+                    parser.var_declaration()
+                } else {
+                    parser.expression_statement()
+                }
+            })))
         };
         let condition = if !self.check(TokenType::SEMICOLON) {
-            self.expression()
+            Some(self.expression())
         } else {
-            Expr::new_literal(LiteralValue::BooleanVal(true))
+            None
         };
         self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.");
         let increment = if !self.check(TokenType::RIGHT_PAREN) {
@@ -132,25 +401,21 @@ impl Parser {
             None
         };
         self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.");
-        let mut body = Box::new(self.statement());
-        if let Some(increment) = increment {
-            body = Box::new(Stmt::Block { statements: vec![*body, Stmt::Expression { expression: increment }] });
-        }
-        body = Box::new(Stmt::While { condition, body });
-        if let Some(initializer) = initializer {
-            body = Box::new(Stmt::Block { statements: vec![initializer, *body] });
-        }
-        *body
+        let body = Box::new(self.statement());
+        Node::For { keyword, initializer, condition, increment, body }
     }
-    fn while_statement(&mut self) -> Stmt {
+
+    fn while_statement(&mut self) -> Node {
+        let keyword = self.previous();
         self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.");
         let condition = self.expression();
         self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.");
         let body = Box::new(self.statement());
-        Stmt::While { condition, body }
+        Node::While { keyword, condition, body }
     }
 
-    fn if_statement(&mut self) -> Stmt {
+    fn if_statement(&mut self) -> Node {
+        let keyword = self.previous();
         self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.");
         let condition = self.expression();
         self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.");
@@ -160,50 +425,81 @@ impl Parser {
         } else {
             None
         };
-        Stmt::If { condition, then_branch, else_branch }
+        Node::If { keyword, condition, then_branch, else_branch }
     }
 
-    fn var_declaration(&mut self) -> Stmt {
+    fn var_declaration(&mut self) -> Node {
         let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.");
-        let mut initializer = Expr::new_literal(LiteralValue::NullVal);
+        let type_annotation = self.type_annotation();
+        let mut initializer = None;
         if self.match_check(vec![TokenType::EQUAL]) {
-            initializer = self.expression();
+            initializer = Some(self.expression());
         }
         self.consume(TokenType::SEMICOLON, "Expect ';' after variable declaration.");
-        Stmt::Var { name, initializer }
+        Node::Simple(Stmt::Var { name, type_annotation, initializer })
+    }
+
+    fn const_declaration(&mut self) -> Node {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect constant name.");
+        let type_annotation = self.type_annotation();
+        self.consume(TokenType::EQUAL, "Expect '=' after constant name.");
+        let initializer = self.expression();
+        self.consume(TokenType::SEMICOLON, "Expect ';' after constant declaration.");
+        Node::Simple(Stmt::Const { name, type_annotation, initializer })
+    }
+
+    fn type_annotation(&mut self) -> Option<Token> {
+        if !self.match_check(vec![TokenType::COLON]) {
+            return None;
+        }
+        Some(self.consume_type_name("Expect type name after ':'."))
+    }
+
+    fn consume_type_name(&mut self, message: &str) -> Token {
+        if self.match_check(vec![TokenType::IDENTIFIER, TokenType::NIL]) {
+            return self.previous();
+        }
+        self.error(self.peek(), message)
     }
 
-    fn print_statement(&mut self) -> Stmt {
+    fn print_statement(&mut self) -> Node {
+        let keyword = self.previous();
         let value = self.expression();
         self.consume(TokenType::SEMICOLON, "Expect ';' after value.");
-        Print { expression: value }
+        Node::Simple(Stmt::Print { keyword, expression: value })
     }
 
-    fn function(&mut self, kind: &str) -> Stmt {
+    fn function(&mut self, kind: &str) -> Node {
         let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", kind));
         self.consume(TokenType::LEFT_PAREN, &format!("Expect '(' after {} name.", kind));
         let mut params: Vec<Token> = Vec::new();
+        let mut param_types: Vec<Option<Token>> = Vec::new();
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
                 if params.len() >= 255 {
                     self.error(self.peek(), "Cannot have more than 255 parameters.");
                 }
                 params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name."));
+                param_types.push(self.type_annotation());
                 if !self.match_check(vec![TokenType::COMMA]) {
                     break;
                 }
             }
         }
         self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.");
+        let mut return_type = None;
+        if self.match_check(vec![TokenType::ARROW]) {
+            return_type = Some(self.consume_type_name("Expect return type after '->'."));
+        }
         self.consume(TokenType::LEFT_BRACE, &format!("Expect '{{' before {} body.", kind));
         let body = self.block();
-        Stmt::Function { name, params, body }
+        Node::Function { name, params, param_types, return_type, body }
     }
 
-    fn expression_statement(&mut self) -> Stmt {
+    fn expression_statement(&mut self) -> Node {
         let expr = self.expression();
         self.consume(TokenType::SEMICOLON, "Expect ';' after expression.");
-        Stmt::Expression { expression: expr }
+        Node::Simple(Stmt::Expression { expression: expr })
     }
 
     fn match_check(&mut self, types: Vec<TokenType>) -> bool {
@@ -229,6 +525,14 @@ impl Parser {
 
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
+            if self.keep_trivia {
+                let unused = (self.trivia_pos..self.trivia[self.current].len())
+                    .filter(|i| self.trivia[self.current][*i].kind == TokenType::COMMENT)
+                    .map(|i| self.comment(i))
+                    .collect();
+                self.keep_comments(unused);
+                self.trivia_pos = 0;
+            }
             self.current += 1;
         }
         self.previous()
@@ -251,7 +555,7 @@ impl Parser {
         if self.match_check(vec![TokenType::EQUAL]) {
             let equals = self.previous();
             let value = self.assignment();
-            if let Expr::Variable { name } = expr {
+            if let Expr::Variable { name, .. } = expr {
                 return Expr::new_assign(name, value);
             } else if let Expr::Get { object, name } = expr {
                 return Expr::new_set(*object, name, value);
@@ -347,18 +651,29 @@ impl Parser {
 
     fn finish_call(&mut self, callee: Expr) -> Expr {
         let mut arguments: Vec<Expr> = Vec::new();
+        if self.keep_trivia {
+            self.call_comments.push(vec![Vec::new()]);
+        }
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
                 if arguments.len() >= 255 {
                     self.error(self.peek(), "Cannot have more than 255 arguments.");
                 }
                 arguments.push(self.expression());
+                if let Some(slots) = self.call_comments.last_mut() {
+                    slots.push(Vec::new());
+                }
                 if !self.match_check(vec![TokenType::COMMA]) {
                     break;
                 }
             }
         }
         let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.");
+        if let Some(slots) = self.call_comments.pop() {
+            if let Some(statement) = self.comments.last_mut().filter(|_| slots.iter().any(|s| !s.is_empty())) {
+                statement.calls.insert((paren.line, paren.column), slots);
+            }
+        }
         Expr::new_call(callee, paren, arguments)
     }
 
@@ -402,9 +717,15 @@ impl Parser {
     }
 
     fn error (&mut self, token: Token, message: &str) -> Token {
+        if self.panic_mode {
+            return token;
+        }
+        self.panic_mode = true;
         self.add_parse_error(ParseError::new(message.to_string(), token.clone()));
         let return_token = token.clone();
-        error_token(token, message);
+        if self.report_errors {
+            error_token(token, message);
+        }
         return_token
     }
 
@@ -1,6 +1,8 @@
 use std::fmt::Display;
 use crate::lexer::token::Token;
 use crate::parser::expr::Expr;
+use crate::util::json::Json;
+
 #[derive(Clone)]
 #[derive(Debug)]
 pub enum Stmt {
@@ -11,20 +13,38 @@ pub enum Stmt {
         name: Token,
         methods: Vec<Stmt>
     },
+    Const {
+        name: Token,
+        type_annotation: Option<Token>,
+        initializer: Expr
+    },
+    Enum {
+        name: Token,
+        variants: Vec<(Token, Vec<Token>)>
+    },
     Expression {
         expression: Expr
     },
     Function {
         name: Token,
         params: Vec<Token>,
+        param_types: Vec<Option<Token>>,
+        return_type: Option<Token>,
         body: Vec<Stmt>
     },
     If {
+        keyword: Token,
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>
     },
+    Match {
+        keyword: Token,
+        subject: Expr,
+        arms: Vec<(Pattern, Stmt)>
+    },
     Print {
+        keyword: Token,
         expression: Expr
     },
     Return {
@@ -32,45 +52,260 @@ pub enum Stmt {
         value: Option<Expr>
     },
     While {
+        keyword: Token,
         condition: Expr,
         body: Box<Stmt>
     },
     Var {
         name: Token,
-        initializer: Expr
+        type_annotation: Option<Token>,
+        // `None` for `kekw x;`, which starts as nil.
+        initializer: Option<Expr>
     }
 }
 
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Pattern {
+    Variant {
+        enum_name: Option<Token>,
+        name: Token,
+        bindings: Vec<Token>
+    },
+    Wildcard {
+        keyword: Token
+    }
+}
+
+// Prints the tree as S-expressions with every nested statement on its own,
+// further indented line, e.g.
+//   (while (< i 3)
+//     (block
+//       (print i)))
 impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+impl Display for Pattern {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Variant { ref enum_name, ref name, ref bindings } => {
+                let path = match enum_name {
+                    Some(e) => format!("{}.{}", e.lexeme, name.lexeme),
+                    None => name.lexeme.to_string()
+                };
+                if bindings.is_empty() {
+                    write!(f, "{}", path)
+                } else {
+                    let bindings: Vec<&str> = bindings.iter().map(|b| b.lexeme.as_str()).collect();
+                    write!(f, "({} {})", path, bindings.join(" "))
+                }
+            },
+            Pattern::Wildcard { .. } => write!(f, "_")
+        }
+    }
+}
+
+fn name_json(token: &Option<Token>) -> Json {
+    match token {
+        Some(t) => t.lexeme.to_string().into(),
+        None => Json::Null
+    }
+}
+
+fn statements_json(statements: &[Stmt]) -> Json {
+    statements.iter().map(|s| s.to_json()).collect::<Vec<Json>>().into()
+}
+
+impl Pattern {
+    pub fn to_json(&self) -> Json {
+        match self {
+            Pattern::Variant { enum_name, name, bindings } => Json::object(vec![
+                ("type", "Variant".into()), ("enum", name_json(enum_name)), ("name", name.lexeme.to_string().into()),
+                ("bindings", bindings.iter().map(|b| b.lexeme.to_string().into()).collect::<Vec<Json>>().into())
+            ]),
+            Pattern::Wildcard { .. } => Json::object(vec![("type", "Wildcard".into())])
+        }
+    }
+}
+
+fn annotated(name: &Token, annotation: &Option<Token>) -> String {
+    match annotation {
+        Some(t) => format!("{}: {}", name.lexeme, t.lexeme),
+        None => name.lexeme.to_string()
+    }
+}
+
+impl Stmt {
+    // The line a statement starts on, for the debugger and error messages.
+    // Blocks have none of their own.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Block { .. } => None,
+            Stmt::Class { name, .. } | Stmt::Const { name, .. } | Stmt::Enum { name, .. } |
+            Stmt::Function { name, .. } | Stmt::Var { name, .. } => Some(name.line),
+            Stmt::If { keyword, .. } | Stmt::Match { keyword, .. } |
+            Stmt::Print { keyword, .. } | Stmt::Return { keyword, .. } | Stmt::While { keyword, .. } => Some(keyword.line),
+            Stmt::Expression { expression } => expression.line()
+        }
+    }
+
+    // Used by `--ast-json`, mirroring `Expr::to_json`.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Stmt::Block { statements } => Json::object(vec![
+                ("type", "Block".into()), ("statements", statements_json(statements))
+            ]),
+            Stmt::Class { name, methods } => Json::object(vec![
+                ("type", "Class".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("methods", statements_json(methods))
+            ]),
+            Stmt::Const { name, type_annotation, initializer } => Json::object(vec![
+                ("type", "Const".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("annotation", name_json(type_annotation)), ("initializer", initializer.to_json())
+            ]),
+            Stmt::Enum { name, variants } => {
+                let variants: Vec<Json> = variants.iter().map(|(v, fields)| Json::object(vec![
+                    ("name", v.lexeme.to_string().into()),
+                    ("fields", fields.iter().map(|f| f.lexeme.to_string().into()).collect::<Vec<Json>>().into())
+                ])).collect();
+                Json::object(vec![
+                    ("type", "Enum".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                    ("variants", variants.into())
+                ])
+            },
+            Stmt::Expression { expression } => Json::object(vec![
+                ("type", "Expression".into()), ("expression", expression.to_json())
+            ]),
+            Stmt::Function { name, params, param_types, return_type, body } => {
+                let params: Vec<Json> = params.iter().zip(param_types).map(|(p, t)| Json::object(vec![
+                    ("name", p.lexeme.to_string().into()), ("annotation", name_json(t))
+                ])).collect();
+                Json::object(vec![
+                    ("type", "Function".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                    ("params", params.into()), ("returns", name_json(return_type)), ("body", statements_json(body))
+                ])
+            },
+            Stmt::If { condition, then_branch, else_branch, .. } => Json::object(vec![
+                ("type", "If".into()), ("condition", condition.to_json()), ("then", then_branch.to_json()),
+                ("else", else_branch.as_ref().map(|e| e.to_json()).unwrap_or(Json::Null))
+            ]),
+            Stmt::Match { keyword, subject, arms } => {
+                let arms: Vec<Json> = arms.iter().map(|(pattern, body)| Json::object(vec![
+                    ("pattern", pattern.to_json()), ("body", body.to_json())
+                ])).collect();
+                Json::object(vec![
+                    ("type", "Match".into()), ("line", keyword.line.into()), ("subject", subject.to_json()),
+                    ("arms", arms.into())
+                ])
+            },
+            Stmt::Print { expression, .. } => Json::object(vec![
+                ("type", "Print".into()), ("expression", expression.to_json())
+            ]),
+            Stmt::Return { keyword, value } => Json::object(vec![
+                ("type", "Return".into()), ("line", keyword.line.into()),
+                ("value", value.as_ref().map(|v| v.to_json()).unwrap_or(Json::Null))
+            ]),
+            Stmt::While { condition, body, .. } => Json::object(vec![
+                ("type", "While".into()), ("condition", condition.to_json()), ("body", body.to_json())
+            ]),
+            Stmt::Var { name, type_annotation, initializer } => Json::object(vec![
+                ("type", "Var".into()), ("name", name.lexeme.to_string().into()), ("line", name.line.into()),
+                ("annotation", name_json(type_annotation)),
+                ("initializer", initializer.as_ref().map(|i| i.to_json()).unwrap_or(Json::Null))
+            ])
+        }
+    }
+
+    fn write_child(&self, f: &mut std::fmt::Formatter, indent: usize) -> std::fmt::Result {
+        write!(f, "\n{}", "  ".repeat(indent))?;
+        self.write_tree(f, indent)
+    }
+
+    fn write_tree(&self, f: &mut std::fmt::Formatter, indent: usize) -> std::fmt::Result {
         match self {
             Stmt::Block { ref statements } => {
-                write!(f, "Block: {:?}", statements)
+                write!(f, "(block")?;
+                for s in statements {
+                    s.write_child(f, indent + 1)?;
+                }
+                write!(f, ")")
+            },
+            Stmt::Class { ref name, ref methods } => {
+                write!(f, "(class {}", name.lexeme)?;
+                for m in methods {
+                    m.write_child(f, indent + 1)?;
+                }
+                write!(f, ")")
+            },
+            Stmt::Const { ref name, ref type_annotation, ref initializer } => {
+                write!(f, "(const {} {})", annotated(name, type_annotation), initializer)
+            },
+            Stmt::Enum { ref name, ref variants } => {
+                write!(f, "(enum {}", name.lexeme)?;
+                for (variant, fields) in variants {
+                    if fields.is_empty() {
+                        write!(f, " {}", variant.lexeme)?;
+                    } else {
+                        let fields: Vec<&str> = fields.iter().map(|t| t.lexeme.as_str()).collect();
+                        write!(f, " ({} {})", variant.lexeme, fields.join(" "))?;
+                    }
+                }
+                write!(f, ")")
             },
             Stmt::Expression { ref expression } => {
-                write!(f, "Expression: {:?}", expression)
+                write!(f, "(; {})", expression)
             },
-            Stmt::Function { ref name, ref params, ref body } => {
-                write!(f, "Function: {:?}, {:?}, {:?}", name, params, body)
+            Stmt::Function { ref name, ref params, ref param_types, ref return_type, ref body } => {
+                let params: Vec<String> = params.iter().zip(param_types).map(|(p, t)| annotated(p, t)).collect();
+                write!(f, "(fun {} ({})", name.lexeme, params.join(" "))?;
+                if let Some(r) = return_type {
+                    write!(f, " -> {}", r.lexeme)?;
+                }
+                for s in body {
+                    s.write_child(f, indent + 1)?;
+                }
+                write!(f, ")")
             },
-            Stmt::If { ref condition, ref then_branch, ref else_branch } => {
-                write!(f, "If: {:?}, {:?}, {:?}", condition, then_branch, else_branch)
+            Stmt::If { ref condition, ref then_branch, ref else_branch, .. } => {
+                write!(f, "(if {}", condition)?;
+                then_branch.write_child(f, indent + 1)?;
+                if let Some(else_branch) = else_branch {
+                    else_branch.write_child(f, indent + 1)?;
+                }
+                write!(f, ")")
             },
-            Stmt::Print { ref expression } => {
-                write!(f, "Print: {:?}", expression)
+            Stmt::Match { ref subject, ref arms, .. } => {
+                write!(f, "(match {}", subject)?;
+                for (pattern, body) in arms {
+                    write!(f, "\n{}(=> {}", "  ".repeat(indent + 1), pattern)?;
+                    body.write_child(f, indent + 2)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
             },
-            Stmt::While { ref condition, ref body } => {
-                write!(f, "While: {:?}, {:?}", condition, body)
+            Stmt::Print { ref expression, .. } => {
+                write!(f, "(print {})", expression)
             },
-            Stmt::Var { ref name, ref initializer } => {
-                write!(f, "Var: {:?}, {:?}", name, initializer)
+            Stmt::Return { ref value, .. } => {
+                match value {
+                    Some(v) => write!(f, "(return {})", v),
+                    None => write!(f, "(return)")
+                }
             },
-            Stmt::Return { ref keyword, ref value } => {
-                write!(f, "Return: {:?}, {:?}", keyword, value)
+            Stmt::While { ref condition, ref body, .. } => {
+                write!(f, "(while {}", condition)?;
+                body.write_child(f, indent + 1)?;
+                write!(f, ")")
             },
-            Stmt::Class { ref name, ref methods } => {
-                write!(f, "Class: {:?}, {:?}", name, methods)
+            Stmt::Var { ref name, ref type_annotation, ref initializer } => {
+                match initializer {
+                    Some(i) => write!(f, "(var {} {})", annotated(name, type_annotation), i),
+                    None => write!(f, "(var {})", annotated(name, type_annotation))
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
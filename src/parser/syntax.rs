@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use crate::lexer::token::{LiteralValue, Token};
+use crate::parser::expr::Expr;
+use crate::parser::stmt::{Pattern, Stmt};
+
+// A statement as it was written, for the formatter. Unlike `Stmt` it keeps
+// `forsen` loops, and the comments and blank lines between and inside
+// statements. `Parser::parse` lowers it to `Stmt` straight away, so nothing
+// that runs or checks a script ever sees it.
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Syntax {
+    pub node: Node,
+    pub comments: Comments
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Node {
+    Block {
+        statements: Vec<Syntax>
+    },
+    Class {
+        name: Token,
+        methods: Vec<Syntax>
+    },
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        param_types: Vec<Option<Token>>,
+        return_type: Option<Token>,
+        body: Vec<Syntax>
+    },
+    If {
+        keyword: Token,
+        condition: Expr,
+        then_branch: Box<Syntax>,
+        else_branch: Option<Box<Syntax>>
+    },
+    While {
+        keyword: Token,
+        condition: Expr,
+        body: Box<Syntax>
+    },
+    For {
+        keyword: Token,
+        initializer: Option<Box<Syntax>>,
+        condition: Option<Expr>,
+        increment: Option<Expr>,
+        body: Box<Syntax>
+    },
+    // `arms` holds `Arm`s and the comments and blank lines between them.
+    Match {
+        keyword: Token,
+        subject: Expr,
+        arms: Vec<Syntax>
+    },
+    Arm {
+        pattern: Pattern,
+        body: Box<Syntax>
+    },
+    // Const, Enum, Expression, Print, Return and Var, which hold no other
+    // statements.
+    Simple(Stmt),
+    Comment(Comment),
+    BlankLine
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Comment {
+    pub token: Token,
+    // Whether code comes before it on its line.
+    pub trailing: bool
+}
+
+// Comments found inside a statement rather than between statements.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Comments {
+    // Comments outside the arguments of any call, like ones in an `if`
+    // header or after `=>`.
+    pub inline: Vec<Comment>,
+    // Comments between the arguments of calls, keyed by the line and column
+    // of the call's closing paren. Slot 0 holds the comments after the
+    // opening paren and slot i those after the ith argument.
+    pub calls: HashMap<(usize, usize), Vec<Vec<Comment>>>
+}
+
+impl Syntax {
+    pub fn new(node: Node) -> Syntax {
+        Syntax { node, comments: Comments::default() }
+    }
+
+    // The statement to run, or `None` for a comment or blank line.
+    pub fn lower(self) -> Option<Stmt> {
+        let stmt = match self.node {
+            Node::Block { statements } => Stmt::Block { statements: lower_all(statements) },
+            Node::Class { name, methods } => Stmt::Class { name, methods: lower_all(methods) },
+            Node::Function { name, params, param_types, return_type, body } => {
+                Stmt::Function { name, params, param_types, return_type, body: lower_all(body) }
+            },
+            Node::If { keyword, condition, then_branch, else_branch } => Stmt::If {
+                keyword,
+                condition,
+                then_branch: Box::new(then_branch.lower_body()),
+                else_branch: else_branch.map(|e| Box::new(e.lower_body()))
+            },
+            Node::While { keyword, condition, body } => Stmt::While { keyword, condition, body: Box::new(body.lower_body()) },
+            Node::For { keyword, initializer, condition, increment, body } => {
+                desugar_for(keyword, initializer.and_then(|i| i.lower()), condition, increment, body.lower_body())
+            },
+            Node::Match { keyword, subject, arms } => {
+                let arms = arms.into_iter().filter_map(|a| match a.node {
+                    Node::Arm { pattern, body } => Some((pattern, body.lower_body())),
+                    _ => None
+                }).collect();
+                Stmt::Match { keyword, subject, arms }
+            },
+            Node::Simple(stmt) => stmt,
+            // Arms are lowered with their `Match`.
+            Node::Arm { .. } | Node::Comment(_) | Node::BlankLine => return None
+        };
+        Some(stmt)
+    }
+
+    // Branch and loop bodies are parsed as single statements, which are never
+    // comments or blank lines.
+    fn lower_body(self) -> Stmt {
+        self.lower().unwrap_or(Stmt::Block { statements: Vec::new() })
+    }
+}
+
+fn lower_all(statements: Vec<Syntax>) -> Vec<Stmt> {
+    statements.into_iter().filter_map(Syntax::lower).collect()
+}
+
+fn desugar_for(keyword: Token, initializer: Option<Stmt>, condition: Option<Expr>, increment: Option<Expr>, body: Stmt) -> Stmt {
+    let condition = condition.unwrap_or(Expr::new_literal(LiteralValue::BooleanVal(true)));
+    let mut body = Box::new(body);
+    if let Some(increment) = increment {
+        body = Box::new(Stmt::Block { statements: vec![*body, Stmt::Expression { expression: increment }] });
+    }
+    body = Box::new(Stmt::While { keyword, condition, body });
+    if let Some(initializer) = initializer {
+        body = Box::new(Stmt::Block { statements: vec![initializer, *body] });
+    }
+    *body
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use crate::interpreter::interpreter::{ExecutionHook, Interpreter, LineSteps};
+use crate::lexer::token::{Token, TokenType};
+use crate::parser::expr::Expr;
+use crate::parser::stmt::Stmt;
+
+struct Branch {
+    // The `clueless` keyword or the `and`/`or` operator.
+    token: Token,
+    // How often each side was taken, numbered as in `ExecutionHook::branch_taken`.
+    taken: [u64; 2]
+}
+
+// Records which statements and which sides of every branch a script runs.
+// Lines and branches are registered up front, so code that never runs is
+// still reported.
+pub struct Coverage {
+    lines: BTreeMap<usize, u64>,
+    steps: LineSteps,
+    // Keyed by the token's (line, column).
+    branches: BTreeMap<(usize, usize), Branch>
+}
+
+impl Coverage {
+    pub fn new(statements: &[Stmt]) -> Coverage {
+        let mut coverage = Coverage {
+            lines: BTreeMap::new(),
+            steps: LineSteps::default(),
+            branches: BTreeMap::new()
+        };
+        coverage.statements(statements);
+        coverage
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for s in statements {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if let Some(line) = stmt.line() {
+            self.lines.entry(line).or_insert(0);
+        }
+        match stmt {
+            Stmt::Block { statements } => self.statements(statements),
+            Stmt::Class { methods, .. } => {
+                // Methods are not statements of their own; only their bodies run.
+                for m in methods {
+                    if let Stmt::Function { body, .. } = m {
+                        self.statements(body);
+                    }
+                }
+            },
+            Stmt::Const { initializer, .. } | Stmt::Var { initializer: Some(initializer), .. } => self.expr(initializer),
+            Stmt::Var { initializer: None, .. } => (),
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => self.expr(expression),
+            Stmt::Function { body, .. } => self.statements(body),
+            Stmt::If { keyword, condition, then_branch, else_branch } => {
+                self.branch(keyword);
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(e) = else_branch {
+                    self.stmt(e);
+                }
+            },
+            Stmt::Match { subject, arms, .. } => {
+                self.expr(subject);
+                for (_, body) in arms {
+                    self.stmt(body);
+                }
+            },
+            Stmt::Return { value, .. } => {
+                if let Some(v) = value {
+                    self.expr(v);
+                }
+            },
+            Stmt::While { condition, body, .. } => {
+                self.expr(condition);
+                self.stmt(body);
+            },
+            Stmt::Enum { .. } => ()
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { value, .. } => self.expr(value),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Logical { left, operator, right } => {
+                self.branch(operator);
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Call { callee, arguments, .. } => {
+                self.expr(callee);
+                for a in arguments {
+                    self.expr(a);
+                }
+            },
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Set { object, value, .. } => {
+                self.expr(object);
+                self.expr(value);
+            },
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Literal { .. } | Expr::This { .. } | Expr::Variable { .. } => ()
+        }
+    }
+
+    fn branch(&mut self, token: &Token) {
+        self.branches.entry((token.line, token.column)).or_insert(Branch { token: token.clone(), taken: [0, 0] });
+    }
+
+    // The report in LCOV's tracefile format, as read by genhtml and most
+    // coverage services. `source` is the path written in the `SF:` record.
+    pub fn lcov(&self, source: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", source);
+        for (line, hits) in &self.lines {
+            lcov.push_str(&format!("DA:{},{}\n", line, hits));
+        }
+        lcov.push_str(&format!("LF:{}\nLH:{}\n", self.lines.len(), self.lines.values().filter(|h| **h > 0).count()));
+        let mut block = 0;
+        let mut previous_line = 0;
+        for ((line, _), branch) in &self.branches {
+            // Blocks number the branch points on one line from 0.
+            block = if *line == previous_line { block + 1 } else { 0 };
+            previous_line = *line;
+            let reached = branch.taken.iter().any(|t| *t > 0);
+            for (side, taken) in branch.taken.iter().enumerate() {
+                let taken = if reached { taken.to_string() } else { "-".to_string() };
+                lcov.push_str(&format!("BRDA:{},{},{},{}\n", line, block, side, taken));
+            }
+        }
+        let sides_hit: usize = self.branches.values().map(|b| b.taken.iter().filter(|t| **t > 0).count()).sum();
+        lcov.push_str(&format!("BRF:{}\nBRH:{}\nend_of_record\n", self.branches.len() * 2, sides_hit));
+        lcov
+    }
+
+    pub fn summary(&self) -> String {
+        let lines_hit = self.lines.values().filter(|h| **h > 0).count();
+        let sides_hit: usize = self.branches.values().map(|b| b.taken.iter().filter(|t| **t > 0).count()).sum();
+        let mut summary = format!(
+            "Lines:    {}/{} ({})\nBranches: {}/{} ({})\n",
+            lines_hit, self.lines.len(), percent(lines_hit, self.lines.len()),
+            sides_hit, self.branches.len() * 2, percent(sides_hit, self.branches.len() * 2)
+        );
+        let missed: Vec<usize> = self.lines.iter().filter(|(_, h)| **h == 0).map(|(l, _)| *l).collect();
+        if !missed.is_empty() {
+            summary.push_str(&format!("Lines not run: {}\n", ranges(&missed)));
+        }
+        for branch in self.branches.values() {
+            let token = &branch.token;
+            let sides = match token.kind {
+                TokenType::IF => ["the condition was never true", "the condition was never false"],
+                _ => ["the right side never ran", "it never short-circuited"]
+            };
+            for (side, message) in sides.iter().enumerate() {
+                if branch.taken[side] == 0 {
+                    summary.push_str(&format!("[line {}] '{}': {}\n", token.line, token.lexeme, message));
+                }
+            }
+        }
+        summary
+    }
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", hit as f64 * 100.0 / total as f64)
+}
+
+// Joins sorted line numbers into ranges, e.g. `3, 7-9`.
+fn ranges(lines: &[usize]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let start = lines[i];
+        while i + 1 < lines.len() && lines[i + 1] == lines[i] + 1 {
+            i += 1;
+        }
+        parts.push(if lines[i] == start { start.to_string() } else { format!("{}-{}", start, lines[i]) });
+        i += 1;
+    }
+    parts.join(", ")
+}
+
+impl ExecutionHook for Coverage {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, line: usize) -> Result<(), String> {
+        if self.steps.starts_step(stmt, line, interpreter.call_stack.len()) {
+            *self.lines.entry(line).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    fn branch_taken(&mut self, token: &Token, branch: usize) {
+        if let Some(b) = self.branches.get_mut(&(token.line, token.column)) {
+            b.taken[branch] += 1;
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::lexer::dialect::Dialect;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::token::{LiteralValue, Token, TokenType};
+use crate::parser::expr::Expr;
+use crate::parser::parser::Parser;
+use crate::parser::stmt::{Pattern, Stmt};
+use crate::parser::syntax::{Comment, Comments, Node, Syntax};
+
+const INDENT: usize = 4;
+const MAX_WIDTH: usize = 100;
+
+// Reformats a whole script in the canonical style. Keywords keep the spelling
+// of `dialect`, comments and (collapsed) blank lines are kept. A script that
+// does not parse is left alone and its errors are returned.
+pub fn format_source(source: String, dialect: &Dialect) -> Result<String, String> {
+    let mut lexer = Lexer::new_with_dialect(source, dialect);
+    lexer.collect_errors();
+    lexer.scan_tokens_with_trivia();
+    if !lexer.errors.is_empty() {
+        let messages: Vec<String> = lexer.errors.iter().map(|e| format!("[line {}] Error: {}", e.line, e.message)).collect();
+        return Err(messages.join("\n"));
+    }
+    let mut parser = Parser::new_with_trivia(lexer.tokens);
+    parser.collect_errors();
+    let statements = parser.parse_syntax();
+    if !parser.parse_errors.is_empty() {
+        let messages: Vec<String> = parser.parse_errors.iter().map(|e| format!("[line {}] {}", e.token.line, e.describe())).collect();
+        return Err(messages.join("\n"));
+    }
+    let formatter = Formatter { dialect };
+    let mut lines: Vec<String> = Vec::new();
+    formatter.push_statements(&mut lines, &statements, 0, false);
+    if lines.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+fn pad(indent: usize) -> String {
+    " ".repeat(indent * INDENT)
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+// The column `s` ends at when it is printed starting at `column`.
+fn end_column(column: usize, s: &str) -> usize {
+    match s.rfind('\n') {
+        Some(i) => width(&s[i + 1..]),
+        None => column + width(s)
+    }
+}
+
+fn comment_text(comment: &Comment) -> &str {
+    comment.token.lexeme.trim_end()
+}
+
+struct Formatter<'a> {
+    dialect: &'a Dialect
+}
+
+impl Formatter<'_> {
+    fn keyword(&self, kind: TokenType) -> &str {
+        self.dialect.spelling(&kind).unwrap_or("")
+    }
+
+    // Trailing comments go at the end of the statement they follow, or of the
+    // line that opened the block when they follow the opening brace. `methods`
+    // prints functions without the function keyword.
+    fn push_statements(&self, out: &mut Vec<String>, statements: &[Syntax], indent: usize, methods: bool) {
+        let mut after_code = !out.is_empty();
+        for s in statements {
+            match &s.node {
+                Node::Comment(comment) if comment.trailing && after_code => {
+                    let last = out.last_mut().unwrap();
+                    last.push(' ');
+                    last.push_str(comment_text(comment));
+                    after_code = false;
+                },
+                Node::Comment(_) | Node::BlankLine => {
+                    out.extend(self.stmt(s, indent, methods));
+                    after_code = false;
+                },
+                _ => {
+                    out.extend(self.stmt(s, indent, methods));
+                    after_code = true;
+                }
+            }
+        }
+    }
+
+    // Prints a statement and the comments inside it. Those outside call
+    // arguments end up on its first line, since the code around them is
+    // joined onto that line; when there are several they go above it
+    // instead, one per line.
+    fn stmt(&self, s: &Syntax, indent: usize, method: bool) -> Vec<String> {
+        let mut comments: Vec<&Comment> = Vec::new();
+        let mut lines: Vec<String> = self.node(s, indent, method, &mut comments).iter()
+            .flat_map(|l| l.split('\n').map(|l| l.to_string()).collect::<Vec<String>>())
+            .collect();
+        match (comments.as_slice(), lines.first_mut()) {
+            ([], _) => lines,
+            ([comment], Some(first)) => {
+                first.push(' ');
+                first.push_str(comment_text(comment));
+                lines
+            },
+            _ => comments.iter().map(|c| format!("{}{}", pad(indent), comment_text(c))).chain(lines).collect()
+        }
+    }
+
+    // Prints `header` followed by `body`, which is either a braced block or a
+    // single statement kept on the same line.
+    fn with_body<'s>(&self, header: String, body: &'s Syntax, indent: usize, comments: &mut Vec<&'s Comment>) -> Vec<String> {
+        match &body.node {
+            Node::Block { statements } => {
+                comments.extend(&body.comments.inline);
+                self.braced(header, statements, indent, false)
+            },
+            _ => {
+                let mut lines = self.node(body, indent, false, comments);
+                lines[0] = format!("{}{} {}", pad(indent), header, lines[0].trim_start());
+                lines
+            }
+        }
+    }
+
+    fn braced(&self, header: String, statements: &[Syntax], indent: usize, methods: bool) -> Vec<String> {
+        let open = if header.is_empty() { "{".to_string() } else { format!("{} {{", header) };
+        if statements.is_empty() {
+            return vec![format!("{}{}}}", pad(indent), open)];
+        }
+        let mut lines = vec![format!("{}{}", pad(indent), open)];
+        self.push_statements(&mut lines, statements, indent + 1, methods);
+        lines.push(format!("{}}}", pad(indent)));
+        lines
+    }
+
+    // Prints a statement, adding the comments that belong on its first line
+    // to `comments`.
+    fn node<'s>(&self, s: &'s Syntax, indent: usize, method: bool, comments: &mut Vec<&'s Comment>) -> Vec<String> {
+        comments.extend(&s.comments.inline);
+        let p = pad(indent);
+        match &s.node {
+            Node::Block { statements } => self.braced(String::new(), statements, indent, false),
+            Node::BlankLine => vec![String::new()],
+            Node::Class { name, methods } => {
+                let header = format!("{} {}", self.keyword(TokenType::CLASS), name.lexeme);
+                self.braced(header, methods, indent, true)
+            },
+            Node::Comment(comment) => vec![format!("{}{}", p, comment_text(comment))],
+            Node::For { initializer, condition, increment, body, .. } => {
+                let mut header = format!("{} (", self.keyword(TokenType::FOR));
+                match initializer {
+                    Some(i) => {
+                        let lines = self.node(i, indent, false, comments);
+                        header.push_str(lines.join("\n").trim_start());
+                    },
+                    None => header.push(';')
+                }
+                if let Some(c) = condition {
+                    let column = end_column(width(&p), &header) + 1;
+                    header = format!("{} {}", header, self.expr(c, &s.comments, indent, column));
+                }
+                header.push(';');
+                if let Some(i) = increment {
+                    let column = end_column(width(&p), &header) + 1;
+                    header = format!("{} {}", header, self.expr(i, &s.comments, indent, column));
+                }
+                header.push(')');
+                self.with_body(header, body, indent, comments)
+            },
+            Node::Function { name, params, param_types, return_type, body } => {
+                let params: Vec<String> = params.iter().zip(param_types).map(|(p, t)| annotated(p, t)).collect();
+                let mut header = if method { String::new() } else { format!("{} ", self.keyword(TokenType::FUN)) };
+                header.push_str(&format!("{}({})", name.lexeme, params.join(", ")));
+                if let Some(r) = return_type {
+                    header.push_str(&format!(" -> {}", r.lexeme));
+                }
+                self.braced(header, body, indent, false)
+            },
+            Node::If { condition, then_branch, else_branch, .. } => {
+                let keyword = self.keyword(TokenType::IF);
+                let column = width(&p) + width(keyword) + 2;
+                let header = format!("{} ({})", keyword, self.expr(condition, &s.comments, indent, column));
+                let mut lines = self.with_body(header, then_branch, indent, comments);
+                if let Some(else_branch) = else_branch {
+                    let else_lines = self.with_body(self.keyword(TokenType::ELSE).to_string(), else_branch, indent, comments);
+                    if let Node::Block { .. } = then_branch.node {
+                        let last = lines.pop().unwrap();
+                        lines.push(format!("{} {}", last, else_lines[0].trim_start()));
+                        lines.extend(else_lines.into_iter().skip(1));
+                    } else {
+                        lines.extend(else_lines);
+                    }
+                }
+                lines
+            },
+            Node::Match { subject, arms, .. } => {
+                let keyword = self.keyword(TokenType::MATCH);
+                let column = width(&p) + width(keyword) + 2;
+                let header = format!("{} ({})", keyword, self.expr(subject, &s.comments, indent, column));
+                self.braced(header, arms, indent, false)
+            },
+            Node::Arm { pattern, body } => self.with_body(format!("{} =>", self.pattern(pattern)), body, indent, comments),
+            Node::While { condition, body, .. } => {
+                let keyword = self.keyword(TokenType::WHILE);
+                let column = width(&p) + width(keyword) + 2;
+                let header = format!("{} ({})", keyword, self.expr(condition, &s.comments, indent, column));
+                self.with_body(header, body, indent, comments)
+            },
+            Node::Simple(stmt) => self.simple(stmt, &s.comments, indent)
+        }
+    }
+
+    fn simple(&self, stmt: &Stmt, comments: &Comments, indent: usize) -> Vec<String> {
+        let p = pad(indent);
+        match stmt {
+            Stmt::Const { name, type_annotation, initializer } => {
+                let prefix = format!("{}{} {} = ", p, self.keyword(TokenType::CONST), annotated(name, type_annotation));
+                vec![format!("{}{};", prefix, self.expr(initializer, comments, indent, width(&prefix)))]
+            },
+            Stmt::Enum { name, variants } => {
+                let variants: Vec<String> = variants.iter().map(|(v, fields)| {
+                    if fields.is_empty() {
+                        v.lexeme.to_string()
+                    } else {
+                        let fields: Vec<&str> = fields.iter().map(|f| f.lexeme.as_str()).collect();
+                        format!("{}({})", v.lexeme, fields.join(", "))
+                    }
+                }).collect();
+                let header = format!("{}{} {}", p, self.keyword(TokenType::ENUM), name.lexeme);
+                let flat = format!("{} {{ {} }}", header, variants.join(", "));
+                if variants.is_empty() {
+                    return vec![format!("{} {{}}", header)];
+                }
+                if width(&flat) <= MAX_WIDTH {
+                    return vec![flat];
+                }
+                let mut lines = vec![format!("{} {{", header)];
+                let inner = pad(indent + 1);
+                let count = variants.len();
+                for (i, v) in variants.into_iter().enumerate() {
+                    lines.push(format!("{}{}{}", inner, v, if i + 1 < count { "," } else { "" }));
+                }
+                lines.push(format!("{}}}", p));
+                lines
+            },
+            Stmt::Expression { expression } => vec![format!("{}{};", p, self.expr(expression, comments, indent, width(&p)))],
+            Stmt::Print { expression, .. } => {
+                let prefix = format!("{}{} ", p, self.keyword(TokenType::PRINT));
+                vec![format!("{}{};", prefix, self.expr(expression, comments, indent, width(&prefix)))]
+            },
+            Stmt::Return { value, .. } => {
+                match value {
+                    Some(v) => {
+                        let prefix = format!("{}{} ", p, self.keyword(TokenType::RETURN));
+                        vec![format!("{}{};", prefix, self.expr(v, comments, indent, width(&prefix)))]
+                    },
+                    None => vec![format!("{}{};", p, self.keyword(TokenType::RETURN))]
+                }
+            },
+            Stmt::Var { name, type_annotation, initializer } => {
+                let prefix = format!("{}{} {}", p, self.keyword(TokenType::VAR), annotated(name, type_annotation));
+                match initializer {
+                    Some(initializer) => {
+                        let prefix = format!("{} = ", prefix);
+                        vec![format!("{}{};", prefix, self.expr(initializer, comments, indent, width(&prefix)))]
+                    },
+                    None => vec![format!("{};", prefix)]
+                }
+            },
+            // The parser keeps statements that hold other statements as
+            // their own `Node`s.
+            _ => Vec::new()
+        }
+    }
+
+    fn pattern(&self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Wildcard { .. } => self.keyword(TokenType::ELSE).to_string(),
+            Pattern::Variant { enum_name, name, bindings } => {
+                let mut s = match enum_name {
+                    Some(e) => format!("{}.{}", e.lexeme, name.lexeme),
+                    None => name.lexeme.to_string()
+                };
+                if !bindings.is_empty() {
+                    let bindings: Vec<&str> = bindings.iter().map(|b| b.lexeme.as_str()).collect();
+                    s.push_str(&format!("({})", bindings.join(", ")));
+                }
+                s
+            }
+        }
+    }
+
+    // `column` is where the expression starts on its line. One that would run
+    // past MAX_WIDTH is broken at its outermost operator or call: a binary
+    // operator moves to a new, further indented line and a call gets one
+    // argument per line. Calls with comments between their arguments are
+    // always printed that way, with each comment after its argument.
+    fn expr(&self, expr: &Expr, comments: &Comments, indent: usize, column: usize) -> String {
+        let flat = self.flat(expr);
+        let overflows = column + width(&flat) > MAX_WIDTH;
+        if !overflows && !has_comments(expr, comments) {
+            return flat;
+        }
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                let prefix = format!("{} = ", name.lexeme);
+                format!("{}{}", prefix, self.expr(value, comments, indent, column + width(&prefix)))
+            },
+            Expr::Binary { .. } | Expr::Logical { .. } => self.operation(expr, comments, indent, column, overflows),
+            Expr::Call { callee, paren, arguments } => {
+                let callee = self.expr(callee, comments, indent, column);
+                let slots = comments.calls.get(&(paren.line, paren.column));
+                if arguments.is_empty() && slots.is_none() {
+                    return format!("{}()", callee);
+                }
+                let inner = pad(indent + 1);
+                let slot = |i: usize| slots.and_then(|s| s.get(i)).map(|s| s.as_slice()).unwrap_or(&[]);
+                let mut lines = vec![format!("{}(", callee)];
+                lines.extend(slot(0).iter().map(|c| format!("{}{}", inner, comment_text(c))));
+                for (i, a) in arguments.iter().enumerate() {
+                    let mut line = format!("{}{}", inner, self.expr(a, comments, indent + 1, width(&inner)));
+                    if i + 1 < arguments.len() {
+                        line.push(',');
+                    }
+                    let mut after = slot(i + 1);
+                    if let Some((first, rest)) = after.split_first().filter(|(c, _)| c.trailing) {
+                        line = format!("{} {}", line, comment_text(first));
+                        after = rest;
+                    }
+                    lines.push(line);
+                    lines.extend(after.iter().map(|c| format!("{}{}", inner, comment_text(c))));
+                }
+                lines.push(format!("{})", pad(indent)));
+                lines.join("\n")
+            },
+            Expr::Get { object, name } => format!("{}.{}", self.expr(object, comments, indent, column), name.lexeme),
+            Expr::Grouping { expression } => format!("({})", self.expr(expression, comments, indent, column + 1)),
+            Expr::Set { object, name, value } => {
+                let prefix = format!("{}.{} = ", self.expr(object, comments, indent, column), name.lexeme);
+                format!("{}{}", prefix, self.expr(value, comments, indent, end_column(column, &prefix)))
+            },
+            Expr::Unary { operator, right } => {
+                format!("{}{}", operator.lexeme, self.expr(right, comments, indent, column + width(&operator.lexeme)))
+            },
+            _ => flat
+        }
+    }
+
+    // A binary or logical operation that does not fit on its line starts a
+    // new line at the operator. One that is only printed over several lines
+    // because of comments in its operands stays together.
+    fn operation(&self, expr: &Expr, comments: &Comments, indent: usize, column: usize, overflows: bool) -> String {
+        let (left, operator, right) = match expr {
+            Expr::Binary { left, operator, right } => (left, operator.lexeme.as_str(), right),
+            Expr::Logical { left, operator, right } => (left, self.keyword(operator.kind.clone()), right),
+            _ => return self.flat(expr)
+        };
+        let left = self.expr(left, comments, indent, column);
+        let prefix = if overflows {
+            format!("{}\n{}{} ", left, pad(indent + 1), operator)
+        } else {
+            format!("{} {} ", left, operator)
+        };
+        let right_indent = if overflows { indent + 1 } else { indent };
+        format!("{}{}", prefix, self.expr(right, comments, right_indent, end_column(column, &prefix)))
+    }
+
+    // The expression on a single line.
+    fn flat(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Assign { name, value, .. } => format!("{} = {}", name.lexeme, self.flat(value)),
+            Expr::Binary { left, operator, right } => format!("{} {} {}", self.flat(left), operator.lexeme, self.flat(right)),
+            Expr::Call { callee, arguments, .. } => {
+                let args: Vec<String> = arguments.iter().map(|a| self.flat(a)).collect();
+                format!("{}({})", self.flat(callee), args.join(", "))
+            },
+            Expr::Get { object, name } => format!("{}.{}", self.flat(object), name.lexeme),
+            Expr::Grouping { expression } => format!("({})", self.flat(expression)),
+            Expr::Literal { value } => {
+                match value {
+                    LiteralValue::StringVal(s) => format!("\"{}\"", s),
+                    LiteralValue::BooleanVal(true) => self.keyword(TokenType::TRUE).to_string(),
+                    LiteralValue::BooleanVal(false) => self.keyword(TokenType::FALSE).to_string(),
+                    LiteralValue::NullVal => self.keyword(TokenType::NIL).to_string(),
+                    v => v.to_string()
+                }
+            },
+            Expr::Logical { left, operator, right } => {
+                format!("{} {} {}", self.flat(left), self.keyword(operator.kind.clone()), self.flat(right))
+            },
+            Expr::Set { object, name, value } => format!("{}.{} = {}", self.flat(object), name.lexeme, self.flat(value)),
+            Expr::This { .. } => self.keyword(TokenType::THIS).to_string(),
+            Expr::Unary { operator, right } => format!("{}{}", operator.lexeme, self.flat(right)),
+            Expr::Variable { name, .. } => name.lexeme.to_string()
+        }
+    }
+}
+
+// Whether a call in `expr` has comments between its arguments.
+fn has_comments(expr: &Expr, comments: &Comments) -> bool {
+    if comments.calls.is_empty() {
+        return false;
+    }
+    match expr {
+        Expr::Call { callee, paren, arguments } => {
+            comments.calls.contains_key(&(paren.line, paren.column)) || has_comments(callee, comments) ||
+                arguments.iter().any(|a| has_comments(a, comments))
+        },
+        Expr::Assign { value, .. } => has_comments(value, comments),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => has_comments(left, comments) || has_comments(right, comments),
+        Expr::Get { object, .. } => has_comments(object, comments),
+        Expr::Grouping { expression } => has_comments(expression, comments),
+        Expr::Set { object, value, .. } => has_comments(object, comments) || has_comments(value, comments),
+        Expr::Unary { right, .. } => has_comments(right, comments),
+        Expr::Literal { .. } | Expr::This { .. } | Expr::Variable { .. } => false
+    }
+}
+
+fn annotated(name: &Token, annotation: &Option<Token>) -> String {
+    match annotation {
+        Some(t) => format!("{}: {}", name.lexeme, t.lexeme),
+        None => name.lexeme.to_string()
+    }
+}
@@ -0,0 +1,5 @@
+pub mod translate;
+pub mod formatter;
+pub mod profiler;
+pub mod coverage;
+pub mod testing;
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::interpreter::interpreter::{ExecutionHook, Interpreter, LineSteps};
+use crate::parser::stmt::Stmt;
+
+#[derive(Default)]
+struct FunctionStats {
+    calls: u64,
+    inclusive: Duration,
+    exclusive: Duration
+}
+
+struct ActiveCall {
+    function: String,
+    start: Instant,
+    // Time spent in calls made from this one.
+    children: Duration
+}
+
+// Records call counts, wall time and line hits while a script runs. The
+// script itself is counted as a call to `script`.
+pub struct Profiler {
+    source: Vec<String>,
+    stack: Vec<ActiveCall>,
+    functions: HashMap<String, FunctionStats>,
+    lines: HashMap<usize, u64>,
+    steps: LineSteps,
+    // Exclusive time per call path, e.g. `script;main;fib`.
+    folded: HashMap<String, Duration>
+}
+
+impl Profiler {
+    pub fn new(source: &str) -> Profiler {
+        let mut profiler = Profiler {
+            source: source.lines().map(|l| l.to_string()).collect(),
+            stack: Vec::new(),
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            steps: LineSteps::default(),
+            folded: HashMap::new()
+        };
+        profiler.enter("script");
+        profiler
+    }
+
+    fn enter(&mut self, function: &str) {
+        self.functions.entry(function.to_string()).or_default().calls += 1;
+        self.stack.push(ActiveCall { function: function.to_string(), start: Instant::now(), children: Duration::ZERO });
+    }
+
+    fn exit(&mut self) {
+        let path: Vec<&str> = self.stack.iter().map(|c| c.function.as_str()).collect();
+        let path = path.join(";");
+        let call = match self.stack.pop() {
+            Some(c) => c,
+            None => return
+        };
+        let elapsed = call.start.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+        // A recursive call's time is already inside the outermost call's.
+        let recursive = self.stack.iter().any(|c| c.function == call.function);
+        let stats = self.functions.entry(call.function).or_default();
+        if !recursive {
+            stats.inclusive += elapsed;
+        }
+        stats.exclusive += exclusive;
+        *self.folded.entry(path).or_default() += exclusive;
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    // Stops the clock on every call still running, including the script.
+    pub fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.exit();
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut functions: Vec<(&String, &FunctionStats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        let width = functions.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("function".len());
+        let mut report = format!("{:<width$} {:>10} {:>14} {:>14}\n", "function", "calls", "inclusive ms", "exclusive ms");
+        for (name, stats) in functions {
+            report.push_str(&format!(
+                "{:<width$} {:>10} {:>14.3} {:>14.3}\n",
+                name, stats.calls, milliseconds(stats.inclusive), milliseconds(stats.exclusive)
+            ));
+        }
+        let mut lines: Vec<(&usize, &u64)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        report.push_str(&format!("\n{:>6} {:>10}  source\n", "line", "hits"));
+        for (line, hits) in lines {
+            let text = self.source.get(line - 1).map(|l| l.trim()).unwrap_or("");
+            report.push_str(&format!("{:>6} {:>10}  {}\n", line, hits, text));
+        }
+        report
+    }
+
+    // One `path microseconds` line per call path, the input format of
+    // flamegraph.pl and inferno.
+    pub fn folded_stacks(&self) -> String {
+        let mut paths: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        paths.sort();
+        paths.iter().map(|(path, time)| format!("{} {}\n", path, time.as_micros())).collect()
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl ExecutionHook for Profiler {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt, line: usize) -> Result<(), String> {
+        if self.steps.starts_step(stmt, line, interpreter.call_stack.len()) {
+            *self.lines.entry(line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn function_entered(&mut self, interpreter: &Interpreter) {
+        if let Some(frame) = interpreter.call_stack.last() {
+            self.enter(&frame.function);
+        }
+    }
+
+    fn function_exited(&mut self, _interpreter: &Interpreter) {
+        self.exit();
+    }
+}
@@ -0,0 +1,179 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::interpreter::interpreter::Interpreter;
+use crate::interpreter::options::RunOptions;
+use crate::lexer::dialect::Dialect;
+use crate::lexer::token::{call_function_val, LiteralValue};
+use crate::optimizer::optimizer::OptLevel;
+use crate::parser::frontend::parse_program;
+use crate::parser::stmt::Stmt;
+
+// Test files end in this suffix and test functions start with this prefix.
+const FILE_SUFFIX: &str = "_test.pepega";
+const FUNCTION_PREFIX: &str = "test";
+
+struct Failure {
+    name: String,
+    message: String,
+    output: String
+}
+
+// Collects test files under `path` in name order, skipping hidden directories
+// and build output.
+fn discover(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return
+    };
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if entry.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                discover(&entry, files);
+            }
+        } else if name.ends_with(FILE_SUFFIX) {
+            files.push(entry);
+        }
+    }
+}
+
+fn assert(_interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    if arguments[0].is_truthy() {
+        Ok(LiteralValue::NullVal)
+    } else {
+        Err("Assertion failed.".to_string())
+    }
+}
+
+fn assert_eq(_interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    let (actual, expected) = (&arguments[0], &arguments[1]);
+    if LiteralValue::is_equal(actual.clone(), expected.clone()) {
+        return Ok(LiteralValue::NullVal);
+    }
+    Err(format!("Values are not equal.\n{}", diff(&expected.to_string(), &actual.to_string())))
+}
+
+// Calls a function that takes no arguments and fails unless it raises a
+// runtime error containing `message`.
+fn assert_error(interpreter: &mut Interpreter, arguments: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+    let message = arguments[1].to_string();
+    let depth = interpreter.call_stack.len();
+    let line = interpreter.line();
+    let result = match &arguments[0] {
+        LiteralValue::FunctionVal(stmt, closure) => call_function_val(interpreter, stmt, closure, vec![], LiteralValue::NullVal),
+        _ => return Err("assert_error expects a function.".to_string())
+    };
+    interpreter.call_stack.truncate(depth);
+    if let Some(frame) = interpreter.call_stack.last_mut() {
+        frame.line = line;
+    }
+    match result {
+        Err(e) if e.contains(&message) => Ok(LiteralValue::NullVal),
+        Err(e) => Err(format!("Expected an error containing '{}'.\n{}", message, diff(&message, &e))),
+        Ok(_) => Err(format!("Expected an error containing '{}' but none was raised.", message))
+    }
+}
+
+// Expected and actual values line by line, marking lines that differ.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => diff.push_str(&format!("    {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    diff.push_str(&format!("  - {}\n", e));
+                }
+                if let Some(a) = a {
+                    diff.push_str(&format!("  + {}\n", a));
+                }
+            }
+        }
+    }
+    if diff.is_empty() {
+        diff.push_str("  - \n  + \n");
+    }
+    format!("  (- expected, + actual)\n{}", diff.trim_end())
+}
+
+// Runs the script's top-level code and then the named test in a fresh
+// interpreter set up by `options`, so each test gets a budget of its own.
+// Everything the test prints is returned either way.
+fn run_test(statements: &[Stmt], test: &Stmt, options: &RunOptions) -> (Result<(), String>, String) {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    options.apply(&mut interpreter);
+    interpreter.output = output.clone();
+    interpreter.define_native("assert", 1, assert);
+    interpreter.define_native("assert_eq", 2, assert_eq);
+    interpreter.define_native("assert_error", 2, assert_error);
+    let mut result = interpreter.interpret_stmt(statements);
+    if result.is_ok() {
+        let global = interpreter.global.clone();
+        result = call_function_val(&mut interpreter, test, &global, vec![], LiteralValue::NullVal)
+            .map(|_| ())
+            .map_err(|e| format!("{}\n[line {}]", e, interpreter.line()));
+    }
+    let output = String::from_utf8_lossy(&output.borrow()).to_string();
+    (result, output)
+}
+
+// Runs every test found under `paths` and returns whether all of them passed.
+pub fn run_tests(paths: &[String], dialect: &Dialect, opt_level: OptLevel, options: &RunOptions) -> bool {
+    let mut files = Vec::new();
+    for path in paths {
+        discover(Path::new(path), &mut files);
+    }
+    let mut passed = 0;
+    let mut failures: Vec<Failure> = Vec::new();
+    for file in files {
+        let display = file.display().to_string();
+        let statements = match fs::read_to_string(&file).map_err(|e| e.to_string()).and_then(|s| parse_program(s, dialect, opt_level)) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("{} ... FAILED", display);
+                failures.push(Failure { name: display, message: e, output: String::new() });
+                continue;
+            }
+        };
+        for test in &statements {
+            let (name, params) = match test {
+                Stmt::Function { name, params, .. } if name.lexeme.starts_with(FUNCTION_PREFIX) => (name, params),
+                _ => continue
+            };
+            let full_name = format!("{}::{}", display, name.lexeme);
+            let (result, output) = if params.is_empty() {
+                run_test(&statements, test, options)
+            } else {
+                (Err(format!("[line {}] Test functions cannot take parameters.", name.line)), String::new())
+            };
+            match result {
+                Ok(_) => {
+                    println!("{} ... ok", full_name);
+                    passed += 1;
+                },
+                Err(message) => {
+                    println!("{} ... FAILED", full_name);
+                    failures.push(Failure { name: full_name, message, output });
+                }
+            }
+        }
+    }
+    for failure in &failures {
+        println!("\n---- {} ----\n{}", failure.name, failure.message);
+        if !failure.output.is_empty() {
+            println!("output:\n{}", failure.output.trim_end());
+        }
+    }
+    println!("\n{} passed, {} failed", passed, failures.len());
+    failures.is_empty()
+}
@@ -0,0 +1,32 @@
+use crate::lexer::dialect::Dialect;
+use crate::lexer::lexer::Lexer;
+use crate::lexer::token::TokenType;
+
+// Re-spells every keyword of `source` from one dialect to another. Works on the
+// lossless token stream, so comments, whitespace and layout are kept as is.
+pub fn translate(source: String, from: &Dialect, to: &Dialect) -> Result<String, String> {
+    let mut lexer = Lexer::new_with_dialect(source, from);
+    lexer.collect_errors();
+    lexer.scan_tokens_with_trivia();
+    if !lexer.errors.is_empty() {
+        let messages: Vec<String> = lexer.errors.iter().map(|e| format!("[line {}] Error: {}", e.line, e.message)).collect();
+        return Err(messages.join("\n"));
+    }
+    let mut output = String::new();
+    for token in &lexer.tokens {
+        match token.kind {
+            TokenType::EOF => (),
+            TokenType::IDENTIFIER => {
+                if to.keywords().contains_key(token.lexeme.as_str()) {
+                    return Err(format!("[line {}] Identifier '{}' is a keyword in the '{}' dialect.", token.line, token.lexeme, to.name));
+                }
+                output.push_str(&token.lexeme);
+            },
+            _ => match to.spelling(&token.kind) {
+                Some(spelling) => output.push_str(spelling),
+                None => output.push_str(&token.lexeme)
+            }
+        }
+    }
+    Ok(output)
+}
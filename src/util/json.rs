@@ -0,0 +1,385 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Kept as a list so that output preserves insertion order.
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Json, String> {
+        let mut parser = JsonParser { chars: source.chars().collect(), current: 0 };
+        parser.skip_whitespace();
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.current < parser.chars.len() {
+            return Err(parser.error("Unexpected trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Json)>> {
+        match self {
+            Json::Object(o) => Some(o),
+            _ => None
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => {
+                if !n.is_finite() {
+                    write!(f, "null")
+                } else if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{}", n)
+                }
+            },
+            Json::String(s) => write!(f, "{}", escape(s)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", escape(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}.", message, self.current)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.current += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("Expected '{}'", word)));
+            }
+            self.current += 1;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('n') => self.expect_word("null", Json::Null),
+            Some('t') => self.expect_word("true", Json::Bool(true)),
+            Some('f') => self.expect_word("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("Expected a JSON value"))
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.current += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.current += 1,
+                Some(']') => {
+                    self.current += 1;
+                    return Ok(Json::Array(items));
+                },
+                _ => return Err(self.error("Expected ',' or ']' in array"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.current += 1;
+        let mut pairs = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Json::Object(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected string key in object"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(':') {
+                return Err(self.error("Expected ':' after object key"));
+            }
+            self.current += 1;
+            self.skip_whitespace();
+            pairs.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.current += 1,
+                Some('}') => {
+                    self.current += 1;
+                    return Ok(Json::Object(pairs));
+                },
+                _ => return Err(self.error("Expected ',' or '}' in object"))
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.peek().and_then(|c| c.to_digit(16)).ok_or_else(|| self.error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+            self.current += 1;
+        }
+        Ok(code)
+    }
+
+    // Consumes a `\u` escape of a low surrogate following a high one. Anything
+    // else is left for the string to read on its own.
+    fn low_surrogate(&mut self) -> Option<u32> {
+        if self.chars.get(self.current) != Some(&'\\') || self.chars.get(self.current + 1) != Some(&'u') {
+            return None;
+        }
+        let start = self.current;
+        self.current += 2;
+        match self.hex4() {
+            Ok(low) if (0xDC00..0xE000).contains(&low) => Some(low),
+            _ => {
+                self.current = start;
+                None
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.current += 1;
+        let mut out = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(self.error("Unterminated string"))
+            };
+            self.current += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("Unterminated string"))?;
+                    self.current += 1;
+                    match escaped {
+                        '"' => out.push('"'),
+                        '\\' => out.push('\\'),
+                        '/' => out.push('/'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xD800..0xDC00).contains(&code) {
+                                if let Some(low) = self.low_surrogate() {
+                                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                }
+                            }
+                            // A surrogate that is not part of a pair is no character.
+                            out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        },
+                        _ => return Err(self.error("Invalid escape sequence"))
+                    }
+                },
+                c => out.push(c)
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.current;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        text.parse::<f64>().map(Json::Number).map_err(|_| self.error("Invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    fn string(source: &str) -> Json {
+        Json::parse(source).unwrap()
+    }
+
+    #[test]
+    fn surrogate_pair() {
+        assert_eq!(string(r#""\uD83D\uDE00""#), Json::String("\u{1F600}".to_string()));
+    }
+
+    #[test]
+    fn lone_high_surrogate() {
+        assert_eq!(string(r#""\uD800""#), Json::String("\u{FFFD}".to_string()));
+        assert_eq!(string(r#""\uD800x""#), Json::String("\u{FFFD}x".to_string()));
+        assert_eq!(string(r#""\uD800\n""#), Json::String("\u{FFFD}\n".to_string()));
+    }
+
+    #[test]
+    fn high_surrogate_before_other_escape() {
+        assert_eq!(string(r#""\uD800\u0041""#), Json::String("\u{FFFD}A".to_string()));
+        assert_eq!(string(r#""\uD800\uD800\uDC00""#), Json::String("\u{FFFD}\u{10000}".to_string()));
+    }
+
+    #[test]
+    fn lone_low_surrogate() {
+        assert_eq!(string(r#""\uDC00""#), Json::String("\u{FFFD}".to_string()));
+    }
+
+    #[test]
+    fn reversed_surrogate_pair() {
+        assert_eq!(string(r#""\uDE00\uD83D""#), Json::String("\u{FFFD}\u{FFFD}".to_string()));
+    }
+
+    #[test]
+    fn invalid_escape_after_high_surrogate() {
+        assert!(Json::parse(r#""\uD800\uZZZZ""#).is_err());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod json;
+pub mod rpc;
\ No newline at end of file
@@ -0,0 +1,40 @@
+use std::io;
+use std::io::{BufRead, Write};
+use crate::util::json::Json;
+
+// Messages framed the way LSP and DAP expect: a `Content-Length` header, an
+// empty line and then that many bytes of JSON.
+
+// Returns None once the input is closed.
+pub fn read_message(input: &mut impl BufRead) -> Option<Result<Json, String>> {
+    let mut length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = match length {
+        Some(l) => l,
+        None => return Some(Err("Missing Content-Length header.".to_string()))
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    Some(String::from_utf8(body).map_err(|e| e.to_string()).and_then(|b| Json::parse(&b)))
+}
+
+// Fails once the client has gone away.
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
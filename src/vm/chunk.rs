@@ -0,0 +1,117 @@
+use crate::vm::value::Value;
+
+// One byte per instruction followed by its operands. Constant and name
+// operands are two-byte indexes into the constant table, jumps are two-byte
+// offsets and slots, upvalues and argument counts take one byte.
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    DefineConstant,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    // A call in tail position. A function callee takes over the caller's
+    // frame; anything else is called as usual.
+    TailCall,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Method,
+    // Pushes whether the value on top of the stack is the named variant. The
+    // operands are the variant name, the enum name (or `NO_ENUM`) and how
+    // many bindings the pattern has.
+    TestVariant,
+    // Pushes field N of the variant on top of the stack.
+    VariantField,
+    // Fails with "No pattern matched" for the value on top of the stack.
+    NoMatch
+}
+
+// The enum operand of `TestVariant` when the pattern does not name one.
+pub const NO_ENUM: usize = u16::MAX as usize;
+
+const OPCODES: [OpCode; 40] = [
+    OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False, OpCode::Pop,
+    OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal, OpCode::DefineGlobal, OpCode::DefineConstant,
+    OpCode::SetGlobal, OpCode::GetUpvalue, OpCode::SetUpvalue, OpCode::GetProperty, OpCode::SetProperty,
+    OpCode::Equal, OpCode::Greater, OpCode::GreaterEqual, OpCode::Less, OpCode::LessEqual,
+    OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide, OpCode::Not,
+    OpCode::Negate, OpCode::Print, OpCode::Jump, OpCode::JumpIfFalse, OpCode::Loop,
+    OpCode::Call, OpCode::TailCall, OpCode::Closure, OpCode::CloseUpvalue, OpCode::Return,
+    OpCode::Class, OpCode::Method, OpCode::TestVariant, OpCode::VariantField, OpCode::NoMatch
+];
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> OpCode {
+        OPCODES[byte as usize]
+    }
+}
+
+pub struct Chunk {
+    pub code: Vec<u8>,
+    // The source line of every byte in `code`.
+    pub lines: Vec<usize>,
+    pub constants: Vec<Value>
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            code: Vec::new(),
+            lines: Vec::new(),
+            constants: Vec::new()
+        }
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+
+    pub fn write_u16(&mut self, value: usize, line: usize) {
+        self.write((value >> 8) as u8, line);
+        self.write(value as u8, line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> usize {
+        ((self.code[offset] as usize) << 8) | self.code[offset + 1] as usize
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
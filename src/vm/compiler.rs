@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::lexer::token::{LiteralValue, TokenType};
+use crate::parser::expr::Expr;
+use crate::parser::stmt::{Pattern, Stmt};
+use crate::vm::chunk::{Chunk, OpCode, NO_ENUM};
+use crate::vm::value::{EnumDef, Function, Value};
+
+#[derive(PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    // Closed over by a nested function, so it has to be moved off the stack
+    // when its scope ends.
+    captured: bool
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+struct UpvalueRef {
+    index: usize,
+    // Whether `index` is a local slot of the enclosing function or one of
+    // its upvalues.
+    is_local: bool
+}
+
+// Everything being tracked for the function currently being compiled.
+struct FunctionState {
+    name: Rc<str>,
+    arity: usize,
+    kind: FunctionKind,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize
+}
+
+// Compiles resolved statements into bytecode in a single pass over the tree.
+// Top-level declarations become globals; everything inside a block or
+// function lives in a stack slot.
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    // The line of the statement being compiled, which is what runtime errors
+    // report, like the tree-walker does.
+    line: usize,
+    strings: HashMap<String, Rc<str>>
+}
+
+impl Compiler {
+    pub fn compile(statements: &[Stmt]) -> Result<Function, String> {
+        let mut compiler = Compiler {
+            functions: Vec::new(),
+            line: 0,
+            strings: HashMap::new()
+        };
+        compiler.begin_function(FunctionKind::Script, "script");
+        for s in statements {
+            compiler.stmt(s)?;
+        }
+        Ok(compiler.end_function().0)
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn intern(&mut self, s: &str) -> Rc<str> {
+        self.strings.entry(s.to_string()).or_insert_with(|| Rc::from(s)).clone()
+    }
+
+    fn begin_function(&mut self, kind: FunctionKind, name: &str) {
+        // Slot 0 holds the function being called, or the receiver in methods.
+        let slot_zero = if kind == FunctionKind::Method || kind == FunctionKind::Initializer { "this" } else { "" };
+        self.functions.push(FunctionState {
+            name: Rc::from(name),
+            arity: 0,
+            kind,
+            chunk: Chunk::new(),
+            locals: vec![Local { name: slot_zero.to_string(), depth: 0, captured: false }],
+            upvalues: Vec::new(),
+            scope_depth: 0
+        });
+    }
+
+    fn end_function(&mut self) -> (Function, Vec<UpvalueRef>) {
+        self.emit_return();
+        let state = self.functions.pop().unwrap();
+        let function = Function {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: state.chunk
+        };
+        (function, state.upvalues)
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        let line = self.line;
+        self.current().chunk.write_op(op, line);
+    }
+
+    fn emit_byte(&mut self, byte: usize) {
+        let line = self.line;
+        self.current().chunk.write(byte as u8, line);
+    }
+
+    fn emit_u16(&mut self, value: usize) {
+        let line = self.line;
+        self.current().chunk.write_u16(value, line);
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    fn constant(&mut self, value: Value) -> Result<usize, String> {
+        let index = self.current().chunk.add_constant(value);
+        if index >= NO_ENUM {
+            return Err("Too many constants in one chunk.".to_string());
+        }
+        Ok(index)
+    }
+
+    fn name_constant(&mut self, name: &str) -> Result<usize, String> {
+        let name = self.intern(name);
+        self.constant(Value::String(name))
+    }
+
+    fn emit_constant(&mut self, value: Value) -> Result<(), String> {
+        let index = self.constant(value)?;
+        self.emit(OpCode::Constant);
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_u16(0xffff);
+        self.current().chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), String> {
+        let jump = self.current().chunk.code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err("Too much code to jump over.".to_string());
+        }
+        let code = &mut self.current().chunk.code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize) -> Result<(), String> {
+        self.emit(OpCode::Loop);
+        let offset = self.current().chunk.code.len() - start + 2;
+        if offset > u16::MAX as usize {
+            return Err("Loop body too large.".to_string());
+        }
+        self.emit_u16(offset);
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self.current().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop };
+            self.emit(op);
+            self.current().locals.pop();
+        }
+    }
+
+    fn add_local(&mut self, name: &str) -> Result<(), String> {
+        let state = self.current();
+        if state.locals.len() > u8::MAX as usize {
+            return Err("Too many local variables in function.".to_string());
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local { name: name.to_string(), depth, captured: false });
+        Ok(())
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<usize> {
+        self.functions[function].locals.iter().rposition(|l| l.name == name)
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: UpvalueRef) -> Result<usize, String> {
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(i) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(i);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err("Too many closure variables in function.".to_string());
+        }
+        upvalues.push(upvalue);
+        Ok(upvalues.len() - 1)
+    }
+
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Result<Option<usize>, String> {
+        if function == 0 {
+            return Ok(None);
+        }
+        if let Some(local) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[local].captured = true;
+            return self.add_upvalue(function, UpvalueRef { index: local, is_local: true }).map(Some);
+        }
+        match self.resolve_upvalue(function - 1, name)? {
+            Some(upvalue) => self.add_upvalue(function, UpvalueRef { index: upvalue, is_local: false }).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn variable(&mut self, name: &str, set: bool) -> Result<(), String> {
+        let function = self.functions.len() - 1;
+        let (op, operand) = if let Some(slot) = self.resolve_local(function, name) {
+            (if set { OpCode::SetLocal } else { OpCode::GetLocal }, slot)
+        } else if let Some(upvalue) = self.resolve_upvalue(function, name)? {
+            (if set { OpCode::SetUpvalue } else { OpCode::GetUpvalue }, upvalue)
+        } else {
+            let constant = self.name_constant(name)?;
+            self.emit(if set { OpCode::SetGlobal } else { OpCode::GetGlobal });
+            self.emit_u16(constant);
+            return Ok(());
+        };
+        self.emit(op);
+        self.emit_byte(operand);
+        Ok(())
+    }
+
+    // Defines the value on top of the stack as `name` in the current scope.
+    fn define(&mut self, name: &str, constant: bool) -> Result<(), String> {
+        if self.current().scope_depth > 0 {
+            return self.add_local(name);
+        }
+        let index = self.name_constant(name)?;
+        self.emit(if constant { OpCode::DefineConstant } else { OpCode::DefineGlobal });
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        let previous_line = self.line;
+        if let Some(line) = stmt.line() {
+            self.line = line;
+        }
+        self.stmt_body(stmt)?;
+        self.line = previous_line;
+        Ok(())
+    }
+
+    fn stmt_body(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                for s in statements {
+                    self.stmt(s)?;
+                }
+                self.end_scope();
+            },
+            Stmt::Class { name, methods } => {
+                let constant = self.name_constant(&name.lexeme)?;
+                self.emit(OpCode::Class);
+                self.emit_u16(constant);
+                self.define(&name.lexeme, false)?;
+                self.variable(&name.lexeme, false)?;
+                for m in methods {
+                    if let Stmt::Function { name: method, .. } = m {
+                        let kind = if method.lexeme == "init" { FunctionKind::Initializer } else { FunctionKind::Method };
+                        self.function(m, kind)?;
+                        let constant = self.name_constant(&method.lexeme)?;
+                        self.emit(OpCode::Method);
+                        self.emit_u16(constant);
+                    }
+                }
+                self.emit(OpCode::Pop);
+            },
+            Stmt::Const { name, initializer, .. } => {
+                self.expr(initializer)?;
+                self.define(&name.lexeme, true)?;
+            },
+            Stmt::Enum { name, variants } => {
+                let name_str = self.intern(&name.lexeme);
+                let variants = variants.iter()
+                    .map(|(v, fields)| (self.intern(&v.lexeme), fields.len()))
+                    .collect();
+                self.emit_constant(Value::Enum(Rc::new(EnumDef { name: name_str, variants })))?;
+                self.define(&name.lexeme, false)?;
+            },
+            Stmt::Expression { expression } => {
+                self.expr(expression)?;
+                self.emit(OpCode::Pop);
+            },
+            Stmt::Function { name, .. } => {
+                // A local function is in scope inside its own body, so it can
+                // call itself.
+                if self.current().scope_depth > 0 {
+                    self.add_local(&name.lexeme)?;
+                    self.function(stmt, FunctionKind::Function)?;
+                } else {
+                    self.function(stmt, FunctionKind::Function)?;
+                    self.define(&name.lexeme, false)?;
+                }
+            },
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.expr(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.stmt(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(e) = else_branch {
+                    self.stmt(e)?;
+                }
+                self.patch_jump(else_jump)?;
+            },
+            Stmt::Match { subject, arms, .. } => self.match_stmt(subject, arms)?,
+            Stmt::Print { expression, .. } => {
+                self.expr(expression)?;
+                self.emit(OpCode::Print);
+            },
+            Stmt::Return { value, .. } => {
+                match value {
+                    Some(v) if self.current().kind == FunctionKind::Initializer => {
+                        // `init` always hands back the instance.
+                        self.expr(v)?;
+                        self.emit(OpCode::Pop);
+                        self.emit_return();
+                    },
+                    Some(Expr::Call { callee, arguments, .. }) if self.current().kind != FunctionKind::Script => {
+                        self.call(callee, arguments, OpCode::TailCall)?;
+                        // Only reached when the callee was not a function.
+                        self.emit(OpCode::Return);
+                    },
+                    Some(v) => {
+                        self.expr(v)?;
+                        self.emit(OpCode::Return);
+                    },
+                    None => self.emit_return()
+                }
+            },
+            Stmt::While { condition, body, .. } => {
+                let start = self.current().chunk.code.len();
+                self.expr(condition)?;
+                let exit = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.stmt(body)?;
+                self.emit_loop(start)?;
+                self.patch_jump(exit)?;
+                self.emit(OpCode::Pop);
+            },
+            Stmt::Var { name, initializer, .. } => {
+                match initializer {
+                    Some(i) => self.expr(i)?,
+                    None => self.emit(OpCode::Nil)
+                }
+                self.define(&name.lexeme, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Compiles a function body and emits the closure that creates it.
+    fn function(&mut self, stmt: &Stmt, kind: FunctionKind) -> Result<(), String> {
+        let (name, params, body) = match stmt {
+            Stmt::Function { name, params, body, .. } => (name, params, body),
+            _ => return Err("Expected a function.".to_string())
+        };
+        self.begin_function(kind, &name.lexeme);
+        self.current().arity = params.len();
+        self.begin_scope();
+        for p in params {
+            self.add_local(&p.lexeme)?;
+        }
+        for s in body {
+            self.stmt(s)?;
+        }
+        let (function, upvalues) = self.end_function();
+        let constant = self.constant(Value::Function(Rc::new(function)))?;
+        self.emit(OpCode::Closure);
+        self.emit_u16(constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as usize);
+            self.emit_byte(upvalue.index);
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, callee: &Expr, arguments: &[Expr], op: OpCode) -> Result<(), String> {
+        self.expr(callee)?;
+        for a in arguments {
+            self.expr(a)?;
+        }
+        self.emit(op);
+        self.emit_byte(arguments.len());
+        Ok(())
+    }
+
+    // The subject is kept in a hidden local. Each arm tests it, binds its
+    // fields to locals and runs its body, then jumps past the other arms.
+    fn match_stmt(&mut self, subject: &Expr, arms: &[(Pattern, Stmt)]) -> Result<(), String> {
+        self.begin_scope();
+        self.expr(subject)?;
+        self.add_local("")?;
+        let slot = self.current().locals.len() - 1;
+        let mut exits = Vec::new();
+        for (pattern, body) in arms {
+            match pattern {
+                Pattern::Wildcard { .. } => {
+                    self.stmt(body)?;
+                    exits.push(self.emit_jump(OpCode::Jump));
+                },
+                Pattern::Variant { enum_name, name, bindings } => {
+                    self.emit(OpCode::GetLocal);
+                    self.emit_byte(slot);
+                    let variant = self.name_constant(&name.lexeme)?;
+                    let enum_name = match enum_name {
+                        Some(e) => self.name_constant(&e.lexeme)?,
+                        None => NO_ENUM
+                    };
+                    self.emit(OpCode::TestVariant);
+                    self.emit_u16(variant);
+                    self.emit_u16(enum_name);
+                    self.emit_byte(bindings.len());
+                    let next = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit(OpCode::Pop);
+                    self.emit(OpCode::Pop);
+                    self.begin_scope();
+                    for (i, b) in bindings.iter().enumerate() {
+                        self.emit(OpCode::GetLocal);
+                        self.emit_byte(slot);
+                        self.emit(OpCode::VariantField);
+                        self.emit_byte(i);
+                        self.add_local(&b.lexeme)?;
+                    }
+                    self.stmt(body)?;
+                    self.end_scope();
+                    exits.push(self.emit_jump(OpCode::Jump));
+                    self.patch_jump(next)?;
+                    self.emit(OpCode::Pop);
+                    self.emit(OpCode::Pop);
+                }
+            }
+        }
+        self.emit(OpCode::GetLocal);
+        self.emit_byte(slot);
+        self.emit(OpCode::NoMatch);
+        for exit in exits {
+            self.patch_jump(exit)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.expr(value)?;
+                self.variable(&name.lexeme, true)?;
+            },
+            Expr::Binary { left, operator, right } => {
+                self.expr(left)?;
+                self.expr(right)?;
+                let op = match operator.kind {
+                    TokenType::BANG_EQUAL => {
+                        self.emit(OpCode::Equal);
+                        OpCode::Not
+                    },
+                    TokenType::EQUAL_EQUAL => OpCode::Equal,
+                    TokenType::GREATER => OpCode::Greater,
+                    TokenType::GREATER_EQUAL => OpCode::GreaterEqual,
+                    TokenType::LESS => OpCode::Less,
+                    TokenType::LESS_EQUAL => OpCode::LessEqual,
+                    TokenType::PLUS => OpCode::Add,
+                    TokenType::MINUS => OpCode::Subtract,
+                    TokenType::STAR => OpCode::Multiply,
+                    TokenType::SLASH => OpCode::Divide,
+                    _ => return Err("Invalid operator.".to_string())
+                };
+                self.emit(op);
+            },
+            Expr::Call { callee, arguments, .. } => self.call(callee, arguments, OpCode::Call)?,
+            Expr::Get { object, name } => {
+                self.expr(object)?;
+                let constant = self.name_constant(&name.lexeme)?;
+                self.emit(OpCode::GetProperty);
+                self.emit_u16(constant);
+            },
+            Expr::Grouping { expression } => self.expr(expression)?,
+            Expr::Literal { value } => {
+                match value {
+                    LiteralValue::NullVal => self.emit(OpCode::Nil),
+                    LiteralValue::BooleanVal(true) => self.emit(OpCode::True),
+                    LiteralValue::BooleanVal(false) => self.emit(OpCode::False),
+                    LiteralValue::FloatVal(f) => self.emit_constant(Value::Number(*f))?,
+                    LiteralValue::NumberVal(n) => self.emit_constant(Value::Number(*n as f64))?,
+                    other => {
+                        let s = self.intern(&other.to_string());
+                        self.emit_constant(Value::String(s))?;
+                    }
+                }
+            },
+            Expr::Logical { left, operator, right } => {
+                self.expr(left)?;
+                if operator.kind == TokenType::OR {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit(OpCode::Pop);
+                    self.expr(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit(OpCode::Pop);
+                    self.expr(right)?;
+                    self.patch_jump(end_jump)?;
+                }
+            },
+            Expr::Set { object, name, value } => {
+                self.expr(object)?;
+                self.expr(value)?;
+                let constant = self.name_constant(&name.lexeme)?;
+                self.emit(OpCode::SetProperty);
+                self.emit_u16(constant);
+            },
+            Expr::This { keyword, .. } => {
+                // Outside a method the lookup fails, naming the keyword as
+                // the dialect spells it.
+                let in_method = self.functions.iter().any(|f| f.locals.iter().any(|l| l.name == "this"));
+                self.variable(if in_method { "this" } else { &keyword.lexeme }, false)?
+            },
+            Expr::Unary { operator, right } => {
+                self.expr(right)?;
+                self.emit(if operator.kind == TokenType::BANG { OpCode::Not } else { OpCode::Negate });
+            },
+            Expr::Variable { name, .. } => self.variable(&name.lexeme, false)?
+        }
+        Ok(())
+    }
+}
+
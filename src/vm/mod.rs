@@ -0,0 +1,6 @@
+pub mod chunk;
+pub mod value;
+pub mod compiler;
+pub mod natives;
+#[allow(clippy::module_inception)]
+pub mod vm;
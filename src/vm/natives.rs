@@ -0,0 +1,47 @@
+use crate::interpreter::natives::{environment_variable, file_contents, seconds_since_epoch};
+use crate::vm::value::Value;
+
+pub type NativeFn = fn(Vec<Value>) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: NativeFn,
+    // Only defined once the embedder allows host access.
+    pub host: bool
+}
+
+// The tree-walker's natives, defined as globals of every VM apart from the
+// `host` ones.
+pub const NATIVES: &[Native] = &[
+    Native { name: "clock", arity: 0, function: clock, host: false },
+    Native { name: "gc", arity: 0, function: gc, host: false },
+    Native { name: "read_file", arity: 1, function: read_file, host: true },
+    Native { name: "env", arity: 1, function: env, host: true }
+];
+
+fn string_argument(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(s) => Some(s),
+        _ => None
+    }
+}
+
+fn clock(_arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Number(seconds_since_epoch()?))
+}
+
+// Values are freed as soon as nothing refers to them, and there is no
+// collector to run for cycles, so cycles are never freed. Failing keeps a
+// script from assuming they were.
+fn gc(_arguments: Vec<Value>) -> Result<Value, String> {
+    Err("gc() is only supported by the tree backend.".to_string())
+}
+
+fn read_file(arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::String(file_contents(string_argument(&arguments[0]))?.into()))
+}
+
+fn env(arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(environment_variable(string_argument(&arguments[0]))?.map_or(Value::Nil, |v| Value::String(v.into())))
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+use crate::vm::chunk::Chunk;
+use crate::vm::natives::Native;
+
+// Values on the VM's stack. Everything but numbers, booleans and nil lives
+// behind an `Rc`, so copying a value never copies an object.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    // Only found in constant tables; `Closure` turns it into a closure.
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+    Enum(Rc<EnumDef>),
+    // A variant with fields that has not been given them yet.
+    VariantCtor(Rc<EnumDef>, usize),
+    Variant(Rc<Variant>),
+    Native(&'static Native)
+}
+
+pub struct Function {
+    // For tracebacks; "script" for the top level.
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>
+}
+
+// A captured variable. It points into the stack until the variable's scope
+// ends and is then closed over, holding the value itself.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value)
+}
+
+pub struct Class {
+    pub name: Rc<str>,
+    pub methods: RefCell<HashMap<Rc<str>, Rc<Closure>>>
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Rc<str>, Value>>
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>
+}
+
+pub struct EnumDef {
+    pub name: Rc<str>,
+    // Each variant's name and field count, in declaration order.
+    pub variants: Vec<(Rc<str>, usize)>
+}
+
+pub struct Variant {
+    pub enum_def: Rc<EnumDef>,
+    pub index: usize,
+    pub fields: Vec<Value>
+}
+
+// Dropping nested variants, like a long cons list, one inside the other would
+// take a host stack frame per level, so they are unpacked onto a list instead.
+impl Drop for Variant {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.fields);
+        while let Some(value) = pending.pop() {
+            if let Value::Variant(variant) = value {
+                if let Ok(mut variant) = Rc::try_unwrap(variant) {
+                    pending.append(&mut variant.fields);
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty(),
+            _ => true
+        }
+    }
+
+    // Same rules as `LiteralValue::is_equal`: objects other than variants
+    // and natives are never equal, not even to themselves.
+    pub fn is_equal(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Native(a), Value::Native(b)) => a.name == b.name,
+            (Value::Variant(a), Value::Variant(b)) => {
+                a.enum_def.name == b.enum_def.name
+                    && a.enum_def.variants[a.index].0 == b.enum_def.variants[b.index].0
+                    && a.fields.len() == b.fields.len()
+                    && a.fields.iter().zip(&b.fields).all(|(a, b)| a.is_equal(b))
+            },
+            _ => false
+        }
+    }
+}
+
+// Matches how the tree-walker prints `LiteralValue`s.
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Function(_) | Value::Closure(_) | Value::BoundMethod(_) => write!(f, "function"),
+            Value::Class(_) => write!(f, "class"),
+            Value::Instance(i) => write!(f, "{}", i.class.name),
+            Value::Enum(_) => write!(f, "enum"),
+            Value::VariantCtor(_, _) => write!(f, "variant"),
+            Value::Native(_) => write!(f, "native function"),
+            Value::Variant(v) => {
+                let name = &v.enum_def.variants[v.index].0;
+                if v.fields.is_empty() {
+                    write!(f, "{}", name)
+                } else {
+                    let fields: Vec<String> = v.fields.iter().map(|f| f.to_string()).collect();
+                    write!(f, "{}({})", name, fields.join(", "))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+use crate::interpreter::interpreter::{stack_overflow, Frame, DEFAULT_MAX_CALL_DEPTH};
+use crate::vm::chunk::{OpCode, NO_ENUM};
+use crate::vm::natives::NATIVES;
+use crate::vm::value::{BoundMethod, Class, Closure, Function, Instance, Upvalue, Value, Variant};
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of slot 0, the callee or receiver.
+    base: usize
+}
+
+// A stack-based virtual machine running the compiler's bytecode.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    constants: HashSet<Rc<str>>,
+    // Upvalues still pointing into the stack, ordered by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // Where `chatting` writes to; stdout unless a tool redirects it.
+    pub output: Rc<RefCell<dyn Write>>,
+    // Same limit as the tree-walker's.
+    pub max_call_depth: usize
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        let mut vm = Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            constants: HashSet::new(),
+            open_upvalues: Vec::new(),
+            output: Rc::new(RefCell::new(io::stdout())),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH
+        };
+        vm.define_natives(false);
+        vm
+    }
+
+    // Also defines the natives that touch files, processes or the network,
+    // as `Interpreter::allow_host_access` does.
+    pub fn allow_host_access(&mut self) {
+        self.define_natives(true);
+    }
+
+    fn define_natives(&mut self, host: bool) {
+        for native in NATIVES.iter().filter(|n| n.host == host) {
+            self.globals.insert(native.name.into(), Value::Native(native));
+        }
+    }
+
+    // Runs a compiled script. Errors read like the tree-walker's:
+    // the message, then the line it happened on.
+    pub fn interpret(&mut self, function: Function) -> Result<(), String> {
+        let closure = Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() });
+        self.stack.push(Value::Closure(closure.clone()));
+        self.frames.push(CallFrame { closure, ip: 0, base: 0 });
+        let result = self.run();
+        if let Err(e) = result {
+            let line = self.frames.last().map(|f| f.closure.function.chunk.lines[f.ip.saturating_sub(1)]).unwrap_or(0);
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            return Err(format!("{}\n[line {}]", e, line));
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_byte(&mut self) -> usize {
+        let frame = self.frame();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte as usize
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let frame = self.frame();
+        let value = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16();
+        self.frame().closure.function.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Value::String(s) => s,
+            _ => unreachable!("name operands are always strings")
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn numbers(&mut self) -> Result<(f64, f64), String> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                let operands = (*a, *b);
+                self.stack.truncate(self.stack.len() - 2);
+                Ok(operands)
+            },
+            _ => Err("Operands must be two numbers.".to_string())
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        loop {
+            let op = OpCode::from_byte(self.read_byte() as u8);
+            match op {
+                OpCode::Constant => {
+                    let value = self.read_constant();
+                    self.stack.push(value);
+                },
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                },
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() + self.frame().base;
+                    self.stack.push(self.stack[slot].clone());
+                },
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() + self.frame().base;
+                    self.stack[slot] = self.peek(0).clone();
+                },
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(v) => self.stack.push(v.clone()),
+                        None => return Err(format!("Undefined variable '{}'.", name))
+                    }
+                },
+                OpCode::DefineGlobal | OpCode::DefineConstant => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    if self.constants.contains(&name) {
+                        return Err(format!("Cannot redeclare constant '{}'.", name));
+                    }
+                    if let OpCode::DefineConstant = op {
+                        self.constants.insert(name.clone());
+                    }
+                    self.globals.insert(name, value);
+                },
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    if self.constants.contains(&name) {
+                        return Err(format!("Cannot assign to constant '{}'.", name));
+                    }
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(v) => *v = value,
+                        None => return Err(format!("Undefined variable '{}'.", name))
+                    }
+                },
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte();
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone()
+                    };
+                    self.stack.push(value);
+                },
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte();
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let value = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value
+                    };
+                },
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let value = self.get_property(self.peek(0).clone(), &name)?;
+                    self.pop();
+                    self.stack.push(value);
+                },
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            instance.fields.borrow_mut().insert(name, value.clone());
+                        },
+                        _ => return Err("Only instances have fields.".to_string())
+                    }
+                    self.stack.push(value);
+                },
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Value::Bool(a.is_equal(&b)));
+                },
+                OpCode::Greater => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Bool(a > b));
+                },
+                OpCode::GreaterEqual => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Bool(a >= b));
+                },
+                OpCode::Less => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Bool(a < b));
+                },
+                OpCode::LessEqual => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Bool(a <= b));
+                },
+                OpCode::Add => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    match (a, b) {
+                        (Value::Number(a), Value::Number(b)) => self.stack.push(Value::Number(a + b)),
+                        (Value::String(a), Value::String(b)) => self.stack.push(Value::String(Rc::from(format!("{}{}", a, b)))),
+                        _ => return Err("Operands must be two numbers or two strings.".to_string())
+                    }
+                },
+                OpCode::Subtract => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Number(a - b));
+                },
+                OpCode::Multiply => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Number(a * b));
+                },
+                OpCode::Divide => {
+                    let (a, b) = self.numbers()?;
+                    self.stack.push(Value::Number(a / b));
+                },
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                },
+                OpCode::Negate => {
+                    match self.pop() {
+                        Value::Number(n) => self.stack.push(Value::Number(-n)),
+                        _ => return Err("Invalid operand.".to_string())
+                    }
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.output.borrow_mut(), "{}", value).map_err(|e| e.to_string())?;
+                },
+                OpCode::Jump => {
+                    let offset = self.read_u16();
+                    self.frame().ip += offset;
+                },
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16();
+                    if !self.peek(0).is_truthy() {
+                        self.frame().ip += offset;
+                    }
+                },
+                OpCode::Loop => {
+                    let offset = self.read_u16();
+                    self.frame().ip -= offset;
+                },
+                OpCode::Call => {
+                    let count = self.read_byte();
+                    self.call_value(self.peek(count).clone(), count)?;
+                },
+                OpCode::TailCall => {
+                    let count = self.read_byte();
+                    let callee = self.peek(count).clone();
+                    // With the wrong number of arguments the call fails, and
+                    // the caller's frame is kept to report it.
+                    let replaces_frame = match &callee {
+                        Value::Closure(closure) => closure.function.arity == count,
+                        Value::BoundMethod(bound) => bound.method.function.arity == count,
+                        _ => false
+                    };
+                    if replaces_frame {
+                        let frame = self.frames.pop().unwrap();
+                        self.close_upvalues(frame.base);
+                        let start = self.stack.len() - count - 1;
+                        self.stack.drain(frame.base..start);
+                    }
+                    self.call_value(callee, count)?;
+                },
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Value::Function(f) => f,
+                        _ => unreachable!("closure operands are always functions")
+                    };
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte();
+                        if is_local {
+                            let slot = self.frame().base + index;
+                            upvalues.push(self.capture_upvalue(slot));
+                        } else {
+                            upvalues.push(self.frame().closure.upvalues[index].clone());
+                        }
+                    }
+                    self.stack.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                },
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                },
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                },
+                OpCode::Class => {
+                    let name = self.read_name();
+                    self.stack.push(Value::Class(Rc::new(Class { name, methods: RefCell::new(HashMap::new()) })));
+                },
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let method = match self.pop() {
+                        Value::Closure(c) => c,
+                        _ => unreachable!("methods are always closures")
+                    };
+                    if let Value::Class(class) = self.peek(0) {
+                        class.methods.borrow_mut().insert(name, method);
+                    }
+                },
+                OpCode::TestVariant => {
+                    let name = self.read_name();
+                    let enum_index = self.read_u16();
+                    let enum_name = if enum_index == NO_ENUM {
+                        None
+                    } else {
+                        match self.frame().closure.function.chunk.constants[enum_index].clone() {
+                            Value::String(s) => Some(s),
+                            _ => None
+                        }
+                    };
+                    let bindings = self.read_byte();
+                    let matched = match self.peek(0) {
+                        Value::Variant(v) => {
+                            let same = v.enum_def.variants[v.index].0 == name
+                                && enum_name.is_none_or(|e| e == v.enum_def.name);
+                            if same && bindings != v.fields.len() {
+                                return Err(format!("Pattern '{}' expects {} field(s) but variant has {}.", name, bindings, v.fields.len()));
+                            }
+                            same
+                        },
+                        _ => false
+                    };
+                    self.stack.push(Value::Bool(matched));
+                },
+                OpCode::VariantField => {
+                    let index = self.read_byte();
+                    let field = match self.pop() {
+                        Value::Variant(v) => v.fields[index].clone(),
+                        _ => unreachable!("fields are only read after a successful TestVariant")
+                    };
+                    self.stack.push(field);
+                },
+                OpCode::NoMatch => {
+                    return Err(format!("No pattern matched value '{}'.", self.peek(0)));
+                }
+            }
+        }
+    }
+
+    fn get_property(&self, object: Value, name: &Rc<str>) -> Result<Value, String> {
+        match object {
+            Value::Instance(instance) => {
+                if let Some(v) = instance.fields.borrow().get(name) {
+                    return Ok(v.clone());
+                }
+                match instance.class.methods.borrow().get(name) {
+                    Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                        receiver: Value::Instance(instance.clone()),
+                        method: method.clone()
+                    }))),
+                    None => Err(format!("Undefined property '{}'.", name))
+                }
+            },
+            Value::Enum(enum_def) => {
+                match enum_def.variants.iter().position(|(v, _)| v == name) {
+                    Some(index) if enum_def.variants[index].1 == 0 => {
+                        Ok(Value::Variant(Rc::new(Variant { enum_def: enum_def.clone(), index, fields: Vec::new() })))
+                    },
+                    Some(index) => Ok(Value::VariantCtor(enum_def.clone(), index)),
+                    None => Err(format!("Undefined variant '{}'.", name))
+                }
+            },
+            _ => Err("Only instances have properties.".to_string())
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, count: usize) -> Result<(), String> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, count),
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(bound.method.clone(), count)
+            },
+            Value::Class(class) => {
+                let slot = self.stack.len() - count - 1;
+                let instance = Instance { class: class.clone(), fields: RefCell::new(HashMap::new()) };
+                self.stack[slot] = Value::Instance(Rc::new(instance));
+                let init = class.methods.borrow().get("init").cloned();
+                match init {
+                    Some(init) => self.call(init, count),
+                    None if count != 0 => Err(format!("Expected 0 arguments but got {}.", count)),
+                    None => Ok(())
+                }
+            },
+            Value::VariantCtor(enum_def, index) => {
+                let (name, arity) = enum_def.variants[index].clone();
+                if count != arity {
+                    return Err(format!("Variant '{}' expects {} field(s) but got {}.", name, arity, count));
+                }
+                let fields = self.stack.split_off(self.stack.len() - count);
+                self.pop();
+                self.stack.push(Value::Variant(Rc::new(Variant { enum_def, index, fields })));
+                Ok(())
+            },
+            Value::Native(native) => {
+                if count != native.arity {
+                    return Err(format!("Expected {} arguments but got {}.", native.arity, count));
+                }
+                let arguments = self.stack.split_off(self.stack.len() - count);
+                self.pop();
+                let result = (native.function)(arguments)?;
+                self.stack.push(result);
+                Ok(())
+            },
+            _ => Err("Can only call functions and classes.".to_string())
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, count: usize) -> Result<(), String> {
+        if count != closure.function.arity {
+            return Err(format!("Expected {} arguments but got {}.", closure.function.arity, count));
+        }
+        // The script's own frame is not a call.
+        if self.frames.len() > self.max_call_depth {
+            return Err(stack_overflow(self.max_call_depth, &self.call_stack()));
+        }
+        let base = self.stack.len() - count - 1;
+        self.frames.push(CallFrame { closure, ip: 0, base });
+        Ok(())
+    }
+
+    // The frames as the tree-walker would list them.
+    fn call_stack(&self) -> Vec<Frame> {
+        self.frames.iter().map(|f| Frame {
+            function: f.closure.function.name.to_string(),
+            line: f.closure.function.chunk.lines[f.ip.saturating_sub(1)]
+        }).collect()
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self.open_upvalues.iter().position(|u| matches!(*u.borrow(), Upvalue::Open(s) if s >= slot));
+        if let Some(i) = position {
+            let upvalue = &self.open_upvalues[i];
+            if matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot) {
+                return upvalue.clone();
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position.unwrap_or(self.open_upvalues.len()), upvalue.clone());
+        upvalue
+    }
+
+    // Moves every captured variable at or above `slot` off the stack.
+    fn close_upvalues(&mut self, slot: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let open_slot = match *upvalue.borrow() {
+                Upvalue::Open(s) if s >= slot => s,
+                _ => break
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[open_slot].clone());
+            self.open_upvalues.pop();
+        }
+    }
+}
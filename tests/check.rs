@@ -0,0 +1,85 @@
+// Runs `pepega check` on small scripts and checks its diagnostics.
+
+mod common;
+
+use common::{pepega, script};
+
+#[test]
+fn annotated_variable_without_initializer() {
+    let path = script("check", "uninitialized.pepega", "kekw x: number;\nx = 1;\nchatting x;\n");
+    let run = pepega(&["check", path.to_str().unwrap()]);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn annotated_variable_initialized_with_nil() {
+    let path = script("check", "nil_initializer.pepega", "kekw x: number = nil;\n");
+    let run = pepega(&["check", path.to_str().unwrap()]);
+    assert_eq!(run.stderr, "[line 1] Error: Cannot initialize 'x' of type number with a value of type nil.\n");
+    assert_eq!(run.status, 65);
+}
+
+fn check(name: &str, source: &str, strict: bool) -> (String, i32) {
+    let path = script("check", name, source);
+    let mut args = vec!["check"];
+    if strict {
+        args.push("--strict");
+    }
+    args.push(path.to_str().unwrap());
+    let run = pepega(&args);
+    assert_eq!(run.stdout, "");
+    (run.stderr, run.status)
+}
+
+#[test]
+fn operand_type_mismatch() {
+    assert_eq!(check("minus.pepega", "kekw x = \"a\" - 1;\n", false), (
+        "[line 1] Error: Operands of '-' must be numbers, found string and number.\n".to_string(), 65
+    ));
+    assert_eq!(check("plus.pepega", "\n\"a\" + 1;\n", false), (
+        "[line 2] Error: Operands of '+' must be two numbers or two strings, found string and number.\n".to_string(), 65
+    ));
+    assert_eq!(check("negate.pepega", "-\"a\";\n", false), (
+        "[line 1] Error: Operand of '-' must be a number, found string.\n".to_string(), 65
+    ));
+}
+
+#[test]
+fn wrong_arity() {
+    assert_eq!(check("arity.pepega", "pog f(a) { xdd a; }\nf(1, 2);\n", false), (
+        "[line 2] Error: Expected 1 arguments but got 2.\n".to_string(), 65
+    ));
+}
+
+#[test]
+fn calling_a_non_callable() {
+    assert_eq!(check("call.pepega", "kekw n: number = 1;\nn();\n", false), (
+        "[line 2] Error: Can only call functions and classes, found number.\n".to_string(), 65
+    ));
+}
+
+// Warnings are printed either way but only fail the check under `--strict`.
+#[test]
+fn warnings_fail_only_when_strict() {
+    let source = "chatting y;\nxdd 1;\n";
+    let warnings = "[line 1] Warning: Undefined variable 'y'.\n[line 2] Warning: Return outside of a function.\n".to_string();
+    assert_eq!(check("warnings.pepega", source, false), (warnings.clone(), 0));
+    assert_eq!(check("warnings.pepega", source, true), (warnings, 65));
+}
+
+#[test]
+fn clean_script() {
+    assert_eq!(check("clean.pepega", "pog f(a: number) -> number { xdd a * 2; }\nchatting f(2);\n", true), (String::new(), 0));
+}
+
+// A script that does not parse is reported instead of checked.
+#[test]
+fn syntax_error() {
+    assert_eq!(check("syntax.pepega", "kekw x = ;\nkekw y = ;\n", false), (
+        "[line 1] Error at ';': Expect expression.\n[line 2] Error at ';': Expect expression.\n".to_string(), 65
+    ));
+    assert_eq!(check("lex.pepega", "kekw x = 1 € 2;\n", false), (
+        "[line 1] Error: Unexpected character.\n".to_string(), 65
+    ));
+}
@@ -0,0 +1,98 @@
+// Helpers shared by the integration tests that drive the `pepega` binary.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use pepega::util::json::Json;
+
+// Longer than any run should take; a run that hangs fails instead.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Run {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String
+}
+
+// Writes `source` to a file named `name` in a directory of its own for the
+// calling test and returns its path.
+pub fn script(test: &str, name: &str, source: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(name);
+    fs::write(&path, source).unwrap();
+    path
+}
+
+// Runs `pepega` with `args` in `directory`, writing `stdin` to it.
+pub fn pepega_in(directory: &PathBuf, args: &[&str], stdin: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pepega"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let stdin = stdin.to_string();
+    let writer = thread::spawn(move || {
+        let _ = input.write_all(stdin.as_bytes());
+    });
+    let stdout = read_all(child.stdout.take().unwrap());
+    let stderr = read_all(child.stderr.take().unwrap());
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            panic!("pepega {} timed out after {} seconds", args.join(" "), TIMEOUT.as_secs());
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    writer.join().unwrap();
+    Run {
+        status: status.code().unwrap_or(-1),
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap()
+    }
+}
+
+// Reads everything from `stream` on a thread of its own, so a child writing a
+// lot never blocks on a full pipe.
+fn read_all(mut stream: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = stream.read_to_end(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+pub fn pepega(args: &[&str]) -> Run {
+    pepega_in(&PathBuf::from(env!("CARGO_MANIFEST_DIR")), args, "")
+}
+
+// Frames `message` the way LSP and DAP clients send it.
+pub fn frame(message: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+}
+
+// Splits the framed messages a server wrote back apart.
+pub fn messages(output: &str) -> Vec<Json> {
+    let mut messages = Vec::new();
+    let mut rest = output;
+    while !rest.is_empty() {
+        let (header, body) = rest.split_once("\r\n\r\n").unwrap();
+        let length: usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        messages.push(Json::parse(&body[..length]).unwrap());
+        rest = &body[length..];
+    }
+    messages
+}
@@ -0,0 +1,193 @@
+// Runs every script under tests/conformance and checks what it prints against
+// its annotations, in the style of the Crafting Interpreters test suite:
+//
+//     chatting 1 + 2; // expect: 3
+//     nil(); // expect runtime error: Can only call functions and classes.
+//
+// `// expect:` lines give stdout in order. A runtime error is expected on
+// stderr as the message followed by `[line N]` for the annotated line, with
+// exit status 70. Errors that print a traceback between the two list its lines
+// in order as `// expect traceback: ` comments. A compile error is annotated
+// with the message as printed after `[line N] `, like `// Error at 'a': ...`,
+// and expected on stderr for the annotated line with exit status 65. Anything
+// else must exit 0 with nothing on stderr.
+//
+// The suite runs on the tree-walking interpreter and on the bytecode VM
+// (`--backend vm`), each with and without the optimizer (`--opt-level 2`).
+// Scripts under tests/sandbox run on the tree-walker with `--sandbox` and
+// every budget set. Those under tests/gc call `gc()`, which only the
+// tree-walker supports; `gc_on_vm` checks the VM refuses it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Longer than any case should take; a script that hangs fails instead.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Cases that fail on the tree-walker because of known interpreter bugs,
+// relative to tests/conformance. A listed case that starts passing fails the
+// run so it is taken off the list.
+const TREE_WALKER_FAILURES: &[&str] = &[
+    // Instances are copied by value, so fields set on one copy are lost.
+    "class/bound_method.pepega",
+    "class/field_set_and_get.pepega",
+    "class/init_sets_fields.pepega",
+    "class/instances_share_identity.pepega",
+    "class/method_uses_this.pepega",
+    // Reading a method with `.` calls it straight away with no arguments.
+    "class/method.pepega",
+    "class/method_return.pepega",
+];
+
+struct Expectation {
+    stdout: String,
+    stderr: String,
+    status: i32
+}
+
+fn expectation(source: &str) -> Expectation {
+    let mut stdout = String::new();
+    let mut error = None;
+    let mut traceback = String::new();
+    let mut compile_errors = String::new();
+    for (i, line) in source.lines().enumerate() {
+        if let Some((_, text)) = line.split_once("// expect: ") {
+            stdout.push_str(text);
+            stdout.push('\n');
+        } else if let Some((_, message)) = line.split_once("// expect runtime error: ") {
+            error = Some((message, i + 1));
+        } else if let Some((_, text)) = line.split_once("// expect traceback: ") {
+            traceback.push_str(text);
+            traceback.push('\n');
+        } else if let Some((_, message)) = line.split_once("// Error") {
+            compile_errors.push_str(&format!("[line {}] Error{}\n", i + 1, message));
+        }
+    }
+    if !compile_errors.is_empty() {
+        return Expectation { stdout, stderr: compile_errors, status: 65 };
+    }
+    let stderr = match error {
+        Some((message, line)) => format!("{}\n{}[line {}]\n", message, traceback, line),
+        None => String::new()
+    };
+    let status = if stderr.is_empty() { 0 } else { 70 };
+    Expectation { stdout, stderr, status }
+}
+
+fn scripts(directory: &Path, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory).unwrap().map(|e| e.unwrap().path()).collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            scripts(&entry, found);
+        } else if entry.extension().is_some_and(|e| e == "pepega") {
+            found.push(entry);
+        }
+    }
+}
+
+// Why the script at `path` does not behave as annotated, if it doesn't.
+fn check(path: &Path, args: &[&str]) -> Option<String> {
+    let expected = expectation(&fs::read_to_string(path).unwrap());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pepega"))
+        .args(args)
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            return Some(format!("timed out after {} seconds", TIMEOUT.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = output.status.code().unwrap_or(-1);
+    let mut problems = Vec::new();
+    if stdout != expected.stdout {
+        problems.push(format!("stdout:\n  expected: {:?}\n  actual:   {:?}", expected.stdout, stdout));
+    }
+    if stderr != expected.stderr {
+        problems.push(format!("stderr:\n  expected: {:?}\n  actual:   {:?}", expected.stderr, stderr));
+    }
+    if status != expected.status {
+        problems.push(format!("exit status: expected {}, got {}", expected.status, status));
+    }
+    if problems.is_empty() { None } else { Some(problems.join("\n")) }
+}
+
+fn conformance(directory: &str, args: &[&str], known_failures: &[&str]) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(directory);
+    let mut found = Vec::new();
+    scripts(&root, &mut found);
+    assert!(!found.is_empty(), "no scripts found under {}", root.display());
+    let mut failures = Vec::new();
+    for path in &found {
+        let name = path.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/");
+        let known = known_failures.contains(&name.as_str());
+        match (check(path, args), known) {
+            (Some(problem), false) => failures.push(format!("{}\n{}", name, problem)),
+            (None, true) => failures.push(format!("{}\npasses now; remove it from the known failures", name)),
+            _ => ()
+        }
+    }
+    for name in known_failures {
+        if !root.join(name).exists() {
+            failures.push(format!("{}\nis a known failure but does not exist", name));
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} scripts failed:\n\n{}", failures.len(), found.len(), failures.join("\n\n"));
+}
+
+#[test]
+fn tree_walker() {
+    conformance("conformance", &[], TREE_WALKER_FAILURES);
+}
+
+#[test]
+fn tree_walker_optimized() {
+    conformance("conformance", &["--opt-level", "2"], TREE_WALKER_FAILURES);
+}
+
+#[test]
+fn vm() {
+    conformance("conformance", &["--backend", "vm"], &[]);
+}
+
+#[test]
+fn vm_optimized() {
+    conformance("conformance", &["--backend", "vm", "--opt-level", "2"], &[]);
+}
+
+#[test]
+fn sandbox() {
+    let args = ["--sandbox", "--max-steps", "100000", "--max-values", "1000", "--max-string-bytes", "10000", "--max-output", "1000"];
+    conformance("sandbox", &args, &[]);
+}
+
+#[test]
+fn gc() {
+    conformance("gc", &[], &[]);
+}
+
+#[test]
+fn gc_on_vm() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/gc/automatic.pepega");
+    let output = Command::new(env!("CARGO_BIN_EXE_pepega")).args(["--backend", "vm"]).arg(&path).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("gc() is only supported by the tree backend.\n"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(70));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pepega")).args(["--backend", "vm", "--gc-stats"]).arg(path).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "--gc-stats is only supported by the tree backend.\n");
+    assert_eq!(output.status.code(), Some(64));
+}
@@ -0,0 +1,82 @@
+// Runs a script under `run --coverage` and checks the summary and the LCOV
+// tracefile record by record.
+
+mod common;
+
+use std::fs;
+use common::{pepega_in, script};
+
+// Line 2's `clueless` is never true, the `and` on line 5 short-circuits, and
+// nothing in `unused` ever runs.
+const SOURCE: &str = "\
+kekw x = 1;
+clueless (x > 5) {
+    chatting \"big\";
+}
+clueless (x > 5 and x < 10) chatting \"medium\";
+pog unused() {
+    xdd x or 2;
+}
+chatting x;
+";
+
+#[test]
+fn lcov_records() {
+    let path = script("coverage", "branches.pepega", SOURCE);
+    let directory = path.parent().unwrap().to_path_buf();
+    let run = pepega_in(&directory, &["run", "--coverage", "--coverage-out", "branches.info", "branches.pepega"], "");
+    assert_eq!(run.status, 0);
+    assert_eq!(run.stdout, "1\n");
+    assert_eq!(run.stderr, "\
+Lines:    5/7 (71.4%)
+Branches: 3/8 (37.5%)
+Lines not run: 3, 7
+[line 2] 'clueless': the condition was never true
+[line 5] 'clueless': the condition was never true
+[line 5] 'and': the right side never ran
+[line 7] 'or': the right side never ran
+[line 7] 'or': it never short-circuited
+Coverage written to branches.info.
+");
+
+    // Branches that were never reached are `-` rather than 0.
+    let lcov = fs::read_to_string(directory.join("branches.info")).unwrap();
+    let source = fs::canonicalize(&path).unwrap();
+    assert_eq!(lcov, format!("\
+TN:
+SF:{}
+DA:1,1
+DA:2,1
+DA:3,0
+DA:5,1
+DA:6,1
+DA:7,0
+DA:9,1
+LF:7
+LH:5
+BRDA:2,0,0,0
+BRDA:2,0,1,1
+BRDA:5,0,0,0
+BRDA:5,0,1,1
+BRDA:5,1,0,0
+BRDA:5,1,1,1
+BRDA:7,0,0,-
+BRDA:7,0,1,-
+BRF:8
+BRH:3
+end_of_record
+", source.display()));
+}
+
+// A loop body on the loop's own line is counted once per iteration, as the
+// profiler counts it.
+#[test]
+fn loop_on_one_line() {
+    let path = script("coverage", "loop.pepega", "kekw i = 0;\nresidentsleeper (i < 3) i = i + 1;\n");
+    let directory = path.parent().unwrap().to_path_buf();
+    let run = pepega_in(&directory, &["run", "--coverage", "--coverage-out", "loop.info", "loop.pepega"], "");
+    assert_eq!(run.status, 0);
+    let lcov = fs::read_to_string(directory.join("loop.info")).unwrap();
+    let lines: Vec<&str> = lcov.lines().filter(|l| l.starts_with("DA:")).collect();
+    assert_eq!(lines, ["DA:1,1", "DA:2,3"]);
+}
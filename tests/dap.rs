@@ -0,0 +1,122 @@
+// Drives `pepega dap` with a scripted client over framed DAP messages.
+
+mod common;
+
+use pepega::util::json::Json;
+use common::{frame, messages, pepega_in, script};
+
+// `a` is a local of both the script's block and `add`, so each frame must
+// show and evaluate its own.
+const SOURCE: &str = "\
+pog add(a, b) {
+    kekw sum = a + b;
+    xdd sum;
+}
+{
+    kekw a = 5;
+    chatting add(1, 2) + a;
+}
+";
+
+fn request(seq: usize, command: &str, arguments: &str) -> String {
+    frame(&format!(r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#, seq, command, arguments))
+}
+
+fn text(json: &Json) -> String {
+    json.to_string()
+}
+
+fn body(message: &Json) -> String {
+    text(message.get("body").unwrap())
+}
+
+#[test]
+fn session() {
+    let path = script("dap", "add.pepega", SOURCE);
+    let program = Json::from(path.to_str().unwrap()).to_string();
+    let stdin = [
+        request(1, "initialize", r#"{"adapterID":"pepega"}"#),
+        request(2, "launch", &format!(r#"{{"program":{}}}"#, program)),
+        request(3, "setBreakpoints", &format!(r#"{{"source":{{"path":{}}},"breakpoints":[{{"line":2}}]}}"#, program)),
+        request(4, "configurationDone", "{}"),
+        request(5, "stackTrace", r#"{"threadId":1}"#),
+        request(6, "scopes", r#"{"frameId":0}"#),
+        request(7, "variables", r#"{"variablesReference":1}"#),
+        request(8, "variables", r#"{"variablesReference":2}"#),
+        request(9, "scopes", r#"{"frameId":1}"#),
+        request(10, "variables", r#"{"variablesReference":3}"#),
+        request(11, "evaluate", r#"{"expression":"a * 10","frameId":0}"#),
+        request(12, "evaluate", r#"{"expression":"a * 10","frameId":1}"#),
+        request(13, "evaluate", r#"{"expression":"a","frameId":2}"#),
+        request(14, "next", r#"{"threadId":1}"#),
+        request(15, "stackTrace", r#"{"threadId":1}"#),
+        request(16, "variables", r#"{"variablesReference":3}"#),
+        request(17, "continue", r#"{"threadId":1}"#),
+        request(18, "disconnect", "{}")
+    ].concat();
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["dap"], &stdin);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    let messages = messages(&run.stdout);
+    let summary: Vec<String> = messages.iter().map(|m| {
+        let kind = m.get("type").and_then(|t| t.as_str()).unwrap();
+        let name = m.get("command").or(m.get("event")).and_then(|n| n.as_str()).unwrap();
+        format!("{} {}", kind, name)
+    }).collect();
+    assert_eq!(summary, [
+        "response initialize", "event initialized", "response launch", "response setBreakpoints",
+        "response configurationDone", "event stopped",
+        "response stackTrace", "response scopes", "response variables", "response variables",
+        "response scopes", "response variables", "response evaluate", "response evaluate", "response evaluate",
+        "response next", "event stopped", "response stackTrace", "response variables",
+        "response continue", "event output", "event exited", "event terminated", "response disconnect"
+    ]);
+
+    let source = format!(r#"{{"name":"add.pepega","path":{}}}"#, program);
+    assert_eq!(body(&messages[3]), r#"{"breakpoints":[{"verified":true,"line":2}]}"#);
+    assert_eq!(body(&messages[5]), r#"{"reason":"breakpoint","threadId":1,"allThreadsStopped":true}"#);
+    assert_eq!(body(&messages[6]), format!(concat!(
+        r#"{{"stackFrames":[{{"id":1,"name":"add","source":{0},"line":2,"column":1}},"#,
+        r#"{{"id":0,"name":"script","source":{0},"line":7,"column":1}}],"totalFrames":2}}"#
+    ), source));
+
+    // The script's frame: its block's `a`, with `add` among the globals.
+    assert_eq!(body(&messages[7]), concat!(
+        r#"{"scopes":[{"name":"Locals","variablesReference":1,"expensive":false},"#,
+        r#"{"name":"Globals","variablesReference":2,"expensive":false}]}"#
+    ));
+    assert_eq!(body(&messages[8]), r#"{"variables":[{"name":"a","value":"5","variablesReference":0}]}"#);
+    let globals = messages[9].get("body").and_then(|b| b.get("variables")).and_then(|v| v.as_array()).unwrap();
+    let globals: Vec<&str> = globals.iter().filter_map(|v| v.get("name").and_then(|n| n.as_str())).collect();
+    assert!(globals.contains(&"add"), "{:?}", globals);
+    assert!(!globals.contains(&"a"), "{:?}", globals);
+
+    // The frame of `add`.
+    assert_eq!(body(&messages[10]), concat!(
+        r#"{"scopes":[{"name":"Locals","variablesReference":3,"expensive":false},"#,
+        r#"{"name":"Globals","variablesReference":4,"expensive":false}]}"#
+    ));
+    assert_eq!(body(&messages[11]), concat!(
+        r#"{"variables":[{"name":"a","value":"1","variablesReference":0},"#,
+        r#"{"name":"b","value":"2","variablesReference":0}]}"#
+    ));
+    assert_eq!(body(&messages[12]), r#"{"result":"50","variablesReference":0}"#);
+    assert_eq!(body(&messages[13]), r#"{"result":"10","variablesReference":0}"#);
+    assert_eq!(messages[14].get("success"), Some(&Json::Bool(false)));
+    assert_eq!(messages[14].get("message").and_then(|m| m.as_str()), Some("There is no frame 2."));
+
+    // `next` stays in `add`.
+    assert_eq!(body(&messages[16]), r#"{"reason":"step","threadId":1,"allThreadsStopped":true}"#);
+    assert_eq!(body(&messages[17]), format!(concat!(
+        r#"{{"stackFrames":[{{"id":1,"name":"add","source":{0},"line":3,"column":1}},"#,
+        r#"{{"id":0,"name":"script","source":{0},"line":7,"column":1}}],"totalFrames":2}}"#
+    ), source));
+    assert_eq!(body(&messages[18]), concat!(
+        r#"{"variables":[{"name":"a","value":"1","variablesReference":0},"#,
+        r#"{"name":"b","value":"2","variablesReference":0},{"name":"sum","value":"3","variablesReference":0}]}"#
+    ));
+
+    assert_eq!(body(&messages[20]), r#"{"category":"stdout","output":"8\n"}"#);
+    assert_eq!(body(&messages[21]), r#"{"exitCode":0}"#);
+    assert_eq!(body(&messages[22]), "{}");
+}
@@ -0,0 +1,73 @@
+// Drives `pepega debug` with a scripted stdin session and checks the
+// transcript.
+
+mod common;
+
+use common::{pepega_in, script};
+
+const SOURCE: &str = "\
+pog add(a, b) {
+    kekw sum = a + b;
+    xdd sum;
+}
+kekw total = 0;
+total = add(1, 2);
+chatting total;
+total = add(total, 10);
+chatting total;
+";
+
+#[test]
+fn session() {
+    let path = script("debugger", "add.pepega", SOURCE);
+    let commands = [
+        "b 2",           // break on the first line of `add`
+        "b",             // list breakpoints
+        "c",             // run to it
+        "bt",
+        "p a * 10",
+        "n",             // over to line 3
+        "p sum",
+        "o",             // out of `add`, back in the script after the call
+        "n",             // runs `chatting total;`
+        "p total = 100", // assignment through print
+        "s",             // into `add` again
+        "s",
+        "bt",
+        "d 2",
+        "c"
+    ];
+    let stdin: String = commands.iter().map(|c| format!("{}\n", c)).collect();
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["debug", "add.pepega"], &stdin);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    assert_eq!(run.stdout, "\
+[line 1] pog add(a, b) {
+(pepega) (pepega) line 2: kekw sum = a + b;
+(pepega) [line 2] kekw sum = a + b;
+(pepega) #0 add at line 2
+#1 script at line 6
+(pepega) 10
+(pepega) [line 3] xdd sum;
+(pepega) 3
+(pepega) [line 7] chatting total;
+(pepega) 3
+[line 8] total = add(total, 10);
+(pepega) 100
+(pepega) [line 2] kekw sum = a + b;
+(pepega) [line 3] xdd sum;
+(pepega) #0 add at line 3
+#1 script at line 8
+(pepega) (pepega) 110
+Program finished.
+");
+}
+
+#[test]
+fn break_option_and_quit() {
+    let path = script("debugger", "quit.pepega", SOURCE);
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["debug", "--break", "7", "quit.pepega"], "p total\nq\n");
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    assert_eq!(run.stdout, "[line 7] chatting total;\n(pepega) 3\n(pepega) ");
+}
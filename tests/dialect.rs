@@ -0,0 +1,25 @@
+// Runs scripts written in a dialect loaded from a file.
+
+mod common;
+
+use common::{pepega, script};
+
+// The pepega dialect with `this` spelled `me`.
+fn me_dialect() -> String {
+    let pepega = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Dialects/pepega.json")).unwrap();
+    let dialect = pepega.replace("\"this\": \"this\"", "\"this\": \"me\"");
+    script("dialect", "me.json", &dialect).to_str().unwrap().to_string()
+}
+
+#[test]
+fn this_keeps_its_spelling() {
+    let dialect = me_dialect();
+    let path = script("dialect", "me.pepega", "gigachad A {\n    f() {\n        xdd me;\n    }\n}\nchatting me;\n");
+    for backend in ["tree", "vm"] {
+        let run = pepega(&["--dialect", &dialect, "--backend", backend, path.to_str().unwrap()]);
+        assert_eq!(run.stderr, "Undefined variable 'me'.\n[line 6]\n", "{}", backend);
+        assert_eq!(run.status, 70, "{}", backend);
+    }
+    let run = pepega(&["--dialect", &dialect, "--ast", path.to_str().unwrap()]);
+    assert!(run.stdout.contains("(return me)"), "{}", run.stdout);
+}
@@ -0,0 +1,73 @@
+// Runs the `--tokens`, `--ast` and `--ast-json` front-end dump modes.
+
+mod common;
+
+use pepega::util::json::Json;
+use common::{pepega, script};
+
+const SOURCE: &str = "kekw x: number = 1 + 2;\nclueless (x > 2) chatting \"big\"; aware chatting nil;\n";
+
+fn dump(mode: &str) -> String {
+    let path = script("dump", "dump.pepega", SOURCE);
+    let run = pepega(&[mode, path.to_str().unwrap()]);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    run.stdout
+}
+
+#[test]
+fn tokens() {
+    assert_eq!(dump("--tokens"), "\
+VAR kekw nil
+IDENTIFIER x nil
+COLON : nil
+IDENTIFIER number nil
+EQUAL = nil
+NUMBER 1 1
+PLUS + nil
+NUMBER 2 2
+SEMICOLON ; nil
+IF clueless nil
+LEFT_PAREN ( nil
+IDENTIFIER x nil
+GREATER > nil
+NUMBER 2 2
+RIGHT_PAREN ) nil
+PRINT chatting nil
+STRING \"big\" big
+SEMICOLON ; nil
+ELSE aware nil
+PRINT chatting nil
+NIL nil nil
+SEMICOLON ; nil
+EOF  nil
+");
+}
+
+#[test]
+fn ast() {
+    assert_eq!(dump("--ast"), "\
+(var x: number (+ 1 2))
+(if (> x 2)
+  (print \"big\")
+  (print nil))
+");
+}
+
+#[test]
+fn ast_json() {
+    let output = dump("--ast-json");
+    assert_eq!(output, concat!(
+        r#"[{"type":"Var","name":"x","line":1,"annotation":"number","initializer":"#,
+        r#"{"type":"Binary","operator":"+","line":1,"left":{"type":"Literal","value":1},"right":{"type":"Literal","value":2}}},"#,
+        r#"{"type":"If","condition":{"type":"Binary","operator":">","line":2,"left":{"type":"Variable","name":"x","line":2},"#,
+        r#""right":{"type":"Literal","value":2}},"then":{"type":"Print","expression":{"type":"Literal","value":"big"}},"#,
+        r#""else":{"type":"Print","expression":{"type":"Literal","value":null}}}]"#,
+        "\n"
+    ));
+    let json = Json::parse(output.trim_end()).unwrap();
+    let statements = json.as_array().unwrap();
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0].get("type").and_then(|t| t.as_str()), Some("Var"));
+    assert_eq!(statements[1].get("else").and_then(|e| e.get("type")).and_then(|t| t.as_str()), Some("Print"));
+}
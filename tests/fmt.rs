@@ -0,0 +1,82 @@
+// Formats every script under tests/fmt and compares the result with the
+// `.formatted` file next to it. Formatting that output again, or checking
+// it with `--check`, must leave it alone.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use common::{pepega, script};
+
+fn cases() -> Vec<(String, String, String)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fmt");
+    let mut paths: Vec<_> = fs::read_dir(&root).unwrap().map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "pepega"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scripts found under {}", root.display());
+    paths.iter().map(|p| {
+        let name = p.file_name().unwrap().to_string_lossy().into_owned();
+        (name, fs::read_to_string(p).unwrap(), fs::read_to_string(p.with_extension("formatted")).unwrap())
+    }).collect()
+}
+
+#[test]
+fn golden() {
+    for (name, source, expected) in cases() {
+        let path = script("fmt", &name, &source);
+        let run = pepega(&["fmt", path.to_str().unwrap()]);
+        assert_eq!(run.stderr, "", "{}", name);
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn idempotent() {
+    for (name, _, expected) in cases() {
+        let path = script("fmt_again", &name, &expected);
+        let run = pepega(&["fmt", path.to_str().unwrap()]);
+        assert_eq!(run.status, 0, "{}", name);
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected, "{}", name);
+        let run = pepega(&["fmt", "--check", path.to_str().unwrap()]);
+        assert_eq!((run.status, run.stdout.as_str()), (0, ""), "{}", name);
+    }
+}
+
+#[test]
+fn check_leaves_files_alone() {
+    for (name, source, _) in cases() {
+        let path = script("fmt_check", &name, &source);
+        let run = pepega(&["fmt", "--check", path.to_str().unwrap()]);
+        assert_eq!(run.status, 1, "{}", name);
+        assert_eq!(run.stdout, format!("Would reformat: {}\n", path.display()), "{}", name);
+        assert_eq!(fs::read_to_string(&path).unwrap(), source, "{}", name);
+    }
+}
+
+// A file that does not parse is reported and left alone, and the files after
+// it are still formatted.
+#[test]
+fn file_that_does_not_parse() {
+    let broken = script("fmt_broken", "broken.pepega", "kekw x = ;\nchatting   x;\n");
+    let good = script("fmt_broken", "good.pepega", "chatting   1;\n");
+    for check in [false, true] {
+        fs::write(&good, "chatting   1;\n").unwrap();
+        let mut args = vec!["fmt"];
+        if check {
+            args.push("--check");
+        }
+        args.extend([broken.to_str().unwrap(), good.to_str().unwrap()]);
+        let run = pepega(&args);
+        assert_eq!(run.stderr, format!("Cannot format '{}':\n[line 1] Error at ';': Expect expression.\n", broken.display()));
+        assert_eq!(run.status, 65);
+        assert_eq!(fs::read_to_string(&broken).unwrap(), "kekw x = ;\nchatting   x;\n");
+        if check {
+            assert_eq!(run.stdout, format!("Would reformat: {}\n", good.display()));
+            assert_eq!(fs::read_to_string(&good).unwrap(), "chatting   1;\n");
+        } else {
+            assert_eq!(run.stdout, "");
+            assert_eq!(fs::read_to_string(&good).unwrap(), "chatting 1;\n");
+        }
+    }
+}
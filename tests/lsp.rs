@@ -0,0 +1,108 @@
+// Drives `pepega lsp` with a scripted client over framed JSON-RPC.
+
+mod common;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use pepega::util::json::Json;
+use common::{frame, messages, pepega_in};
+
+// Line 0 has two characters outside the Basic Multilingual Plane, which take
+// two UTF-16 code units each, so `naïve` starts at character 22 on the wire.
+const DOCUMENT: &str = "chatting \"🐸🐸\"; kekw naïve = 1;\nchatting naïve;\n";
+const BROKEN: &str = "chatting \"🐸\" + ;\n";
+
+fn open(uri: &str, text: &str) -> String {
+    let text = Json::from(text).to_string();
+    format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","languageId":"pepega","version":1,"text":{}}}}}}}"#, uri, text)
+}
+
+fn at(id: usize, method: &str, line: usize, character: usize) -> String {
+    format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{{"textDocument":{{"uri":"file:///a.pepega"}},"position":{{"line":{},"character":{}}}}}}}"#, id, method, line, character)
+}
+
+fn text(json: &Json) -> String {
+    json.to_string()
+}
+
+#[test]
+fn session() {
+    let script = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+        open("file:///a.pepega", DOCUMENT),
+        at(2, "textDocument/hover", 1, 10),
+        at(3, "textDocument/definition", 1, 10),
+        r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/semanticTokens/full","params":{"textDocument":{"uri":"file:///a.pepega"}}}"#.to_string(),
+        at(5, "textDocument/hover", 0, 17),
+        open("file:///b.pepega", BROKEN),
+        r#"{"jsonrpc":"2.0","id":6,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string()
+    ];
+    let stdin: String = script.iter().map(|m| frame(m)).collect();
+    let run = pepega_in(&PathBuf::from(env!("CARGO_MANIFEST_DIR")), &["lsp"], &stdin);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    let messages = messages(&run.stdout);
+    assert_eq!(messages.len(), 8, "{}", run.stdout);
+
+    let capabilities = messages[0].get("result").and_then(|r| r.get("capabilities")).unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    assert_eq!(capabilities.get("definitionProvider"), Some(&Json::Bool(true)));
+    assert!(capabilities.get("semanticTokensProvider").is_some());
+
+    assert_eq!(text(&messages[1]), r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.pepega","diagnostics":[]}}"#);
+    assert_eq!(text(&messages[2]), concat!(
+        r#"{"jsonrpc":"2.0","id":2,"result":{"contents":{"kind":"markdown","value":"```pepega\nkekw naïve\n```"},"#,
+        r#""range":{"start":{"line":1,"character":9},"end":{"line":1,"character":14}}}}"#
+    ));
+    assert_eq!(text(&messages[3]), concat!(
+        r#"{"jsonrpc":"2.0","id":3,"result":{"uri":"file:///a.pepega","#,
+        r#""range":{"start":{"line":0,"character":22},"end":{"line":0,"character":27}}}}"#
+    ));
+    // Five numbers per token: line delta, start delta, length, type, modifiers.
+    // The string is 6 UTF-16 code units long and `kekw` starts at 17.
+    assert_eq!(text(&messages[4]), concat!(
+        r#"{"jsonrpc":"2.0","id":4,"result":{"data":["#,
+        "0,0,8,0,0,", "0,9,6,8,0,", "0,8,4,0,0,", "0,5,5,1,0,", "0,6,1,11,0,", "0,2,1,9,0,",
+        "1,0,8,0,0,", "0,9,5,1,0",
+        "]}}"
+    ));
+    assert_eq!(text(&messages[5]), concat!(
+        r#"{"jsonrpc":"2.0","id":5,"result":{"contents":{"kind":"markdown","value":"`kekw` is the `var` keyword."},"#,
+        r#""range":{"start":{"line":0,"character":17},"end":{"line":0,"character":21}}}}"#
+    ));
+    assert_eq!(text(&messages[6]), concat!(
+        r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///b.pepega","diagnostics":["#,
+        r#"{"range":{"start":{"line":0,"character":16},"end":{"line":0,"character":17}},"severity":1,"source":"pepega","#,
+        r#""message":"Error at ';': Expect expression."}]}}"#
+    ));
+    assert_eq!(text(&messages[7]), r#"{"jsonrpc":"2.0","id":6,"result":null}"#);
+}
+
+#[test]
+fn exit_without_shutdown() {
+    let stdin = frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    let run = pepega_in(&PathBuf::from(env!("CARGO_MANIFEST_DIR")), &["lsp"], &stdin);
+    assert_eq!((run.status, run.stdout.as_str()), (1, ""));
+}
+
+// A client that stops reading must not make the server panic.
+#[test]
+fn client_goes_away() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pepega"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let mut input = child.stdin.take().unwrap();
+    let _ = input.write_all(frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#).as_bytes());
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,109 @@
+// Checks what `--opt-level` does to the tree with `--ast` snapshots, and that
+// the optimized program still prints the same.
+
+mod common;
+
+use common::{pepega, script};
+
+const SOURCE: &str = "\
+pog f() { chatting \"called\"; xdd 1; }
+chatting 1 + 2 * 3;
+kekw s = \"a\" + \"b\";
+clueless (kappa) chatting \"never\"; aware chatting \"always\";
+clueless (yep and 1 < 2) chatting \"kept\";
+residentsleeper (kappa) chatting \"loop\";
+chatting f() + 2 * 3;
+chatting f() and kappa;
+chatting kappa and f();
+kekw x = 1;
+chatting (x = 2) * 0;
+chatting -(4 / 2);
+";
+
+fn run(args: &[&str]) -> String {
+    let path = script("optimizer", "fold.pepega", SOURCE);
+    let mut args = args.to_vec();
+    args.push(path.to_str().unwrap());
+    let run = pepega(&args);
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 0);
+    run.stdout
+}
+
+#[test]
+fn unoptimized() {
+    assert_eq!(run(&["--ast", "--opt-level", "0"]), "\
+(fun f ()
+  (print \"called\")
+  (return 1))
+(print (+ 1 (* 2 3)))
+(var s (+ \"a\" \"b\"))
+(if false
+  (print \"never\")
+  (print \"always\"))
+(if (and true (< 1 2))
+  (print \"kept\"))
+(while false
+  (print \"loop\"))
+(print (+ (call f) (* 2 3)))
+(print (and (call f) false))
+(print (and false (call f)))
+(var x 1)
+(print (* (group (= x 2)) 0))
+(print (- (group (/ 4 2))))
+");
+}
+
+// Level 1 folds constants but keeps every branch. Operands with side effects,
+// like a call or an assignment, are never folded away.
+#[test]
+fn folded() {
+    assert_eq!(run(&["--ast", "--opt-level", "1"]), "\
+(fun f ()
+  (print \"called\")
+  (return 1))
+(print 7)
+(var s \"ab\")
+(if false
+  (print \"never\")
+  (print \"always\"))
+(if true
+  (print \"kept\"))
+(while false
+  (print \"loop\"))
+(print (+ (call f) 6))
+(print (and (call f) false))
+(print false)
+(var x 1)
+(print (* (= x 2) 0))
+(print -2)
+");
+}
+
+// Level 2 also drops branches and loops that can never run.
+#[test]
+fn dead_code_removed() {
+    assert_eq!(run(&["--ast", "--opt-level", "2"]), "\
+(fun f ()
+  (print \"called\")
+  (return 1))
+(print 7)
+(var s \"ab\")
+(print \"always\")
+(print \"kept\")
+(print (+ (call f) 6))
+(print (and (call f) false))
+(print false)
+(var x 1)
+(print (* (= x 2) 0))
+(print -2)
+");
+}
+
+#[test]
+fn same_output_at_every_level() {
+    let expected = "7\nalways\nkept\ncalled\n7\ncalled\nfalse\nfalse\n0\n-2\n";
+    for level in ["0", "1", "2"] {
+        assert_eq!(run(&["--opt-level", level]), expected, "--opt-level {}", level);
+    }
+}
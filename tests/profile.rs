@@ -0,0 +1,72 @@
+// Runs scripts under `--profile` and checks the counts it reports. Timings
+// vary from run to run, so only their presence is checked.
+
+mod common;
+
+use std::fs;
+use common::{pepega_in, script};
+
+const FIB: &str = "\
+pog fib(n) {
+    clueless (n < 2) xdd n;
+    xdd fib(n - 1) + fib(n - 2);
+}
+chatting fib(6);
+";
+
+#[test]
+fn recursive_calls() {
+    let path = script("profile", "fib.pepega", FIB);
+    let directory = path.parent().unwrap().to_path_buf();
+    let run = pepega_in(&directory, &["--profile", "--profile-out", "fib.folded", "fib.pepega"], "");
+    assert_eq!(run.status, 0);
+    assert_eq!(run.stdout, "8\n");
+
+    let mut sections = run.stderr.split("\n\n");
+    let functions: Vec<Vec<&str>> = sections.next().unwrap().lines()
+        .map(|l| l.split_whitespace().take(2).collect())
+        .collect();
+    assert_eq!(functions[0], ["function", "calls"]);
+    let mut calls = functions[1..].to_vec();
+    calls.sort();
+    assert_eq!(calls, [["fib", "25"], ["script", "1"]]);
+
+    // The `xdd` on line 2 is part of the `clueless` step, so the line is hit
+    // once per call rather than once per statement.
+    let lines: Vec<(&str, &str)> = sections.next().unwrap().lines()
+        .take_while(|l| !l.starts_with("Folded stacks written"))
+        .map(|l| {
+            let mut fields = l.split_whitespace();
+            (fields.next().unwrap(), fields.next().unwrap())
+        })
+        .collect();
+    assert_eq!(lines, [("line", "hits"), ("2", "25"), ("3", "12"), ("1", "1"), ("5", "1")]);
+
+    let folded = fs::read_to_string(directory.join("fib.folded")).unwrap();
+    let paths: Vec<&str> = folded.lines()
+        .map(|l| {
+            let (path, micros) = l.rsplit_once(' ').unwrap();
+            micros.parse::<u64>().unwrap();
+            path
+        })
+        .collect();
+    assert_eq!(paths, [
+        "script",
+        "script;fib",
+        "script;fib;fib",
+        "script;fib;fib;fib",
+        "script;fib;fib;fib;fib",
+        "script;fib;fib;fib;fib;fib",
+        "script;fib;fib;fib;fib;fib;fib"
+    ]);
+}
+
+// A loop body on the loop's own line is counted once per iteration.
+#[test]
+fn loop_on_one_line() {
+    let path = script("profile", "loop.pepega", "kekw i = 0;\nresidentsleeper (i < 3) i = i + 1;\n");
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["--profile", "--profile-out", "loop.folded", "loop.pepega"], "");
+    assert_eq!(run.status, 0);
+    let lines: Vec<&str> = run.stderr.lines().filter(|l| l.ends_with(';')).map(|l| l.trim()).collect();
+    assert_eq!(lines, ["2          3  residentsleeper (i < 3) i = i + 1;", "1          1  kekw i = 0;"]);
+}
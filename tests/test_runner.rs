@@ -0,0 +1,170 @@
+// Runs `pepega test` on test files and checks its report and exit status.
+
+mod common;
+
+use common::{pepega_in, script};
+
+const PASSING: &str = "\
+pog double(n) {
+    xdd n * 2;
+}
+
+pog divide_by_string() {
+    xdd 1 / \"a\";
+}
+
+pog test_double() {
+    assert_eq(double(2), 4);
+    assert(double(0) == 0);
+}
+
+pog test_divide_by_string() {
+    assert_error(divide_by_string, \"Operands must be\");
+}
+
+pog helper_is_not_a_test() {
+    assert(kappa);
+}
+";
+
+const FAILING: &str = "\
+pog test_lines() {
+    chatting \"computing\";
+    assert_eq(\"one
+two
+three\", \"one
+2
+three\");
+}
+
+pog fine() {
+    xdd 1;
+}
+
+pog add_nil() {
+    xdd nil + 1;
+}
+
+pog test_no_error() {
+    assert_error(fine, \"boom\");
+}
+
+pog test_wrong_error() {
+    assert_error(add_nil, \"boom\");
+}
+
+pog test_ok() {
+    assert(yep);
+}
+";
+
+#[test]
+fn passing_file() {
+    let path = script("test_runner_pass", "math_test.pepega", PASSING);
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["test"], "");
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.stdout, "\
+./math_test.pepega::test_double ... ok
+./math_test.pepega::test_divide_by_string ... ok
+
+2 passed, 0 failed
+");
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn failing_expectations() {
+    let path = script("test_runner_fail", "lines_test.pepega", FAILING);
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["test", "lines_test.pepega"], "");
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.stdout, "\
+lines_test.pepega::test_lines ... FAILED
+lines_test.pepega::test_no_error ... FAILED
+lines_test.pepega::test_wrong_error ... FAILED
+lines_test.pepega::test_ok ... ok
+
+---- lines_test.pepega::test_lines ----
+Values are not equal.
+  (- expected, + actual)
+    one
+  - 2
+  + two
+    three
+[line 3]
+output:
+computing
+
+---- lines_test.pepega::test_no_error ----
+Expected an error containing 'boom' but none was raised.
+[line 19]
+
+---- lines_test.pepega::test_wrong_error ----
+Expected an error containing 'boom'.
+  (- expected, + actual)
+  - boom
+  + Operands must be two numbers or two strings.
+[line 23]
+
+1 passed, 3 failed
+");
+    assert_eq!(run.status, 1);
+}
+
+// A file that does not parse fails as a whole.
+#[test]
+fn file_that_does_not_parse() {
+    let path = script("test_runner_broken", "broken_test.pepega", "pog test_broken() {\n    assert(;\n}\n");
+    let run = pepega_in(&path.parent().unwrap().to_path_buf(), &["test", "broken_test.pepega"], "");
+    assert_eq!(run.stdout, "\
+broken_test.pepega ... FAILED
+
+---- broken_test.pepega ----
+[line 2] Error at ';': Expect expression.
+
+0 passed, 1 failed
+");
+    assert_eq!(run.status, 1);
+}
+
+// The command line's sandbox and budget apply to every test, each test getting
+// a budget of its own.
+#[test]
+fn sandbox_and_budget() {
+    let source = "\
+pog test_reads_env() {
+    env(\"HOME\");
+}
+
+pog test_loops() {
+    kekw i = 0;
+    residentsleeper (i < 100) i = i + 1;
+}
+
+pog test_short() {
+    assert(yep);
+}
+";
+    let path = script("test_runner_limits", "limits_test.pepega", source);
+    let directory = path.parent().unwrap().to_path_buf();
+    let run = pepega_in(&directory, &["--sandbox", "--max-steps", "200", "test", "limits_test.pepega"], "");
+    assert_eq!(run.stdout, "\
+limits_test.pepega::test_reads_env ... FAILED
+limits_test.pepega::test_loops ... FAILED
+limits_test.pepega::test_short ... ok
+
+---- limits_test.pepega::test_reads_env ----
+Undefined variable 'env'.
+[line 2]
+
+---- limits_test.pepega::test_loops ----
+Budget exceeded: more than 200 steps.
+[line 7]
+
+1 passed, 2 failed
+");
+    assert_eq!(run.status, 1);
+
+    let run = pepega_in(&directory, &["test", "limits_test.pepega"], "");
+    assert!(run.stdout.contains("test_reads_env ... ok"), "{}", run.stdout);
+    assert!(run.stdout.contains("test_loops ... ok"), "{}", run.stdout);
+}
@@ -0,0 +1,51 @@
+// Runs `pepega translate` between the built-in dialects.
+
+mod common;
+
+use common::{pepega, script};
+
+// Uses every keyword, with comments and uneven layout that must survive.
+const EVERY_KEYWORD: &str = "\
+// Keywords inside comments stay as they are: kekw pog xdd
+kappa123 LIMIT = 3;
+pepegas Shape { Circle(r), Square }
+gigachad Counter {
+    init() { this.count = 0; }
+    bump()   { xdd this.count + 1; }
+}
+pog pick(shape) {
+    modcheck (shape) {
+        Shape.Circle(r) => xdd r;
+        Shape.Square => xdd nil;
+    }
+}
+kekw i = 0;
+residentsleeper (i < LIMIT and yep) { i = i + 1; }
+forsen (kekw j = 0; j < 2 or kappa; j = j + 1) chatting \"kekw in a string\";
+clueless (i == 3) chatting pick(Shape.Circle(2)); aware chatting super;
+";
+
+#[test]
+fn round_trip() {
+    let path = script("translate", "every_keyword.pepega", EVERY_KEYWORD);
+    let lox = pepega(&["translate", "--from", "pepega", "--to", "lox", path.to_str().unwrap()]);
+    assert_eq!(lox.stderr, "");
+    assert_eq!(lox.status, 0);
+    assert!(lox.stdout.contains("class Counter {"), "{}", lox.stdout);
+    assert!(lox.stdout.contains("// Keywords inside comments stay as they are: kekw pog xdd"), "{}", lox.stdout);
+    assert!(lox.stdout.contains("print \"kekw in a string\";"), "{}", lox.stdout);
+    let path = script("translate", "every_keyword.lox", &lox.stdout);
+    let back = pepega(&["translate", "--from", "lox", "--to", "pepega", path.to_str().unwrap()]);
+    assert_eq!(back.stderr, "");
+    assert_eq!(back.status, 0);
+    assert_eq!(back.stdout, EVERY_KEYWORD);
+}
+
+#[test]
+fn lex_error() {
+    let path = script("translate", "unterminated.pepega", "kekw x = \"unterminated;\n");
+    let run = pepega(&["translate", path.to_str().unwrap()]);
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.stderr, "[line 2] Error: Unterminated string.\n");
+    assert_eq!(run.status, 65);
+}